@@ -0,0 +1,440 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use straaljager::sampler::SamplerKind;
+use straaljager::tiles::TileOrder;
+
+pub const USAGE: &str = "Usage: straaljager [OPTIONS] [SCENE]
+
+Renders SCENE, a TOML scene description (default: ./scenes/spheres.toml).
+
+Options:
+  -o, --output <PATH>          Output image path (default: the first free ./output/output_NNNNNN.<FORMAT>)
+  -f, --format <FORMAT>        Output format: ppm, png, exr, hdr or pfm (default: derived from the output
+                               extension, else ppm). exr, hdr and pfm store the linear, unclamped radiance
+      --half                   Store exr channels as 16-bit instead of 32-bit floats
+  -r, --resolution <WxH>       Image resolution, overrides the scene, e.g. 800x600
+  -c, --crop <X,Y,W,H>         Only render the W by H pixels at X,Y from the top left, the image is that size
+  -s, --samples <N>            Samples per pixel, overrides the scene
+  -d, --max-depth <N>          Maximum number of bounces, overrides the scene
+  -t, --threads <N>            Number of render threads (default: one per core)
+      --tile-size <N>          Edge length of the tiles the threads render, overrides the scene
+      --tile-order <ORDER>     Order tiles are rendered in: scanline, spiral or hilbert, overrides the scene
+      --no-light-sampling      Only follow random bounces to find lights, for reference renders
+      --adaptive <ERROR>       Stop sampling pixels once their relative error is below ERROR, e.g. 0.01.
+                               --samples is then the maximum per pixel
+      --heatmap <PATH>         Also write an image of the number of samples per pixel, in the format of
+                               the extension
+      --checkpoint <PATH>      Render in passes, saving the image and a checkpoint to PATH along the way.
+                               An existing checkpoint is resumed, raise --samples to keep refining
+      --pass-samples <N>       Samples per pixel in every pass of a checkpointed render (default: 4)
+      --checkpoint-interval <SECONDS>
+                               Minimum time between saves of a checkpointed render (default: 60)
+      --sampler <SAMPLER>      Sample distribution: independent, stratified, halton or sobol, overrides the scene
+      --seed <N>               Seed for all random sampling, the same seed and settings give the same image
+                               (default: 0)
+  -q, --quiet                  Don't print progress
+      --benchmark-bvh          Compare ray traversal speed of the BVH variants instead of rendering
+  -h, --help                   Print this help";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Ppm,
+    Png,
+    Exr,
+    Hdr,
+    Pfm,
+}
+
+impl OutputFormat {
+    pub fn from_extension(path: &Path) -> Option<OutputFormat> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        extension.parse().ok()
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Ppm => "ppm",
+            OutputFormat::Png => "png",
+            OutputFormat::Exr => "exr",
+            OutputFormat::Hdr => "hdr",
+            OutputFormat::Pfm => "pfm",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = UsageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ppm" => Ok(OutputFormat::Ppm),
+            "png" => Ok(OutputFormat::Png),
+            "exr" => Ok(OutputFormat::Exr),
+            "hdr" => Ok(OutputFormat::Hdr),
+            "pfm" => Ok(OutputFormat::Pfm),
+            _ => Err(UsageError(format!("unsupported output format '{}'", s))),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UsageError(pub String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "error: {}\n\n{}", self.0, USAGE)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub scene_path: PathBuf,
+    pub output: Option<PathBuf>,
+    pub format: OutputFormat,
+    pub half_float: bool,
+    pub resolution: Option<(usize, usize)>,
+    pub crop: Option<[usize; 4]>,
+    pub samples: Option<u32>,
+    pub max_depth: Option<u32>,
+    pub no_light_sampling: bool,
+    pub sampler: Option<SamplerKind>,
+    pub adaptive_threshold: Option<f64>,
+    pub heatmap: Option<PathBuf>,
+    pub checkpoint: Option<PathBuf>,
+    pub pass_samples: u32,
+    pub checkpoint_interval: u64,
+    pub threads: Option<usize>,
+    pub tile_size: Option<usize>,
+    pub tile_order: Option<TileOrder>,
+    pub seed: Option<u64>,
+    pub quiet: bool,
+    pub benchmark_bvh: bool,
+}
+
+pub enum Command {
+    Render(Box<Options>),
+    Help,
+}
+
+fn parse_positive<N>(option: &str, value: &str) -> Result<N, UsageError>
+    where
+        N: FromStr + PartialOrd + Default,
+{
+    match value.parse::<N>() {
+        Ok(n) if n > N::default() => Ok(n),
+        _ => Err(UsageError(format!("{} expects a positive number, got '{}'", option, value))),
+    }
+}
+
+fn parse_crop(value: &str) -> Result<[usize; 4], UsageError> {
+    let numbers: Option<Vec<usize>> = value.split(',').map(|p| p.trim().parse().ok()).collect();
+    match numbers {
+        Some(n) if n.len() == 4 && n[2] > 0 && n[3] > 0 => Ok([n[0], n[1], n[2], n[3]]),
+        _ => Err(UsageError(format!("--crop expects X,Y,WIDTH,HEIGHT with a non-zero size, got '{}'", value))),
+    }
+}
+
+fn parse_resolution(value: &str) -> Result<(usize, usize), UsageError> {
+    let mut parts = value.splitn(2, ['x', 'X']);
+    match (parts.next(), parts.next()) {
+        (Some(w), Some(h)) => Ok((parse_positive("--resolution", w)?, parse_positive("--resolution", h)?)),
+        _ => Err(UsageError(format!("--resolution expects WIDTHxHEIGHT, got '{}'", value))),
+    }
+}
+
+/// Parses the arguments, without the program name
+pub fn parse_args<I>(args: I) -> Result<Command, UsageError>
+    where
+        I: IntoIterator<Item=String>,
+{
+    let mut scene_path = None;
+    let mut output: Option<PathBuf> = None;
+    let mut format = None;
+    let mut half_float = false;
+    let mut resolution = None;
+    let mut crop = None;
+    let mut samples = None;
+    let mut max_depth = None;
+    let mut no_light_sampling = false;
+    let mut sampler = None;
+    let mut adaptive_threshold = None;
+    let mut heatmap: Option<PathBuf> = None;
+    let mut checkpoint = None;
+    let mut pass_samples = None;
+    let mut checkpoint_interval = None;
+    let mut threads = None;
+    let mut tile_size = None;
+    let mut tile_order = None;
+    let mut seed = None;
+    let mut quiet = false;
+    let mut benchmark_bvh = false;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        //Accept both "--option value" and "--option=value"
+        let (option, inline_value) = match arg.find('=') {
+            Some(i) if arg.starts_with("--") => (arg[..i].to_string(), Some(arg[i + 1..].to_string())),
+            _ => (arg.clone(), None),
+        };
+        let takes_value = match option.as_str() {
+            "-h" | "--help" | "-q" | "--quiet" | "--benchmark-bvh" | "--no-light-sampling" | "--half" => false,
+            _ => option.starts_with('-'),
+        };
+        let value = if takes_value {
+            match inline_value.or_else(|| args.next()) {
+                Some(v) => v,
+                None => return Err(UsageError(format!("{} expects a value", option))),
+            }
+        } else {
+            if inline_value.is_some() {
+                return Err(UsageError(format!("{} doesn't take a value", option)));
+            }
+            String::new()
+        };
+
+        match option.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-q" | "--quiet" => quiet = true,
+            "--benchmark-bvh" => benchmark_bvh = true,
+            "--no-light-sampling" => no_light_sampling = true,
+            "--half" => half_float = true,
+            "-o" | "--output" => output = Some(PathBuf::from(value)),
+            "-f" | "--format" => format = Some(value.parse::<OutputFormat>()?),
+            "-r" | "--resolution" => resolution = Some(parse_resolution(&value)?),
+            "-c" | "--crop" => crop = Some(parse_crop(&value)?),
+            "-s" | "--samples" => samples = Some(parse_positive(&option, &value)?),
+            "-d" | "--max-depth" => max_depth = Some(parse_positive(&option, &value)?),
+            "--sampler" => sampler = Some(value.parse::<SamplerKind>().map_err(UsageError)?),
+            "--adaptive" => adaptive_threshold = Some(parse_positive(&option, &value)?),
+            "--heatmap" => heatmap = Some(PathBuf::from(value)),
+            "--checkpoint" => checkpoint = Some(PathBuf::from(value)),
+            "--pass-samples" => pass_samples = Some(parse_positive(&option, &value)?),
+            "--checkpoint-interval" => checkpoint_interval = Some(parse_positive(&option, &value)?),
+            "-t" | "--threads" => threads = Some(parse_positive(&option, &value)?),
+            "--tile-size" => tile_size = Some(parse_positive(&option, &value)?),
+            "--tile-order" => tile_order = Some(value.parse::<TileOrder>().map_err(UsageError)?),
+            "--seed" => {
+                seed = Some(value.parse::<u64>().map_err(|_| {
+                    UsageError(format!("--seed expects a non-negative integer, got '{}'", value))
+                })?)
+            }
+            _ if option.starts_with('-') => return Err(UsageError(format!("unknown option '{}'", option))),
+            _ => {
+                if scene_path.is_some() {
+                    return Err(UsageError(format!("only one scene can be rendered, got an extra '{}'", option)));
+                }
+                scene_path = Some(PathBuf::from(option));
+            }
+        }
+    }
+
+    if benchmark_bvh && (output.is_some() || heatmap.is_some()) {
+        return Err(UsageError(
+            "--benchmark-bvh doesn't write an image, so it can't be combined with --output or --heatmap".to_string(),
+        ));
+    }
+    if checkpoint.is_none() && (pass_samples.is_some() || checkpoint_interval.is_some()) {
+        return Err(UsageError("--pass-samples and --checkpoint-interval only apply with --checkpoint".to_string()));
+    }
+    if benchmark_bvh && checkpoint.is_some() {
+        return Err(UsageError("--benchmark-bvh can't be combined with --checkpoint".to_string()));
+    }
+    if let Some(path) = &heatmap {
+        if OutputFormat::from_extension(path).is_none() {
+            return Err(UsageError(format!("can't derive an image format for --heatmap from '{}'", path.display())));
+        }
+    }
+
+    //An explicit format has to agree with the output extension, otherwise the extension decides
+    let extension_format = match &output {
+        Some(path) if path.extension().is_some() => match OutputFormat::from_extension(path) {
+            Some(f) => Some(f),
+            None => {
+                return Err(UsageError(format!("can't derive an output format from '{}'", path.display())));
+            }
+        },
+        _ => None,
+    };
+    let format = match (format, extension_format) {
+        (Some(f), Some(e)) if f != e => {
+            return Err(UsageError(format!(
+                "--format {} conflicts with the .{} output extension",
+                f.extension(),
+                e.extension()
+            )));
+        }
+        (Some(f), _) => f,
+        (None, Some(e)) => e,
+        (None, None) => OutputFormat::Ppm,
+    };
+    if half_float && format != OutputFormat::Exr {
+        return Err(UsageError("--half only applies to exr output".to_string()));
+    }
+
+    Ok(Command::Render(Box::new(Options {
+        scene_path: scene_path.unwrap_or_else(|| PathBuf::from("./scenes/spheres.toml")),
+        output,
+        format,
+        half_float,
+        resolution,
+        crop,
+        samples,
+        max_depth,
+        no_light_sampling,
+        sampler,
+        adaptive_threshold,
+        heatmap,
+        checkpoint,
+        pass_samples: pass_samples.unwrap_or(4),
+        checkpoint_interval: checkpoint_interval.unwrap_or(60),
+        threads,
+        tile_size,
+        tile_order,
+        seed,
+        quiet,
+        benchmark_bvh,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Options, String> {
+        match parse_args(args.split_whitespace().map(String::from)) {
+            Ok(Command::Render(options)) => Ok(*options),
+            Ok(Command::Help) => Err("help".to_string()),
+            Err(UsageError(message)) => Err(message),
+        }
+    }
+
+    #[test]
+    fn defaults() {
+        let options = parse("").unwrap();
+        assert_eq!(options.scene_path, PathBuf::from("./scenes/spheres.toml"));
+        assert_eq!(options.output, None);
+        assert_eq!(options.format, OutputFormat::Ppm);
+        assert_eq!(options.resolution, None);
+        assert!(!options.quiet);
+        assert!(matches!(parse_args(vec!["-h".to_string()]), Ok(Command::Help)));
+    }
+
+    #[test]
+    fn resolutions() {
+        let cases = [
+            ("-r 800x600", Ok((800, 600))),
+            ("--resolution 1X1", Ok((1, 1))),
+            ("--resolution=320x240", Ok((320, 240))),
+            ("-r 800", Err("WIDTHxHEIGHT")),
+            ("-r 0x600", Err("positive")),
+            ("-r 800x-1", Err("positive")),
+            ("-r 800x600x2", Err("positive")),
+            ("-r axb", Err("positive")),
+        ];
+        for (args, expected) in cases.iter() {
+            match (parse(args), expected) {
+                (Ok(options), Ok(resolution)) => assert_eq!(options.resolution, Some(*resolution), "{}", args),
+                (Err(message), Err(part)) => assert!(message.contains(part), "{}: {}", args, message),
+                (result, _) => panic!("{}: unexpected {:?}", args, result.map(|o| o.resolution)),
+            }
+        }
+    }
+
+    #[test]
+    fn non_positive_numbers_are_rejected() {
+        for args in &["-s 0", "-s -1", "-d 0", "-t 0", "-t x", "--samples=", "--seed -1", "--seed 1.5"] {
+            assert!(parse(args).is_err(), "{} should be rejected", args);
+        }
+        let options = parse("-s 4 -d 3 -t 2 --seed 0").unwrap();
+        assert_eq!((options.samples, options.max_depth), (Some(4), Some(3)));
+        assert_eq!((options.threads, options.seed), (Some(2), Some(0)));
+    }
+
+    #[test]
+    fn samplers() {
+        assert_eq!(parse("").unwrap().sampler, None);
+        assert_eq!(parse("--sampler halton").unwrap().sampler, Some(SamplerKind::Halton));
+        assert_eq!(parse("--sampler=Sobol").unwrap().sampler, Some(SamplerKind::Sobol));
+        let message = parse("--sampler random").err().unwrap_or_default();
+        assert!(message.contains("unknown sampler 'random'"), "{}", message);
+    }
+
+    #[test]
+    fn formats_and_extensions() {
+        let cases = [
+            ("-o out.ppm", Ok(OutputFormat::Ppm)),
+            ("-o out.PPM -f ppm", Ok(OutputFormat::Ppm)),
+            ("-o out -f ppm", Ok(OutputFormat::Ppm)),
+            ("-o out.png", Ok(OutputFormat::Png)),
+            ("-f PNG", Ok(OutputFormat::Png)),
+            ("-o out.png -f ppm", Err("--format ppm conflicts with the .png output extension")),
+            ("-f png -o out.ppm", Err("--format png conflicts with the .ppm output extension")),
+            ("-o out.exr", Ok(OutputFormat::Exr)),
+            ("-o out.hdr", Ok(OutputFormat::Hdr)),
+            ("-o out -f pfm", Ok(OutputFormat::Pfm)),
+            ("-o out.exr --half", Ok(OutputFormat::Exr)),
+            ("-o out.hdr --half", Err("--half only applies to exr output")),
+            ("-f jpg", Err("unsupported output format")),
+            ("-o out.jpg", Err("can't derive an output format")),
+        ];
+        for (args, expected) in cases.iter() {
+            match (parse(args), expected) {
+                (Ok(options), Ok(format)) => assert_eq!(options.format, *format, "{}", args),
+                (Err(message), Err(part)) => assert!(message.contains(part), "{}: {}", args, message),
+                (result, _) => panic!("{}: unexpected {:?}", args, result.map(|o| o.format)),
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_arguments() {
+        let cases = [
+            ("-o", "expects a value"),
+            ("--quiet=yes", "doesn't take a value"),
+            ("--frobnicate 1", "unknown option"),
+            ("a.toml b.toml", "only one scene"),
+        ];
+        for (args, part) in cases.iter() {
+            let message = parse(args).err().unwrap_or_default();
+            assert!(message.contains(part), "{}: {}", args, message);
+        }
+        assert_eq!(parse("scene.toml -q").unwrap().scene_path, PathBuf::from("scene.toml"));
+    }
+
+    #[test]
+    fn adaptive_sampling_and_heatmaps() {
+        let options = parse("--adaptive 0.02 --heatmap samples.png").unwrap();
+        assert_eq!(options.adaptive_threshold, Some(0.02));
+        assert_eq!(options.heatmap, Some(PathBuf::from("samples.png")));
+        assert!(parse("--adaptive 0").is_err());
+        assert!(parse("--adaptive x").is_err());
+        let message = parse("--heatmap samples").err().unwrap_or_default();
+        assert!(message.contains("can't derive an image format for --heatmap"), "{}", message);
+    }
+
+    #[test]
+    fn crops_and_tiles() {
+        let options = parse("-c 10,20,30,40 --tile-size 16 --tile-order hilbert").unwrap();
+        assert_eq!(options.crop, Some([10, 20, 30, 40]));
+        assert_eq!((options.tile_size, options.tile_order), (Some(16), Some(TileOrder::Hilbert)));
+        assert_eq!(parse("--crop=0,0,1,1").unwrap().crop, Some([0, 0, 1, 1]));
+        for args in &["-c 0,0,0,1", "-c 0,0,1", "-c 0,0,1,1,1", "-c -1,0,1,1", "-c a,b,c,d"] {
+            let message = parse(args).err().unwrap_or_default();
+            assert!(message.contains("--crop expects X,Y,WIDTH,HEIGHT"), "{}: {}", args, message);
+        }
+        assert!(parse("--tile-size 0").is_err());
+        assert!(parse("--tile-order zigzag").err().unwrap_or_default().contains("unknown tile order"));
+    }
+
+    #[test]
+    fn bvh_benchmark_doesnt_write_images() {
+        assert!(parse("--benchmark-bvh").unwrap().benchmark_bvh);
+        assert!(!parse("").unwrap().benchmark_bvh);
+        assert!(parse("--benchmark-bvh=1").is_err());
+        for args in &["--benchmark-bvh -o out.ppm", "-o out.ppm --benchmark-bvh", "--benchmark-bvh --heatmap h.png"] {
+            assert!(parse(args).err().unwrap_or_default().contains("--output or --heatmap"), "{}", args);
+        }
+    }
+}
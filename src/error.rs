@@ -0,0 +1,82 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::io::{ImageError, ObjError, SceneError};
+
+/// Everything that can go wrong while setting up, rendering or saving a scene
+#[derive(Debug)]
+pub enum Error {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Scene(SceneError),
+    Obj(ObjError),
+    Image(ImageError),
+    /// A checkpoint that can't be read, or doesn't belong to the render it should resume
+    Checkpoint {
+        path: PathBuf,
+        message: String,
+    },
+    /// Acceleration structures need at least one primitive to be built over
+    EmptyBvh,
+    /// A hit record refers to a material that has already been dropped
+    MissingMaterial,
+}
+
+pub type Result<V> = std::result::Result<V, Error>;
+
+impl Error {
+    pub fn io(path: &Path, error: io::Error) -> Error {
+        Error::Io {
+            path: path.to_path_buf(),
+            error,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path, error } => write!(f, "Could not access {}: {}", path.display(), error),
+            Error::Scene(error) => write!(f, "{}", error),
+            Error::Obj(error) => write!(f, "{}", error),
+            Error::Image(error) => write!(f, "{}", error),
+            Error::Checkpoint { path, message } => write!(f, "Can't resume from {}: {}", path.display(), message),
+            Error::EmptyBvh => write!(f, "Can't build a BVH without any primitives, the scene is empty"),
+            Error::MissingMaterial => write!(f, "A surface was hit whose material no longer exists"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io { error, .. } => Some(error),
+            Error::Scene(error) => Some(error),
+            Error::Obj(error) => Some(error),
+            Error::Image(error) => Some(error),
+            Error::Checkpoint { .. } | Error::EmptyBvh | Error::MissingMaterial => None,
+        }
+    }
+}
+
+impl From<SceneError> for Error {
+    fn from(error: SceneError) -> Error {
+        Error::Scene(error)
+    }
+}
+
+impl From<ObjError> for Error {
+    fn from(error: ObjError) -> Error {
+        Error::Obj(error)
+    }
+}
+
+impl From<ImageError> for Error {
+    fn from(error: ImageError) -> Error {
+        Error::Image(error)
+    }
+}
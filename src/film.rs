@@ -0,0 +1,252 @@
+use straal::{FloatType, Vec3};
+
+use crate::io::ImageBuffer;
+use crate::math::{luminance, srgb_to_linear};
+use crate::renderer::RenderOutput;
+use crate::tiles::Rect;
+
+/// Running mean and variance of the samples of one pixel. The variance is only tracked for the
+/// luminance, using Welford's algorithm, which is enough to tell whether the pixel converged.
+#[derive(Clone, Copy)]
+pub struct PixelStats<T> {
+    pub sum: Vec3<T>,
+    pub count: u32,
+    luminance_mean: f64,
+    luminance_m2: f64,
+}
+
+/// Means below this are treated as this dark when computing the relative error, so nearly
+/// black pixels don't keep sampling to resolve differences nobody can see
+const MIN_RELATIVE_MEAN: f64 = 0.01;
+
+impl<T> PixelStats<T>
+    where
+        T: FloatType<T>,
+{
+    pub fn new() -> PixelStats<T> {
+        PixelStats {
+            sum: Vec3::<T>::zero(),
+            count: 0,
+            luminance_mean: 0.0,
+            luminance_m2: 0.0,
+        }
+    }
+
+    /// Restores stats saved through `luminance_moments`
+    pub fn from_parts(sum: Vec3<T>, count: u32, luminance_mean: f64, luminance_m2: f64) -> PixelStats<T> {
+        PixelStats {
+            sum,
+            count,
+            luminance_mean,
+            luminance_m2,
+        }
+    }
+
+    /// Mean luminance and the sum of squared differences from it
+    pub fn luminance_moments(&self) -> (f64, f64) {
+        (self.luminance_mean, self.luminance_m2)
+    }
+
+    pub fn add(&mut self, radiance: &Vec3<T>) {
+        self.sum += *radiance;
+        self.count += 1;
+        let value: f64 = num::cast(luminance(radiance)).unwrap_or(0.0);
+        let delta = value - self.luminance_mean;
+        self.luminance_mean += delta / self.count as f64;
+        self.luminance_m2 += delta * (value - self.luminance_mean);
+    }
+
+    pub fn mean(&self) -> Vec3<T> {
+        if self.count == 0 {
+            return Vec3::<T>::zero();
+        }
+        self.sum / T::from(self.count).unwrap()
+    }
+
+    /// Standard error of the mean luminance relative to the mean, infinite with fewer than two
+    /// samples
+    pub fn relative_error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let variance = self.luminance_m2 / (self.count - 1) as f64;
+        (variance / self.count as f64).sqrt() / self.luminance_mean.abs().max(MIN_RELATIVE_MEAN)
+    }
+}
+
+impl<T> Default for PixelStats<T>
+    where
+        T: FloatType<T>,
+{
+    fn default() -> Self {
+        PixelStats::new()
+    }
+}
+
+/// Accumulated samples of every pixel of an image, or of the cropped part of it, top row first.
+/// Rendering more samples into the same film keeps refining the image.
+pub struct Film<T> {
+    /// The pixels covered, in the coordinates of the full image
+    pub area: Rect,
+    pub pixels: Vec<PixelStats<T>>,
+}
+
+/// The samples of a part of the film, one slice per row, so tiles can be rendered in parallel
+pub struct FilmTile<'a, T> {
+    pub area: Rect,
+    pub rows: Vec<&'a mut [PixelStats<T>]>,
+}
+
+impl<T> Film<T>
+    where
+        T: FloatType<T>,
+{
+    pub fn new(area: Rect) -> Film<T> {
+        Film {
+            area,
+            pixels: vec![PixelStats::new(); area.area()],
+        }
+    }
+
+    /// Splits the film into the given tiles, in the same order. The tiles have to lie within the
+    /// film and must not overlap.
+    pub fn split_tiles(&mut self, areas: &[Rect]) -> Vec<FilmTile<'_, T>> {
+        let film_area = self.area;
+        let mut tiles: Vec<FilmTile<T>> = areas
+            .iter()
+            .map(|area| FilmTile {
+                area: *area,
+                rows: Vec::with_capacity(area.height),
+            })
+            .collect();
+        let mut left_to_right: Vec<usize> = (0..areas.len()).collect();
+        left_to_right.sort_by_key(|i| areas[*i].x);
+
+        for (row, pixels) in self.pixels.chunks_mut(film_area.width.max(1)).enumerate() {
+            let y = film_area.y + row;
+            let mut rest = pixels;
+            let mut x = film_area.x;
+            for i in left_to_right.iter().cloned() {
+                let area = areas[i];
+                if y < area.y || y >= area.y + area.height {
+                    continue;
+                }
+                assert!(area.x >= x && area.x + area.width <= film_area.x + film_area.width, "tiles overlap");
+                let (_, remainder) = rest.split_at_mut(area.x - x);
+                let (tile_row, remainder) = remainder.split_at_mut(area.width);
+                tiles[i].rows.push(tile_row);
+                rest = remainder;
+                x = area.x + area.width;
+            }
+        }
+        tiles
+    }
+
+    /// Mean of the samples so far
+    pub fn image(&self) -> ImageBuffer<T> {
+        ImageBuffer {
+            width: self.area.width,
+            height: self.area.height,
+            pixels: self.pixels.iter().map(|p| p.mean()).collect(),
+        }
+    }
+
+    pub fn average_samples(&self) -> f64 {
+        let total: u64 = self.pixels.iter().map(|p| p.count as u64).sum();
+        total as f64 / self.pixels.len().max(1) as f64
+    }
+
+    pub fn sample_counts(&self) -> Vec<u32> {
+        self.pixels.iter().map(|p| p.count).collect()
+    }
+
+    pub fn output(&self) -> RenderOutput<T> {
+        RenderOutput {
+            image: self.image(),
+            sample_counts: self.sample_counts(),
+        }
+    }
+}
+
+/// Blue through green and yellow to red for `t` from zero to one
+pub fn heatmap_color<T>(t: f64) -> Vec3<T>
+    where
+        T: FloatType<T>,
+{
+    const STOPS: [[f64; 3]; 5] = [
+        [0.0, 0.0, 0.5],
+        [0.0, 0.5, 1.0],
+        [0.0, 1.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [1.0, 0.0, 0.0],
+    ];
+    let position = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+    let index = (position as usize).min(STOPS.len() - 2);
+    let f = position - index as f64;
+    let channel = |c: usize| {
+        let srgb = STOPS[index][c] * (1.0 - f) + STOPS[index + 1][c] * f;
+        srgb_to_linear(T::from(srgb).unwrap())
+    };
+    Vec3::<T> {
+        x: channel(0),
+        y: channel(1),
+        z: channel(2),
+    }
+}
+
+/// Image of the number of samples every pixel took, scaled to the largest count. The colors are
+/// linear like a render, so any of the image writers can store it.
+pub fn sample_heatmap<T>(sample_counts: &[u32], width: usize, height: usize) -> ImageBuffer<T>
+    where
+        T: FloatType<T>,
+{
+    let max_count = sample_counts.iter().cloned().max().unwrap_or(0).max(1);
+    let pixels = sample_counts
+        .iter()
+        .map(|count| heatmap_color(*count as f64 / max_count as f64))
+        .collect();
+    ImageBuffer { width, height, pixels }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(values: &[f64]) -> PixelStats<f64> {
+        let mut stats = PixelStats::new();
+        for v in values {
+            stats.add(&Vec3::all(*v));
+        }
+        stats
+    }
+
+    #[test]
+    fn add_tracks_the_mean_and_variance() {
+        let stats = stats(&[1.0, 2.0, 6.0]);
+        assert_eq!(stats.count, 3);
+        assert_eq!(stats.sum, Vec3::all(9.0));
+        assert_eq!(stats.mean(), Vec3::all(3.0));
+        assert!((stats.luminance_mean - 3.0).abs() < 1e-12);
+        assert!((stats.luminance_m2 - 14.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn relative_error_needs_two_samples() {
+        assert_eq!(stats(&[]).relative_error(), f64::INFINITY);
+        assert_eq!(stats(&[0.5]).relative_error(), f64::INFINITY);
+        assert_eq!(stats(&[]).mean(), Vec3::zero());
+    }
+
+    #[test]
+    fn relative_error_is_the_standard_error_over_the_mean() {
+        //Variance 2 over 2 samples gives a standard error of 1, half the mean
+        assert!((stats(&[1.0, 3.0]).relative_error() - 0.5).abs() < 1e-12);
+        assert_eq!(stats(&[0.25, 0.25, 0.25]).relative_error(), 0.0);
+    }
+
+    #[test]
+    fn relative_error_of_dark_pixels_uses_the_minimum_mean() {
+        //A standard error of 0.001 is measured against 0.01 rather than the mean of 0.001
+        assert!((stats(&[0.0, 0.002]).relative_error() - 0.1).abs() < 1e-9);
+    }
+}
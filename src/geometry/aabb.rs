@@ -23,6 +23,15 @@ impl<T> AABB<T> where T: FloatType<T> {
         self.max
     }
 
+    pub fn centroid(&self) -> Vec3<T> {
+        (self.min + self.max) / T::from(2).unwrap()
+    }
+
+    pub fn surface_area(&self) -> T {
+        let d = self.max - self.min;
+        T::from(2).unwrap() * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
     pub fn hit(&self, r: &Ray<T>, t_min: T, t_max: T) -> bool {
         for i in 0..3 {
             let inv_d = T::one() / r.get_direction()[i];
@@ -44,6 +53,29 @@ impl<T> AABB<T> where T: FloatType<T> {
         true
     }
 
+    /// Slab test with the reciprocal of the ray direction computed up front, for traversals
+    /// that test many boxes against the same ray
+    pub fn hit_with_inverse(&self, origin: &Vec3<T>, inv_direction: &Vec3<T>, t_min: T, t_max: T) -> bool {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for i in 0..3 {
+            let mut t0 = (self.min[i] - origin[i]) * inv_direction[i];
+            let mut t1 = (self.max[i] - origin[i]) * inv_direction[i];
+
+            if inv_direction[i] < T::zero() {
+                mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+
+            if t_max <= t_min {
+                return false;
+            }
+        }
+        true
+    }
+
     pub fn surrounding_box(box0: &AABB<T>, box1: &AABB<T>) -> AABB<T> {
         let min = Vec3::<T>::new(
             T::min(box0.get_min().x, box1.get_min().x),
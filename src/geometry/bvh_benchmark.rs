@@ -0,0 +1,174 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use straal::FloatType;
+
+use crate::geometry::{AABB, HitRecord, Hittable};
+use crate::math::{duration_to_string, random_in_unit_sphere, Camera, Pcg32, Ray};
+use crate::sampler::{IndependentSampler, Sampler};
+
+type SharedHittable<T> = Arc<dyn Hittable<T> + Send + Sync>;
+
+/// The BVH as it was built before the SAH builder, kept as the reference the other trees are
+/// measured against. Primitives are sorted along a random axis and split at the median, and
+/// traversal tests both children over the whole ray interval. The axes are drawn from a fixed
+/// seed, so every run builds the same tree.
+pub struct MedianSplitBvh<T> where T: Sync + Send {
+    left: SharedHittable<T>,
+    right: SharedHittable<T>,
+    aabb: AABB<T>,
+}
+
+impl<T> MedianSplitBvh<T> where T: FloatType<T> + Sync + Send + 'static {
+    /// Returns `None` when the list is empty or holds a primitive without a bounding box, which
+    /// the original tree couldn't handle either
+    pub fn new(list: &[SharedHittable<T>], time0: T, time1: T) -> Option<MedianSplitBvh<T>> {
+        let mut boxed = Vec::with_capacity(list.len());
+        for hittable in list {
+            boxed.push((hittable.bounding_box(time0, time1)?, hittable.clone()));
+        }
+        if boxed.is_empty() {
+            return None;
+        }
+        Some(Self::build(&mut boxed[..], &mut Pcg32::new(0, 0)))
+    }
+
+    fn build(list: &mut [(AABB<T>, SharedHittable<T>)], rng: &mut Pcg32) -> MedianSplitBvh<T> {
+        let axis = rng.gen_range(0, 3);
+        list.sort_unstable_by(|(a, _), (b, _)| a.get_min()[axis].partial_cmp(&b.get_min()[axis]).unwrap());
+
+        let (left, right): (SharedHittable<T>, SharedHittable<T>) = match list.len() {
+            1 => (list[0].1.clone(), list[0].1.clone()),
+            2 => (list[0].1.clone(), list[1].1.clone()),
+            _ => {
+                let (first, second) = list.split_at_mut(list.len() / 2);
+                (Arc::new(Self::build(first, rng)), Arc::new(Self::build(second, rng)))
+            }
+        };
+        let aabb = list.iter().skip(1).fold(list[0].0.clone(), |aabb, (b, _)| AABB::surrounding_box(&aabb, b));
+        MedianSplitBvh { left, right, aabb }
+    }
+}
+
+impl<T> Hittable<T> for MedianSplitBvh<T> where T: FloatType<T> + Sync + Send {
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T, record: &mut HitRecord<T>) -> bool {
+        if !self.aabb.hit(r, t_min, t_max) {
+            return false;
+        }
+        let mut left_rec = HitRecord::<T>::default();
+        let mut right_rec = HitRecord::<T>::default();
+        let hit_left = self.left.hit(r, t_min, t_max, &mut left_rec);
+        let hit_right = self.right.hit(r, t_min, t_max, &mut right_rec);
+        match (hit_left, hit_right) {
+            (true, true) if right_rec.t <= left_rec.t => record.update(right_rec),
+            (true, _) => record.update(left_rec),
+            (false, true) => record.update(right_rec),
+            (false, false) => return false,
+        }
+        true
+    }
+
+    fn bounding_box(&self, _t0: T, _t1: T) -> Option<AABB<T>> {
+        Some(self.aabb.clone())
+    }
+}
+
+pub struct TraversalBenchmark {
+    pub name: String,
+    pub rays: usize,
+    pub hits: usize,
+    pub duration: Duration,
+}
+
+impl TraversalBenchmark {
+    pub fn rays_per_second(&self) -> f64 {
+        let seconds = self.duration.as_secs() as f64 + self.duration.subsec_nanos() as f64 / 1.0e+9;
+        if seconds > 0.0 {
+            self.rays as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    /// How many times faster this run traced its rays than `baseline`
+    pub fn speedup_over(&self, baseline: &TraversalBenchmark) -> f64 {
+        let baseline_speed = baseline.rays_per_second();
+        if baseline_speed > 0.0 {
+            self.rays_per_second() / baseline_speed
+        } else {
+            0.0
+        }
+    }
+}
+
+impl fmt::Display for TraversalBenchmark {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} rays ({} hits) in {}, {:.0} rays/s",
+            self.name,
+            self.rays,
+            self.hits,
+            duration_to_string(&self.duration),
+            self.rays_per_second()
+        )
+    }
+}
+
+/// One primary ray through the center of every pixel, the same rays every time
+pub fn generate_primary_rays<T>(camera: &Camera<T>, width: usize, height: usize) -> Vec<Ray<T>>
+    where
+        T: FloatType<T>,
+{
+    let mut sampler = IndependentSampler::new(0);
+    let mut rays = Vec::with_capacity(width * height);
+    for j in 0..height {
+        for i in 0..width {
+            sampler.start_pixel_sample(i, j, 0);
+            let u = (T::from(i).unwrap() + T::from(0.5).unwrap()) / T::from(width).unwrap();
+            let v = (T::from(j).unwrap() + T::from(0.5).unwrap()) / T::from(height).unwrap();
+            rays.push(camera.get_ray(u, v, &mut sampler));
+        }
+    }
+    rays
+}
+
+/// Traces the primary rays, plus a diffuse bounce ray for every primary hit, on a single thread.
+/// Passing the same rays to different acceleration structures gives comparable numbers.
+pub fn benchmark_traversal<T>(name: &str, hittable: &dyn Hittable<T>, primary_rays: &[Ray<T>]) -> TraversalBenchmark
+    where
+        T: FloatType<T> + Send + Sync,
+{
+    let t_min = T::from(0.01).unwrap();
+    let t_max = T::from(10000000.0).unwrap();
+    let mut rays = 0;
+    let mut hits = 0;
+    let mut rng = Pcg32::new(0, 0);
+
+    let start_time = Instant::now();
+    for ray in primary_rays {
+        let mut record = HitRecord::<T>::default();
+        rays += 1;
+        if hittable.hit(ray, t_min, t_max, &mut record) {
+            hits += 1;
+            let bounce = Ray {
+                origin: record.position,
+                direction: record.normal + random_in_unit_sphere(&mut rng),
+                time: ray.get_time(),
+            };
+            rays += 1;
+            if hittable.hit(&bounce, t_min, t_max, &mut record) {
+                hits += 1;
+            }
+        }
+    }
+
+    TraversalBenchmark {
+        name: name.to_string(),
+        rays,
+        hits,
+        duration: start_time.elapsed(),
+    }
+}
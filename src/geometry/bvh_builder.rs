@@ -0,0 +1,371 @@
+use std::fmt;
+use std::sync::Arc;
+
+use straal::FloatType;
+
+use crate::geometry::{AABB, Hittable};
+
+const BIN_COUNT: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+//Keeps traversal stacks bounded, degenerate inputs get bigger leaves instead of deeper trees
+pub const MAX_BVH_DEPTH: usize = 48;
+//Costs are relative to a single primitive intersection
+const TRAVERSAL_COST: f64 = 1.0;
+const INTERSECTION_COST: f64 = 1.0;
+
+#[derive(Clone, Debug, Default)]
+pub struct BvhStats {
+    pub primitive_count: usize,
+    pub unbounded_count: usize,
+    pub node_count: usize,
+    pub leaf_count: usize,
+    pub max_depth: usize,
+    pub min_leaf_size: usize,
+    pub max_leaf_size: usize,
+    pub sah_cost: f64,
+}
+
+impl BvhStats {
+    pub fn average_leaf_size(&self) -> f64 {
+        if self.leaf_count == 0 {
+            0.0
+        } else {
+            self.primitive_count as f64 / self.leaf_count as f64
+        }
+    }
+}
+
+impl fmt::Display for BvhStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "BVH: {} primitives ({} unbounded), {} nodes, {} leaves, depth {}, leaf size {}-{} (avg {:.2}), SAH cost {:.2}",
+            self.primitive_count,
+            self.unbounded_count,
+            self.node_count,
+            self.leaf_count,
+            self.max_depth,
+            self.min_leaf_size,
+            self.max_leaf_size,
+            self.average_leaf_size(),
+            self.sah_cost
+        )
+    }
+}
+
+/// Intermediate tree produced by the builder. Leaves refer to a range of `BvhBuild::primitives`.
+pub enum BvhBuildNode<T> {
+    Leaf {
+        aabb: AABB<T>,
+        start: usize,
+        count: usize,
+    },
+    Interior {
+        aabb: AABB<T>,
+        axis: usize,
+        left: Box<BvhBuildNode<T>>,
+        right: Box<BvhBuildNode<T>>,
+    },
+}
+
+impl<T> BvhBuildNode<T> {
+    pub fn get_aabb(&self) -> &AABB<T> {
+        match self {
+            BvhBuildNode::Leaf { aabb, .. } => aabb,
+            BvhBuildNode::Interior { aabb, .. } => aabb,
+        }
+    }
+}
+
+pub struct BvhBuild<T> where T: Sync + Send {
+    /// Root of the tree, `None` when there were no primitives with a bounding box
+    pub root: Option<BvhBuildNode<T>>,
+    /// Bounded primitives, ordered so every leaf covers a contiguous range
+    pub primitives: Vec<Arc<dyn Hittable<T> + Send + Sync>>,
+    /// Primitives without a bounding box, these have to be tested for every ray
+    pub unbounded: Vec<Arc<dyn Hittable<T> + Send + Sync>>,
+    pub stats: BvhStats,
+}
+
+struct BuildPrimitive<T> {
+    index: usize,
+    aabb: AABB<T>,
+    centroid: [f64; 3],
+}
+
+fn to_f64<T>(x: T) -> f64 where T: FloatType<T> {
+    num::cast(x).unwrap_or(0.0)
+}
+
+fn surface_area<T>(aabb: &AABB<T>) -> f64 where T: FloatType<T> {
+    to_f64(aabb.surface_area())
+}
+
+fn bin_index(centroid: f64, min: f64, extent: f64) -> usize {
+    let bin = ((centroid - min) / extent * BIN_COUNT as f64) as usize;
+    if bin >= BIN_COUNT { BIN_COUNT - 1 } else { bin }
+}
+
+fn merge<T>(a: &Option<AABB<T>>, b: &AABB<T>) -> Option<AABB<T>> where T: FloatType<T> {
+    match a {
+        Some(a) => Some(AABB::surrounding_box(a, b)),
+        None => Some(b.clone()),
+    }
+}
+
+fn make_leaf<T>(aabb: AABB<T>, start: usize, count: usize, stats: &mut BvhStats) -> BvhBuildNode<T> {
+    stats.node_count += 1;
+    stats.leaf_count += 1;
+    stats.min_leaf_size = if stats.leaf_count == 1 { count } else { stats.min_leaf_size.min(count) };
+    stats.max_leaf_size = stats.max_leaf_size.max(count);
+    BvhBuildNode::Leaf { aabb, start, count }
+}
+
+/// Finds the cheapest split as (axis, last bin of the left side, cost) using binned SAH
+fn find_split<T>(prims: &[BuildPrimitive<T>], node_area: f64, c_min: &[f64; 3], c_max: &[f64; 3]) -> Option<(usize, usize, f64)>
+    where
+        T: FloatType<T>,
+{
+    let mut best: Option<(usize, usize, f64)> = None;
+    for axis in 0..3 {
+        let extent = c_max[axis] - c_min[axis];
+        if extent <= 0.0 {
+            continue;
+        }
+
+        let mut counts = [0usize; BIN_COUNT];
+        let mut bounds: Vec<Option<AABB<T>>> = vec![None; BIN_COUNT];
+        for p in prims {
+            let b = bin_index(p.centroid[axis], c_min[axis], extent);
+            counts[b] += 1;
+            bounds[b] = merge(&bounds[b], &p.aabb);
+        }
+
+        //Sweep from the right to know the area and count on the right of every split plane
+        let mut right_area = [0.0; BIN_COUNT];
+        let mut right_count = [0usize; BIN_COUNT];
+        let mut accumulated: Option<AABB<T>> = None;
+        let mut count = 0;
+        for i in (1..BIN_COUNT).rev() {
+            if let Some(b) = &bounds[i] {
+                accumulated = merge(&accumulated, b);
+            }
+            count += counts[i];
+            right_count[i] = count;
+            right_area[i] = accumulated.as_ref().map_or(0.0, surface_area);
+        }
+
+        let mut accumulated: Option<AABB<T>> = None;
+        let mut count = 0;
+        for i in 0..BIN_COUNT - 1 {
+            if let Some(b) = &bounds[i] {
+                accumulated = merge(&accumulated, b);
+            }
+            count += counts[i];
+            if count == 0 || right_count[i + 1] == 0 {
+                continue;
+            }
+            let left_area = accumulated.as_ref().map_or(0.0, surface_area);
+            let cost = TRAVERSAL_COST
+                + INTERSECTION_COST * (left_area * count as f64 + right_area[i + 1] * right_count[i + 1] as f64) / node_area;
+            if !best.is_some_and(|(_, _, best_cost)| best_cost <= cost) {
+                best = Some((axis, i, cost));
+            }
+        }
+    }
+    best
+}
+
+fn build_node<T>(prims: &mut [BuildPrimitive<T>], start: usize, depth: usize, stats: &mut BvhStats) -> BvhBuildNode<T>
+    where
+        T: FloatType<T>,
+{
+    let aabb = prims[1..]
+        .iter()
+        .fold(prims[0].aabb.clone(), |acc, p| AABB::surrounding_box(&acc, &p.aabb));
+    let count = prims.len();
+    stats.max_depth = stats.max_depth.max(depth);
+
+    if count == 1 || depth >= MAX_BVH_DEPTH {
+        return make_leaf(aabb, start, count, stats);
+    }
+
+    let mut c_min = [f64::INFINITY; 3];
+    let mut c_max = [f64::NEG_INFINITY; 3];
+    for p in prims.iter() {
+        for axis in 0..3 {
+            c_min[axis] = c_min[axis].min(p.centroid[axis]);
+            c_max[axis] = c_max[axis].max(p.centroid[axis]);
+        }
+    }
+
+    let node_area = surface_area(&aabb).max(f64::MIN_POSITIVE);
+    let leaf_cost = INTERSECTION_COST * count as f64;
+
+    let (axis, mid) = match find_split(prims, node_area, &c_min, &c_max) {
+        Some((_, _, cost)) if count <= MAX_LEAF_SIZE && leaf_cost <= cost => {
+            return make_leaf(aabb, start, count, stats);
+        }
+        Some((axis, split, _)) => {
+            let extent = c_max[axis] - c_min[axis];
+            let mut mid = 0;
+            for i in 0..count {
+                if bin_index(prims[i].centroid[axis], c_min[axis], extent) <= split {
+                    prims.swap(i, mid);
+                    mid += 1;
+                }
+            }
+            (axis, mid)
+        }
+        //All centroids coincide, so no plane can separate them
+        None if count <= MAX_LEAF_SIZE => {
+            return make_leaf(aabb, start, count, stats);
+        }
+        None => (0, count / 2),
+    };
+
+    stats.node_count += 1;
+    let (left, right) = prims.split_at_mut(mid);
+    let left = build_node(left, start, depth + 1, stats);
+    let right = build_node(right, start + mid, depth + 1, stats);
+    BvhBuildNode::Interior {
+        aabb,
+        axis,
+        left: Box::new(left),
+        right: Box::new(right),
+    }
+}
+
+/// Expected cost of tracing a ray through the tree, relative to the root's surface area
+fn sah_cost<T>(node: &BvhBuildNode<T>, root_area: f64) -> f64 where T: FloatType<T> {
+    let relative_area = surface_area(node.get_aabb()) / root_area;
+    match node {
+        BvhBuildNode::Leaf { count, .. } => relative_area * INTERSECTION_COST * *count as f64,
+        BvhBuildNode::Interior { left, right, .. } => {
+            relative_area * TRAVERSAL_COST + sah_cost(left, root_area) + sah_cost(right, root_area)
+        }
+    }
+}
+
+/// Builds a BVH using the surface area heuristic, evaluated at a fixed number of bins per axis.
+/// Primitives without a bounding box are kept aside in `BvhBuild::unbounded`.
+pub fn build_sah<T>(list: &[Arc<dyn Hittable<T> + Send + Sync>], time0: T, time1: T) -> BvhBuild<T>
+    where
+        T: FloatType<T> + Send + Sync,
+{
+    let mut stats = BvhStats::default();
+    let mut unbounded = Vec::new();
+    let mut prims = Vec::with_capacity(list.len());
+
+    for (index, hittable) in list.iter().enumerate() {
+        match hittable.bounding_box(time0, time1) {
+            Some(aabb) => {
+                let centroid = aabb.centroid();
+                prims.push(BuildPrimitive {
+                    index,
+                    aabb,
+                    centroid: [to_f64(centroid.x), to_f64(centroid.y), to_f64(centroid.z)],
+                });
+            }
+            None => unbounded.push(hittable.clone()),
+        }
+    }
+
+    stats.primitive_count = prims.len();
+    stats.unbounded_count = unbounded.len();
+
+    let root = if prims.is_empty() {
+        None
+    } else {
+        let root = build_node(&mut prims[..], 0, 0, &mut stats);
+        let root_area = surface_area(root.get_aabb()).max(f64::MIN_POSITIVE);
+        stats.sah_cost = sah_cost(&root, root_area);
+        Some(root)
+    };
+
+    BvhBuild {
+        root,
+        primitives: prims.iter().map(|p| list[p.index].clone()).collect(),
+        unbounded,
+        stats,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use straal::Vec3;
+
+    use super::*;
+    use crate::geometry::{HitRecord, Sphere};
+    use crate::material::DummyMaterial;
+    use crate::math::Ray;
+
+    struct Unbounded;
+
+    impl Hittable<f64> for Unbounded {
+        fn hit(&self, _r: &Ray<f64>, _t_min: f64, _t_max: f64, _record: &mut HitRecord<f64>) -> bool {
+            false
+        }
+
+        fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB<f64>> {
+            None
+        }
+    }
+
+    fn spheres(centers: &[f64]) -> Vec<Arc<dyn Hittable<f64> + Send + Sync>> {
+        centers
+            .iter()
+            .map(|x| {
+                Arc::new(Sphere {
+                    center: Vec3::new(*x, 0.0, 0.0),
+                    radius: 0.5,
+                    material: Arc::new(DummyMaterial),
+                }) as Arc<dyn Hittable<f64> + Send + Sync>
+            })
+            .collect()
+    }
+
+    #[test]
+    fn separated_primitives_get_a_leaf_each() {
+        let build = build_sah(&spheres(&[0.0, 10.0, 20.0, 30.0, 40.0, 50.0, 60.0, 70.0]), 0.0, 1.0);
+        let stats = &build.stats;
+        assert_eq!(stats.primitive_count, 8);
+        assert_eq!(stats.unbounded_count, 0);
+        assert_eq!(stats.node_count, 15);
+        assert_eq!(stats.leaf_count, 8);
+        assert_eq!(stats.max_depth, 3);
+        assert_eq!((stats.min_leaf_size, stats.max_leaf_size), (1, 1));
+        assert_eq!(build.primitives.len(), 8);
+    }
+
+    #[test]
+    fn coinciding_centroids_are_split_in_half() {
+        let build = build_sah(&spheres(&[5.0; 6]), 0.0, 1.0);
+        let stats = &build.stats;
+        assert_eq!(stats.node_count, 3);
+        assert_eq!(stats.leaf_count, 2);
+        assert_eq!(stats.max_depth, 1);
+        assert_eq!((stats.min_leaf_size, stats.max_leaf_size), (3, 3));
+    }
+
+    #[test]
+    fn unbounded_primitives_are_kept_out_of_the_tree() {
+        let mut list = spheres(&[0.0, 10.0, 20.0]);
+        list.insert(1, Arc::new(Unbounded));
+        let build = build_sah(&list, 0.0, 1.0);
+        let stats = &build.stats;
+        assert_eq!(stats.primitive_count, 3);
+        assert_eq!(stats.unbounded_count, 1);
+        assert_eq!(stats.node_count, 5);
+        assert_eq!(stats.leaf_count, 3);
+        assert_eq!(stats.max_depth, 2);
+        assert_eq!((stats.min_leaf_size, stats.max_leaf_size), (1, 1));
+        assert_eq!(build.primitives.len(), 3);
+        assert_eq!(build.unbounded.len(), 1);
+
+        let build = build_sah(&[Arc::new(Unbounded) as Arc<dyn Hittable<f64> + Send + Sync>], 0.0, 1.0);
+        assert!(build.root.is_none());
+        assert_eq!(build.stats.node_count, 0);
+        assert_eq!(build.stats.unbounded_count, 1);
+    }
+}
@@ -1,7 +1,8 @@
-use straal::FloatType;
+use straal::{FloatType, Vec3};
 
 use crate::geometry::{AABB, HitRecord};
 use crate::math::Ray;
+use crate::sampler::Sampler;
 
 pub trait Hittable<T>: Send + Sync
     where
@@ -9,4 +10,16 @@ pub trait Hittable<T>: Send + Sync
 {
     fn hit(&self, r: &Ray<T>, t_min: T, t_max: T, record: &mut HitRecord<T>) -> bool;
     fn bounding_box(&self, t0: T, t1: T) -> Option<AABB<T>>;
+
+    /// Probability density, per unit solid angle as seen from `origin`, with which
+    /// `random_direction` picks `direction`. Hits closer than `t_min` don't count, as for `hit`.
+    /// Shapes that can't be sampled return zero.
+    fn pdf_value(&self, _origin: &Vec3<T>, _direction: &Vec3<T>, _time: T, _t_min: T) -> T {
+        T::zero()
+    }
+
+    /// Random direction from `origin` towards the shape, used to sample light sources
+    fn random_direction(&self, _origin: &Vec3<T>, _time: T, _sampler: &mut dyn Sampler) -> Vec3<T> {
+        Vec3::<T>::new(1.0, 0.0, 0.0)
+    }
 }
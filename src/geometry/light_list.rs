@@ -0,0 +1,50 @@
+use std::sync::Arc;
+
+use straal::{FloatType, Vec3};
+
+use crate::geometry::Hittable;
+use crate::sampler::Sampler;
+
+/// Shapes that are sampled directly for next event estimation. A light is picked uniformly, so
+/// the density of a direction is the average of the densities of all lights.
+pub struct LightList<T> {
+    pub lights: Vec<Arc<dyn Hittable<T> + Send + Sync>>,
+}
+
+impl<T> LightList<T>
+    where
+        T: FloatType<T> + Send + Sync,
+{
+    pub fn new() -> LightList<T> {
+        LightList { lights: Vec::new() }
+    }
+
+    pub fn add_light(&mut self, light: Arc<dyn Hittable<T> + Send + Sync>) {
+        self.lights.push(light);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn pdf_value(&self, origin: &Vec3<T>, direction: &Vec3<T>, time: T, t_min: T) -> T {
+        if self.lights.is_empty() {
+            return T::zero();
+        }
+        let sum = self
+            .lights
+            .iter()
+            .fold(T::zero(), |acc, light| acc + light.pdf_value(origin, direction, time, t_min));
+        sum / T::from(self.lights.len()).unwrap()
+    }
+
+    /// Picks a light with one sample dimension, the light samples a direction from the next ones
+    pub fn random_direction(&self, origin: &Vec3<T>, time: T, sampler: &mut dyn Sampler) -> Vec3<T> {
+        let index = ((sampler.get_1d() * self.lights.len() as f64) as usize).min(self.lights.len() - 1);
+        self.lights[index].random_direction(origin, time, sampler)
+    }
+}
@@ -0,0 +1,221 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use straal::{FloatType, Vec3};
+
+use crate::error::{Error, Result};
+use crate::geometry::{AABB, build_sah, BvhBuildNode, BvhStats, HitRecord, Hittable, MAX_BVH_DEPTH};
+use crate::math::Ray;
+
+#[derive(Clone, Debug)]
+pub struct LinearBvhNode<T> {
+    pub aabb: AABB<T>,
+    /// First primitive for leaves, index of the second child for interior nodes. The first child
+    /// of an interior node always directly follows it.
+    pub offset: usize,
+    /// Zero for interior nodes
+    pub primitive_count: usize,
+    pub axis: usize,
+}
+
+/// BVH flattened into an array in depth-first order, so traversal walks through memory mostly
+/// linearly instead of chasing pointers.
+pub struct LinearBvh<T> where T: Sync + Send {
+    pub nodes: Vec<LinearBvhNode<T>>,
+    pub primitives: Vec<Arc<dyn Hittable<T> + Send + Sync>>,
+    pub unbounded: Vec<Arc<dyn Hittable<T> + Send + Sync>>,
+}
+
+impl<T> LinearBvh<T> where T: FloatType<T> + Sync + Send + Debug + 'static {
+    pub fn new(list: &[Arc<dyn Hittable<T> + Send + Sync>], time0: T, time1: T) -> Result<LinearBvh<T>> {
+        Ok(LinearBvh::with_stats(list, time0, time1)?.0)
+    }
+
+    pub fn with_stats(list: &[Arc<dyn Hittable<T> + Send + Sync>], time0: T, time1: T) -> Result<(LinearBvh<T>, BvhStats)> {
+        if list.is_empty() {
+            return Err(Error::EmptyBvh);
+        }
+
+        let build = build_sah(list, time0, time1);
+        let mut nodes = Vec::with_capacity(build.stats.node_count);
+        if let Some(root) = &build.root {
+            LinearBvh::flatten(root, &mut nodes);
+        }
+        let bvh = LinearBvh {
+            nodes,
+            primitives: build.primitives,
+            unbounded: build.unbounded,
+        };
+        Ok((bvh, build.stats))
+    }
+
+    fn flatten(node: &BvhBuildNode<T>, nodes: &mut Vec<LinearBvhNode<T>>) -> usize {
+        let index = nodes.len();
+        match node {
+            BvhBuildNode::Leaf { aabb, start, count } => {
+                nodes.push(LinearBvhNode {
+                    aabb: aabb.clone(),
+                    offset: *start,
+                    primitive_count: *count,
+                    axis: 0,
+                });
+            }
+            BvhBuildNode::Interior { aabb, axis, left, right } => {
+                nodes.push(LinearBvhNode {
+                    aabb: aabb.clone(),
+                    offset: 0,
+                    primitive_count: 0,
+                    axis: *axis,
+                });
+                LinearBvh::flatten(left, nodes);
+                nodes[index].offset = LinearBvh::flatten(right, nodes);
+            }
+        }
+        index
+    }
+}
+
+impl<T> Hittable<T> for LinearBvh<T> where T: FloatType<T> + Sync + Send {
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T, record: &mut HitRecord<T>) -> bool {
+        let mut hit_anything = false;
+        let mut closest_so_far = t_max;
+
+        for hittable in &self.unbounded {
+            if hittable.hit(r, t_min, closest_so_far, record) {
+                hit_anything = true;
+                closest_so_far = record.t;
+            }
+        }
+
+        if self.nodes.is_empty() {
+            return hit_anything;
+        }
+
+        let origin = r.get_origin();
+        let direction = r.get_direction();
+        let inv_direction = Vec3::<T> {
+            x: T::one() / direction.x,
+            y: T::one() / direction.y,
+            z: T::one() / direction.z,
+        };
+        let direction_is_negative = [
+            inv_direction.x < T::zero(),
+            inv_direction.y < T::zero(),
+            inv_direction.z < T::zero(),
+        ];
+
+        //Nodes still to be visited, the tree depth is capped by the builder
+        let mut stack = [0usize; MAX_BVH_DEPTH + 1];
+        let mut stack_size = 0;
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            if node.aabb.hit_with_inverse(&origin, &inv_direction, t_min, closest_so_far) {
+                if node.primitive_count > 0 {
+                    for hittable in &self.primitives[node.offset..node.offset + node.primitive_count] {
+                        if hittable.hit(r, t_min, closest_so_far, record) {
+                            hit_anything = true;
+                            closest_so_far = record.t;
+                        }
+                    }
+                } else {
+                    //Visit the child closest along the ray first, so the far one can be pruned
+                    //with the shrunk closest_so_far
+                    if direction_is_negative[node.axis] {
+                        stack[stack_size] = current + 1;
+                        current = node.offset;
+                    } else {
+                        stack[stack_size] = node.offset;
+                        current += 1;
+                    }
+                    stack_size += 1;
+                    continue;
+                }
+            }
+            if stack_size == 0 {
+                break;
+            }
+            stack_size -= 1;
+            current = stack[stack_size];
+        }
+        hit_anything
+    }
+
+    fn bounding_box(&self, _t0: T, _t1: T) -> Option<AABB<T>> {
+        if self.unbounded.is_empty() && !self.nodes.is_empty() {
+            Some(self.nodes[0].aabb.clone())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::geometry::{BvhNode, MedianSplitBvh, MeshData, Sphere};
+    use crate::material::DummyMaterial;
+
+    fn random_point(rng: &mut StdRng, scale: f64) -> Vec3<f64> {
+        Vec3::new(rng.gen_range(-scale, scale), rng.gen_range(-scale, scale), rng.gen_range(-scale, scale))
+    }
+
+    /// Overlapping spheres and triangles of all sizes, so rays regularly cross several of them
+    fn random_scene(rng: &mut StdRng) -> Vec<Arc<dyn Hittable<f64> + Send + Sync>> {
+        let mut list: Vec<Arc<dyn Hittable<f64> + Send + Sync>> = Vec::new();
+        for _ in 0..150 {
+            list.push(Arc::new(Sphere {
+                center: random_point(rng, 10.0),
+                radius: rng.gen_range(0.05, 1.5),
+                material: Arc::new(DummyMaterial),
+            }));
+        }
+        let mut positions = Vec::new();
+        for _ in 0..150 {
+            let corner = random_point(rng, 10.0);
+            positions.push(corner);
+            positions.push(corner + random_point(rng, 2.0));
+            positions.push(corner + random_point(rng, 2.0));
+        }
+        let indices = (0..150).map(|i| [3 * i, 3 * i + 1, 3 * i + 2]).collect();
+        let mesh = Arc::new(MeshData::new(positions, vec![], vec![], indices, Arc::new(DummyMaterial)));
+        list.extend(MeshData::triangles(&mesh));
+        list
+    }
+
+    fn closest_hit(hittable: &dyn Hittable<f64>, r: &Ray<f64>) -> Option<(f64, Vec3<f64>, Vec3<f64>)> {
+        let mut record = HitRecord::default();
+        if hittable.hit(r, 0.001, 1.0e9, &mut record) {
+            Some((record.t, record.position, record.normal))
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn all_trees_find_the_same_closest_hit() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let list = random_scene(&mut rng);
+        let linear = LinearBvh::new(&list, 0.0, 1.0).unwrap();
+        let tree = BvhNode::new(&list, 0.0, 1.0).unwrap();
+        let median = MedianSplitBvh::new(&list, 0.0, 1.0).unwrap();
+
+        let mut hits = 0;
+        for _ in 0..5000 {
+            let r = Ray {
+                origin: random_point(&mut rng, 15.0),
+                direction: random_point(&mut rng, 1.0),
+                time: 0.0,
+            };
+            let expected = closest_hit(&linear, &r);
+            assert_eq!(closest_hit(&tree, &r), expected);
+            assert_eq!(closest_hit(&median, &r), expected);
+            hits += expected.is_some() as usize;
+        }
+        //Make sure the comparison isn't only between misses
+        assert!(hits > 1000, "only {} rays hit anything", hits);
+    }
+}
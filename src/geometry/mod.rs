@@ -3,26 +3,56 @@ use std::sync::Weak;
 use straal::{FloatType, Vec3};
 
 pub use aabb::*;
+pub use bvh_benchmark::*;
+pub use bvh_builder::*;
 pub use bvh_node::*;
 pub use hittable::*;
+pub use light_list::*;
+pub use linear_bvh::*;
 pub use movable_sphere::*;
 pub use scene::*;
 pub use sphere::*;
+pub use triangle::*;
+pub use triangle_mesh::*;
 
 use crate::material::{DummyMaterial, Material};
+use crate::math::Onb;
 
 pub mod hittable;
 pub mod scene;
 pub mod sphere;
 pub mod movable_sphere;
 pub mod aabb;
+pub mod bvh_benchmark;
+pub mod bvh_builder;
 pub mod bvh_node;
+pub mod light_list;
+pub mod linear_bvh;
+pub mod triangle;
+pub mod triangle_mesh;
 
 #[derive(Clone)]
 pub struct HitRecord<T> {
     pub t: T,
     pub position: Vec3<T>,
+    /// Normal of the surface itself. It points out of closed shapes and along the winding or
+    /// vertex normals of triangles, whichever side the ray came from.
     pub normal: Vec3<T>,
+    /// Whether the ray arrived from the side `normal` points to
+    pub front_face: bool,
+    /// Normal used for shading, from interpolated vertex normals and normal or bump maps. It
+    /// always lies on the same side of the surface as `normal`.
+    pub shading_normal: Vec3<T>,
+    /// Unit direction along the surface in which `u` increases, zero when unknown
+    pub tangent: Vec3<T>,
+    /// Unit direction along the surface in which `v` increases, zero when unknown
+    pub bitangent: Vec3<T>,
+    pub u: T,
+    pub v: T,
+    /// Change in texture coordinates per unit of distance along the surface
+    pub uv_density: T,
+    /// Width of the area around the hit that one pixel covers, set by the integrator
+    pub footprint: T,
     pub material: Weak<dyn Material<T>>,
 }
 
@@ -35,6 +65,14 @@ impl<T> HitRecord<T>
             t: T::from(0).unwrap(),
             position: Vec3::zero(),
             normal: Vec3::zero(),
+            front_face: true,
+            shading_normal: Vec3::zero(),
+            tangent: Vec3::zero(),
+            bitangent: Vec3::zero(),
+            u: T::zero(),
+            v: T::zero(),
+            uv_density: T::zero(),
+            footprint: T::zero(),
             material: Weak::<DummyMaterial>::new(),
         }
     }
@@ -43,6 +81,45 @@ impl<T> HitRecord<T>
         self.material = other.material;
         self.position = other.position;
         self.normal = other.normal;
+        self.front_face = other.front_face;
+        self.shading_normal = other.shading_normal;
+        self.tangent = other.tangent;
+        self.bitangent = other.bitangent;
+        self.u = other.u;
+        self.v = other.v;
+        self.uv_density = other.uv_density;
+        self.footprint = other.footprint;
         self.t = other.t;
     }
+
+    /// `normal` turned towards the side the ray came from, for materials that look the same from
+    /// both sides
+    pub fn facing_normal(&self) -> Vec3<T> {
+        if self.front_face { self.normal } else { -self.normal }
+    }
+
+    /// `shading_normal` turned towards the side the ray came from
+    pub fn facing_shading_normal(&self) -> Vec3<T> {
+        if self.front_face { self.shading_normal } else { -self.shading_normal }
+    }
+
+    /// Width of the pixel footprint in texture coordinates, for filtering texture lookups
+    pub fn uv_footprint(&self) -> T {
+        self.footprint * self.uv_density
+    }
+
+    /// Orthonormal frame around the shading normal with `u` along the tangent and `v` towards
+    /// the bitangent, so mirrored texture coordinates give a mirrored frame. Any frame around the
+    /// normal is used when the tangent is unknown or parallel to the normal.
+    pub fn tangent_frame(&self) -> Onb<T> {
+        let w = self.shading_normal;
+        let tangent = self.tangent - w * Vec3::dot(self.tangent, w);
+        if tangent.length_squared() < T::from(1e-12).unwrap() {
+            return Onb::from_w(&w);
+        }
+        let u = tangent.normalized();
+        let v = w.cross(u);
+        let v = if Vec3::dot(v, self.bitangent) < T::zero() { -v } else { v };
+        Onb { u, v, w }
+    }
 }
\ No newline at end of file
@@ -3,7 +3,7 @@ use std::sync::Arc;
 
 use straal::{FloatType, Vec3};
 
-use crate::geometry::{AABB, HitRecord, Hittable};
+use crate::geometry::{AABB, HitRecord, Hittable, sphere_tangents, sphere_uv};
 use crate::material::Material;
 use crate::math::Ray;
 
@@ -41,11 +41,21 @@ impl<T> Hittable<T> for MovableSphere<T>
         let discriminant = b * b - a * c;
         if discriminant > T::zero() {
             let sqrt_d = discriminant.sqrt();
+            let uv_density = T::one() / (T::from(std::f64::consts::PI).unwrap() * self.radius);
             let sol = (-b - sqrt_d) / a;
             if sol < t_max && sol > t_min {
                 record.t = sol;
                 record.position = r.point_at_parameter(sol);
                 record.normal = (record.position - self.get_center(r.get_time())) / self.radius;
+                let (u, v) = sphere_uv(&record.normal);
+                let (tangent, bitangent) = sphere_tangents(&record.normal);
+                record.shading_normal = record.normal;
+                record.tangent = tangent;
+                record.bitangent = bitangent;
+                record.u = u;
+                record.v = v;
+                record.uv_density = uv_density;
+                record.front_face = Vec3::dot(r.direction, record.normal) < T::zero();
                 record.material = Arc::downgrade(&self.material);
                 return true;
             }
@@ -54,6 +64,15 @@ impl<T> Hittable<T> for MovableSphere<T>
                 record.t = sol;
                 record.position = r.point_at_parameter(sol);
                 record.normal = (record.position - self.get_center(r.get_time())) / self.radius;
+                let (u, v) = sphere_uv(&record.normal);
+                let (tangent, bitangent) = sphere_tangents(&record.normal);
+                record.shading_normal = record.normal;
+                record.tangent = tangent;
+                record.bitangent = bitangent;
+                record.u = u;
+                record.v = v;
+                record.uv_density = uv_density;
+                record.front_face = Vec3::dot(r.direction, record.normal) < T::zero();
                 record.material = Arc::downgrade(&self.material);
                 return true;
             }
@@ -1,27 +1,81 @@
+use std::fmt::Debug;
 use std::sync::Arc;
 
-use straal::FloatType;
+use straal::{FloatType, Vec3};
 
-use crate::geometry::{AABB, HitRecord, Hittable};
+use crate::geometry::{AABB, HitRecord, Hittable, LightList, MeshData};
 use crate::math::Ray;
 
+/// What a ray sees when it leaves the scene without hitting anything
+#[derive(Clone)]
+pub enum Background<T> {
+    /// Blue to white gradient along the y axis
+    Sky,
+    Color(Vec3<T>),
+}
+
+impl<T> Background<T>
+    where
+        T: FloatType<T>,
+{
+    pub fn sample(&self, r: &Ray<T>) -> Vec3<T> {
+        match self {
+            Background::Sky => {
+                let unit_direction = r.get_direction().normalized();
+                let t = T::from(0.5).unwrap() * (unit_direction.y + T::one());
+                Vec3::<T>::one() * (T::one() - t) + Vec3::<T>::new(0.5, 0.7, 1.0) * t
+            }
+            Background::Color(color) => *color,
+        }
+    }
+}
+
 pub struct HittableScene<T> {
     pub hittable_list: Vec<Arc<dyn Hittable<T> + Send + Sync>>,
+    pub background: Background<T>,
+    /// Emitters that are also sampled directly, these are part of `hittable_list` as well
+    pub lights: LightList<T>,
 }
 
 impl<T> HittableScene<T>
     where
-        T: FloatType<T>,
+        T: FloatType<T> + Send + Sync,
 {
     pub fn new() -> HittableScene<T> {
         return HittableScene {
             hittable_list: Vec::new(),
+            background: Background::Sky,
+            lights: LightList::new(),
         };
     }
 
     pub fn add_hittable(&mut self, hittable: Arc<dyn Hittable<T> + Send + Sync>) {
         self.hittable_list.push(hittable);
     }
+
+    /// Adds a hittable with an emissive material, which will also be sampled as a light
+    pub fn add_light(&mut self, light: Arc<dyn Hittable<T> + Send + Sync>) {
+        self.lights.add_light(light.clone());
+        self.hittable_list.push(light);
+    }
+}
+
+impl<T> HittableScene<T>
+    where
+        T: FloatType<T> + Debug + Send + Sync + 'static,
+{
+    /// Adds every triangle of the mesh separately, so they are part of the scene BVH build.
+    /// Triangles of emissive meshes are added as lights.
+    pub fn add_mesh(&mut self, mesh: &Arc<MeshData<T>>) {
+        let emissive = mesh.material.is_emissive();
+        for triangle in MeshData::triangles(mesh) {
+            if emissive {
+                self.add_light(triangle);
+            } else {
+                self.add_hittable(triangle);
+            }
+        }
+    }
 }
 
 impl<T> Hittable<T> for HittableScene<T>
@@ -36,10 +90,7 @@ impl<T> Hittable<T> for HittableScene<T>
             if hittable.hit(r, t_min, closest_so_far, &mut temp_rec) {
                 hit_anything = true;
                 closest_so_far = temp_rec.t;
-                record.position = temp_rec.position;
-                record.normal = temp_rec.normal;
-                record.t = temp_rec.t;
-                record.material = temp_rec.material.clone();
+                record.update(temp_rec.clone());
             }
         }
         hit_anything
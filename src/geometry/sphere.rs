@@ -5,7 +5,39 @@ use straal::{FloatType, Vec3};
 
 use crate::geometry::{AABB, HitRecord, Hittable};
 use crate::material::Material;
-use crate::math::Ray;
+use crate::math::{Onb, random_to_sphere, random_unit_vector, Ray};
+use crate::sampler::Sampler;
+
+/// Texture coordinates of the point with outward normal `n` on a sphere: `u` goes around the
+/// y axis starting at -x, `v` from the bottom pole to the top one
+pub fn sphere_uv<T>(n: &Vec3<T>) -> (T, T)
+    where
+        T: FloatType<T>,
+{
+    let pi = T::from(std::f64::consts::PI).unwrap();
+    let phi = (-n.z).atan2(n.x) + pi;
+    //Rounding can push the normal just past unit length
+    let theta = T::max(-T::one(), T::min(-n.y, T::one())).acos();
+    (phi / (pi + pi), theta / pi)
+}
+
+/// Directions in which `u` and `v` from `sphere_uv` increase at the point with outward normal
+/// `n`. The tangent is zero at the poles, where `u` is undefined.
+pub fn sphere_tangents<T>(n: &Vec3<T>) -> (Vec3<T>, Vec3<T>)
+    where
+        T: FloatType<T>,
+{
+    let tangent = Vec3::<T> {
+        x: n.z,
+        y: T::zero(),
+        z: -n.x,
+    };
+    if tangent.length_squared() < T::from(1e-12).unwrap() {
+        return (Vec3::zero(), Vec3::zero());
+    }
+    let tangent = tangent.normalized();
+    (tangent, n.cross(tangent))
+}
 
 pub struct Sphere<T> {
     pub center: Vec3<T>,
@@ -38,11 +70,22 @@ impl<T> Hittable<T> for Sphere<T>
         let discriminant = b * b - a * c;
         if discriminant > T::zero() {
             let sqrt_d = discriminant.sqrt();
+            //Half a turn of `v` spans half the circumference
+            let uv_density = T::one() / (T::from(std::f64::consts::PI).unwrap() * self.radius);
             let sol = (-b - sqrt_d) / a;
             if sol < t_max && sol > t_min {
                 record.t = sol;
                 record.position = r.point_at_parameter(sol);
                 record.normal = (record.position - self.center) / self.radius;
+                let (u, v) = sphere_uv(&record.normal);
+                let (tangent, bitangent) = sphere_tangents(&record.normal);
+                record.shading_normal = record.normal;
+                record.tangent = tangent;
+                record.bitangent = bitangent;
+                record.u = u;
+                record.v = v;
+                record.uv_density = uv_density;
+                record.front_face = Vec3::dot(r.direction, record.normal) < T::zero();
                 record.material = Arc::downgrade(&self.material);
                 return true;
             }
@@ -51,6 +94,15 @@ impl<T> Hittable<T> for Sphere<T>
                 record.t = sol;
                 record.position = r.point_at_parameter(sol);
                 record.normal = (record.position - self.center) / self.radius;
+                let (u, v) = sphere_uv(&record.normal);
+                let (tangent, bitangent) = sphere_tangents(&record.normal);
+                record.shading_normal = record.normal;
+                record.tangent = tangent;
+                record.bitangent = bitangent;
+                record.u = u;
+                record.v = v;
+                record.uv_density = uv_density;
+                record.front_face = Vec3::dot(r.direction, record.normal) < T::zero();
                 record.material = Arc::downgrade(&self.material);
                 return true;
             }
@@ -65,4 +117,37 @@ impl<T> Hittable<T> for Sphere<T>
             max: self.get_center() + rad_vec,
         })
     }
+
+    fn pdf_value(&self, origin: &Vec3<T>, direction: &Vec3<T>, time: T, t_min: T) -> T {
+        let ray = Ray {
+            origin: *origin,
+            direction: *direction,
+            time,
+        };
+        let mut record = HitRecord::<T>::default();
+        if !self.hit(&ray, t_min, T::infinity(), &mut record) {
+            return T::zero();
+        }
+
+        let distance_squared = (self.center - *origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            //From the inside every direction hits the sphere
+            return T::one() / T::from(4.0 * std::f64::consts::PI).unwrap();
+        }
+        let cos_theta_max = (T::one() - radius_squared / distance_squared).sqrt();
+        let solid_angle = T::from(2.0 * std::f64::consts::PI).unwrap() * (T::one() - cos_theta_max);
+        T::one() / solid_angle
+    }
+
+    /// Samples the cone of directions subtended by the sphere, rather than its surface, so no
+    /// samples are wasted on the side facing away from `origin`
+    fn random_direction(&self, origin: &Vec3<T>, _time: T, sampler: &mut dyn Sampler) -> Vec3<T> {
+        let direction = self.center - *origin;
+        let distance_squared = direction.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return random_unit_vector(sampler.get_2d());
+        }
+        Onb::from_w(&direction).local(&random_to_sphere(self.radius, distance_squared, sampler.get_2d()))
+    }
 }
\ No newline at end of file
@@ -0,0 +1,296 @@
+use std::fmt::Debug;
+use std::mem;
+use std::sync::Arc;
+
+use straal::{FloatType, Vec3};
+
+use crate::geometry::{AABB, HitRecord, Hittable, MeshData};
+use crate::math::Ray;
+use crate::sampler::Sampler;
+
+pub struct Triangle<T> {
+    pub mesh: Arc<MeshData<T>>,
+    pub index: usize,
+}
+
+impl<T> Triangle<T>
+    where
+        T: FloatType<T>,
+{
+    pub fn new(mesh: Arc<MeshData<T>>, index: usize) -> Triangle<T> {
+        Triangle { mesh, index }
+    }
+
+    pub fn get_vertices(&self) -> (Vec3<T>, Vec3<T>, Vec3<T>) {
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        (self.mesh.positions[i0], self.mesh.positions[i1], self.mesh.positions[i2])
+    }
+
+    pub fn get_geometric_normal(&self) -> Vec3<T> {
+        let (p0, p1, p2) = self.get_vertices();
+        (p1 - p0).cross(p2 - p0).normalized()
+    }
+}
+
+fn max_dimension<T>(v: Vec3<T>) -> usize where T: FloatType<T> {
+    let x = v.x.abs();
+    let y = v.y.abs();
+    let z = v.z.abs();
+    if x > y {
+        if x > z { 0 } else { 2 }
+    } else if y > z {
+        1
+    } else {
+        2
+    }
+}
+
+impl<T> Hittable<T> for Triangle<T>
+    where
+        T: FloatType<T> + Debug + Send + Sync,
+{
+    /// Watertight ray/triangle intersection (Woop, Benthin and Wald 2013). The triangle is
+    /// transformed into a ray-aligned space where the ray points down +z, so shared edges are
+    /// evaluated identically by both neighbouring triangles and rays can't slip between them.
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T, record: &mut HitRecord<T>) -> bool {
+        let direction = r.get_direction();
+        let origin = r.get_origin();
+
+        let kz = max_dimension(direction);
+        let mut kx = (kz + 1) % 3;
+        let mut ky = (kx + 1) % 3;
+        if direction[kz] < T::zero() {
+            mem::swap(&mut kx, &mut ky);
+        }
+
+        let sx = direction[kx] / direction[kz];
+        let sy = direction[ky] / direction[kz];
+        let sz = T::one() / direction[kz];
+
+        let (p0, p1, p2) = self.get_vertices();
+        let a = p0 - origin;
+        let b = p1 - origin;
+        let c = p2 - origin;
+
+        let ax = a[kx] - sx * a[kz];
+        let ay = a[ky] - sy * a[kz];
+        let bx = b[kx] - sx * b[kz];
+        let by = b[ky] - sy * b[kz];
+        let cx = c[kx] - sx * c[kz];
+        let cy = c[ky] - sy * c[kz];
+
+        let mut e0 = cx * by - cy * bx;
+        let mut e1 = ax * cy - ay * cx;
+        let mut e2 = bx * ay - by * ax;
+
+        //Edge functions that land exactly on zero are recomputed in double precision
+        if e0 == T::zero() || e1 == T::zero() || e2 == T::zero() {
+            let f = |x: T| -> f64 { num::cast(x).unwrap_or(0.0) };
+            e0 = T::from(f(cx) * f(by) - f(cy) * f(bx)).unwrap();
+            e1 = T::from(f(ax) * f(cy) - f(ay) * f(cx)).unwrap();
+            e2 = T::from(f(bx) * f(ay) - f(by) * f(ax)).unwrap();
+        }
+
+        if (e0 < T::zero() || e1 < T::zero() || e2 < T::zero())
+            && (e0 > T::zero() || e1 > T::zero() || e2 > T::zero()) {
+            return false;
+        }
+
+        let det = e0 + e1 + e2;
+        if det == T::zero() {
+            return false;
+        }
+
+        let az = sz * a[kz];
+        let bz = sz * b[kz];
+        let cz = sz * c[kz];
+        let inv_det = T::one() / det;
+        let t = (e0 * az + e1 * bz + e2 * cz) * inv_det;
+        if t <= t_min || t >= t_max {
+            return false;
+        }
+
+        let b0 = e0 * inv_det;
+        let b1 = e1 * inv_det;
+        let b2 = e2 * inv_det;
+
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        let mut normal = self.get_geometric_normal();
+        let shading_normal = if self.mesh.normals.is_empty() {
+            normal
+        } else {
+            let n = (self.mesh.normals[i0] * b0 + self.mesh.normals[i1] * b1 + self.mesh.normals[i2] * b2).normalized();
+            //The winding order doesn't have to agree with the vertex normals
+            if Vec3::dot(normal, n) < T::zero() {
+                normal = -normal;
+            }
+            n
+        };
+        //Texture coordinates cover the triangle at a constant density, the square root of the
+        //ratio between its area in texture space and in world space
+        let edge1 = p1 - p0;
+        let edge2 = p2 - p0;
+        let double_area = edge1.cross(edge2).length();
+        let (u, v, uv_double_area, tangent, bitangent) = if self.mesh.uvs.is_empty() {
+            (b1, b2, T::one(), edge1.normalized(), edge2.normalized())
+        } else {
+            let (u0, v0) = self.mesh.uvs[i0];
+            let (u1, v1) = self.mesh.uvs[i1];
+            let (u2, v2) = self.mesh.uvs[i2];
+            let (du1, dv1, du2, dv2) = (u1 - u0, v1 - v0, u2 - u0, v2 - v0);
+            let uv_determinant = du1 * dv2 - du2 * dv1;
+            //Solves the edges for the directions in which u and v increase
+            let (tangent, bitangent) = if uv_determinant.abs() > T::from(1e-12).unwrap() {
+                let inverse = T::one() / uv_determinant;
+                let tangent = (edge1 * dv2 - edge2 * dv1) * inverse;
+                let bitangent = (edge2 * du1 - edge1 * du2) * inverse;
+                (tangent.normalized(), bitangent.normalized())
+            } else {
+                (Vec3::zero(), Vec3::zero())
+            };
+            let u = u0 * b0 + u1 * b1 + u2 * b2;
+            let v = v0 * b0 + v1 * b1 + v2 * b2;
+            (u, v, uv_determinant.abs(), tangent, bitangent)
+        };
+
+        record.t = t;
+        record.position = p0 * b0 + p1 * b1 + p2 * b2;
+        record.normal = normal;
+        record.front_face = Vec3::dot(direction, normal) < T::zero();
+        record.shading_normal = shading_normal;
+        record.tangent = tangent;
+        record.bitangent = bitangent;
+        record.u = u;
+        record.v = v;
+        record.uv_density = if double_area > T::zero() { (uv_double_area / double_area).sqrt() } else { T::zero() };
+        record.material = Arc::downgrade(&self.mesh.material);
+        true
+    }
+
+    fn bounding_box(&self, _t0: T, _t1: T) -> Option<AABB<T>> {
+        let (p0, p1, p2) = self.get_vertices();
+        //Axis aligned triangles would otherwise get a box with zero thickness, which AABB::hit rejects
+        let padding = Vec3::all(T::from(0.0001).unwrap());
+        let min = Vec3::<T> {
+            x: T::min(p0.x, T::min(p1.x, p2.x)),
+            y: T::min(p0.y, T::min(p1.y, p2.y)),
+            z: T::min(p0.z, T::min(p1.z, p2.z)),
+        };
+        let max = Vec3::<T> {
+            x: T::max(p0.x, T::max(p1.x, p2.x)),
+            y: T::max(p0.y, T::max(p1.y, p2.y)),
+            z: T::max(p0.z, T::max(p1.z, p2.z)),
+        };
+        Some(AABB {
+            min: min - padding,
+            max: max + padding,
+        })
+    }
+
+    fn pdf_value(&self, origin: &Vec3<T>, direction: &Vec3<T>, time: T, t_min: T) -> T {
+        let ray = Ray {
+            origin: *origin,
+            direction: *direction,
+            time,
+        };
+        let mut record = HitRecord::<T>::default();
+        if !self.hit(&ray, t_min, T::infinity(), &mut record) {
+            return T::zero();
+        }
+
+        let (p0, p1, p2) = self.get_vertices();
+        let cross = (p1 - p0).cross(p2 - p0);
+        let double_area = cross.length();
+        let direction_length_squared = direction.length_squared();
+        let cosine = (Vec3::dot(*direction, cross) / (double_area * direction_length_squared.sqrt())).abs();
+        if cosine <= T::zero() {
+            return T::zero();
+        }
+        //Converts the uniform density over the area into a density over solid angle
+        let distance_squared = record.t * record.t * direction_length_squared;
+        distance_squared / (cosine * double_area / T::from(2).unwrap())
+    }
+
+    /// Direction towards a uniformly distributed point on the triangle
+    fn random_direction(&self, origin: &Vec3<T>, _time: T, sampler: &mut dyn Sampler) -> Vec3<T> {
+        let (u1, u2) = sampler.get_2d();
+        let su = T::from(u1).unwrap().sqrt();
+        let r2 = T::from(u2).unwrap();
+        let b0 = T::one() - su;
+        let b1 = r2 * su;
+        let b2 = T::one() - b0 - b1;
+        let (p0, p1, p2) = self.get_vertices();
+        p0 * b0 + p1 * b1 + p2 * b2 - *origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::material::DummyMaterial;
+
+    use super::*;
+
+    fn mesh(positions: Vec<Vec3<f64>>, indices: Vec<[usize; 3]>) -> Arc<MeshData<f64>> {
+        Arc::new(MeshData::new(positions, vec![], vec![], indices, Arc::new(DummyMaterial)))
+    }
+
+    fn unit_triangle() -> Triangle<f64> {
+        let positions = vec![Vec3::new(0, 0, 0), Vec3::new(1, 0, 0), Vec3::new(0, 1, 0)];
+        Triangle::new(mesh(positions, vec![[0, 1, 2]]), 0)
+    }
+
+    fn ray(origin: Vec3<f64>, direction: Vec3<f64>) -> Ray<f64> {
+        Ray { origin, direction, time: 0.0 }
+    }
+
+    fn shoot(triangle: &Triangle<f64>, r: &Ray<f64>) -> Option<HitRecord<f64>> {
+        let mut record = HitRecord::default();
+        if triangle.hit(r, 0.001, f64::MAX, &mut record) { Some(record) } else { None }
+    }
+
+    #[test]
+    fn hit_reports_distance_barycentrics_and_face() {
+        let r = ray(Vec3::new(0.25, 0.5, 2.0), Vec3::new(0, 0, -2));
+        let record = shoot(&unit_triangle(), &r).unwrap();
+        assert!((record.t - 1.0).abs() < 1e-12);
+        assert!((record.u - 0.25).abs() < 1e-12);
+        assert!((record.v - 0.5).abs() < 1e-12);
+        assert!((record.position - Vec3::new(0.25, 0.5, 0.0)).length() < 1e-12);
+        assert!((record.normal - Vec3::new(0, 0, 1)).length() < 1e-12);
+        assert!(record.front_face);
+    }
+
+    #[test]
+    fn hit_from_behind_is_a_back_face() {
+        let r = ray(Vec3::new(0.25, 0.25, -1.0), Vec3::new(0, 0, 1));
+        let record = shoot(&unit_triangle(), &r).unwrap();
+        assert!(!record.front_face);
+        assert!((record.facing_normal() - Vec3::new(0, 0, -1)).length() < 1e-12);
+    }
+
+    #[test]
+    fn misses_outside_the_triangle_and_outside_the_ray_interval() {
+        let triangle = unit_triangle();
+        assert!(shoot(&triangle, &ray(Vec3::new(0.75, 0.75, 1.0), Vec3::new(0, 0, -1))).is_none());
+        assert!(shoot(&triangle, &ray(Vec3::new(-0.1, 0.5, 1.0), Vec3::new(0, 0, -1))).is_none());
+        //Pointing away from the triangle
+        assert!(shoot(&triangle, &ray(Vec3::new(0.25, 0.25, 1.0), Vec3::new(0, 0, 1))).is_none());
+        //Parallel to the plane
+        assert!(shoot(&triangle, &ray(Vec3::new(-1.0, 0.25, 0.0), Vec3::new(1, 0, 0))).is_none());
+    }
+
+    #[test]
+    fn shared_edges_and_vertices_are_not_missed() {
+        //Two triangles forming a unit square, split along the diagonal from (1, 0) to (0, 1)
+        let positions = vec![Vec3::new(0, 0, 0), Vec3::new(1, 0, 0), Vec3::new(0, 1, 0), Vec3::new(1, 1, 0)];
+        let data = mesh(positions, vec![[0, 1, 2], [1, 3, 2]]);
+        let triangles = [Triangle::new(data.clone(), 0), Triangle::new(data, 1)];
+        for &(x, y) in &[(0.5, 0.5), (0.3, 0.7), (0.9, 0.1), (1.0, 0.0), (0.0, 1.0)] {
+            let r = ray(Vec3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0));
+            let hits = triangles.iter().filter(|triangle| shoot(triangle, &r).is_some()).count();
+            assert!(hits >= 1, "ray through ({}, {}) slipped between the triangles", x, y);
+        }
+        let r = ray(Vec3::new(0.0, 0.5, 1.0), Vec3::new(0, 0, -1));
+        assert!(shoot(&triangles[0], &r).is_some());
+    }
+}
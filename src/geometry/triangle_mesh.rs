@@ -0,0 +1,87 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use straal::{FloatType, Vec3};
+
+use crate::error::Result;
+use crate::geometry::{AABB, BvhNode, HitRecord, Hittable, Triangle};
+use crate::material::Material;
+use crate::math::Ray;
+
+/// Vertex buffers shared by every triangle of a mesh. Normals and uvs are either empty or indexed
+/// by the same vertex indices as the positions.
+pub struct MeshData<T> {
+    pub positions: Vec<Vec3<T>>,
+    pub normals: Vec<Vec3<T>>,
+    pub uvs: Vec<(T, T)>,
+    pub indices: Vec<[usize; 3]>,
+    pub material: Arc<dyn Material<T>>,
+}
+
+impl<T> MeshData<T>
+    where
+        T: FloatType<T> + Debug + Send + Sync + 'static,
+{
+    pub fn new(
+        positions: Vec<Vec3<T>>,
+        normals: Vec<Vec3<T>>,
+        uvs: Vec<(T, T)>,
+        indices: Vec<[usize; 3]>,
+        material: Arc<dyn Material<T>>,
+    ) -> MeshData<T> {
+        MeshData {
+            positions,
+            normals,
+            uvs,
+            indices,
+            material,
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// One hittable per triangle, all pointing at the same shared buffers, so they can be added
+    /// to a scene and end up in the scene wide BVH.
+    pub fn triangles(mesh: &Arc<MeshData<T>>) -> Vec<Arc<dyn Hittable<T> + Send + Sync>> {
+        (0..mesh.triangle_count())
+            .map(|i| Arc::new(Triangle::new(mesh.clone(), i)) as Arc<dyn Hittable<T> + Send + Sync>)
+            .collect()
+    }
+}
+
+/// A mesh with its own BVH over its triangles, for when the mesh should behave as a single object.
+pub struct TriangleMesh<T> where T: Sync + Send {
+    pub data: Arc<MeshData<T>>,
+    pub bvh: BvhNode<T>,
+}
+
+impl<T> TriangleMesh<T>
+    where
+        T: FloatType<T> + Debug + Send + Sync + 'static,
+{
+    pub fn new(data: MeshData<T>, time0: T, time1: T) -> Result<TriangleMesh<T>> {
+        let data = Arc::new(data);
+        let triangles = MeshData::triangles(&data);
+        let bvh = BvhNode::new(&triangles[..], time0, time1)?;
+        Ok(TriangleMesh { data, bvh })
+    }
+
+    pub fn triangles(&self) -> Vec<Arc<dyn Hittable<T> + Send + Sync>> {
+        MeshData::triangles(&self.data)
+    }
+}
+
+impl<T> Hittable<T> for TriangleMesh<T>
+    where
+        T: FloatType<T> + Debug + Send + Sync + 'static,
+{
+    fn hit(&self, r: &Ray<T>, t_min: T, t_max: T, record: &mut HitRecord<T>) -> bool {
+        self.bvh.hit(r, t_min, t_max, record)
+    }
+
+    fn bounding_box(&self, t0: T, t1: T) -> Option<AABB<T>> {
+        self.bvh.bounding_box(t0, t1)
+    }
+}
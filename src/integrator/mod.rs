@@ -0,0 +1,26 @@
+use straal::{FloatType, Vec3};
+
+pub mod path_tracer;
+
+use crate::error::Result;
+use crate::geometry::{Hittable, HittableScene};
+use crate::math::Ray;
+use crate::sampler::Sampler;
+pub use path_tracer::*;
+
+/// Turns camera rays into radiance estimates
+pub trait Integrator<T>: Send + Sync
+    where
+        T: FloatType<T> + Send + Sync,
+{
+    /// Estimate of the light arriving along `r`, traced against `accelerator`, which holds the
+    /// same geometry as `scene`. All random decisions are taken from `sampler`,
+    /// starting at dimension `CAMERA_DIMENSIONS`.
+    fn radiance(
+        &self,
+        r: &Ray<T>,
+        scene: &HittableScene<T>,
+        accelerator: &dyn Hittable<T>,
+        sampler: &mut dyn Sampler,
+    ) -> Result<Vec3<T>>;
+}
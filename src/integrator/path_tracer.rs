@@ -0,0 +1,193 @@
+use straal::{FloatType, Vec3};
+
+use crate::error::{Error, Result};
+use crate::geometry::{HitRecord, Hittable, HittableScene, LightList};
+use crate::integrator::Integrator;
+use crate::io::RenderSettings;
+use crate::material::Material;
+use crate::math::Ray;
+use crate::sampler::{Sampler, CAMERA_DIMENSIONS};
+
+/// Sample dimensions used per bounce: picking a light and a direction towards it (3), sampling
+/// the material (up to 2) and Russian roulette (1). Every bounce starts at a fixed offset, so a
+/// decision uses the same dimension in every sample no matter how many values earlier ones took.
+const BOUNCE_DIMENSIONS: u32 = 6;
+const MATERIAL_DIMENSION: u32 = 3;
+const ROULETTE_DIMENSION: u32 = 5;
+
+/// Unidirectional path tracer. Paths are extended in a loop while keeping track of their
+/// throughput, so the path length is only limited by `max_depth` and Russian roulette.
+pub struct PathTracer<T> {
+    pub max_depth: u32,
+    /// Number of bounces before paths start being terminated at random
+    pub roulette_depth: u32,
+    /// Minimal distance along a ray for a hit to count, keeps rays from hitting the surface they left
+    pub ray_epsilon: T,
+    /// Sample lights directly at non-specular bounces, combined with BSDF sampling through MIS
+    pub light_sampling: bool,
+    /// Angle between the rays through neighbouring pixels, the footprint of a pixel grows by this
+    /// much per unit of distance travelled. Zero disables texture filtering.
+    pub pixel_spread: T,
+}
+
+impl<T> PathTracer<T>
+    where
+        T: FloatType<T> + Send + Sync,
+{
+    pub fn new(max_depth: u32, roulette_depth: u32, ray_epsilon: T, light_sampling: bool) -> PathTracer<T> {
+        PathTracer {
+            max_depth,
+            roulette_depth,
+            ray_epsilon,
+            light_sampling,
+            pixel_spread: T::zero(),
+        }
+    }
+
+    pub fn with_pixel_spread(mut self, pixel_spread: T) -> PathTracer<T> {
+        self.pixel_spread = pixel_spread;
+        self
+    }
+
+    pub fn from_settings(settings: &RenderSettings) -> PathTracer<T> {
+        PathTracer::new(
+            settings.max_depth,
+            settings.roulette_depth,
+            T::from(settings.ray_epsilon).unwrap(),
+            settings.light_sampling,
+        )
+    }
+
+    /// Estimates the light arriving at the hit point from the scene's lights through a single
+    /// shadow ray towards a randomly chosen light, weighted against the chance that the material
+    /// would have sampled the same direction
+    fn sample_direct_light(
+        &self,
+        r: &Ray<T>,
+        rec: &HitRecord<T>,
+        material: &dyn Material<T>,
+        accelerator: &dyn Hittable<T>,
+        lights: &LightList<T>,
+        sampler: &mut dyn Sampler,
+    ) -> Result<Vec3<T>> {
+        let direction = lights.random_direction(&rec.position, r.time, sampler);
+        let light_pdf = lights.pdf_value(&rec.position, &direction, r.time, self.ray_epsilon);
+        if light_pdf <= T::zero() {
+            return Ok(Vec3::<T>::zero());
+        }
+        let bsdf = material.eval(r, rec, &direction);
+        if bsdf.x <= T::zero() && bsdf.y <= T::zero() && bsdf.z <= T::zero() {
+            return Ok(Vec3::<T>::zero());
+        }
+
+        //Whatever the shadow ray hits first is what's visible in that direction, occluders included
+        let shadow_ray = Ray {
+            origin: rec.position,
+            direction,
+            time: r.time,
+        };
+        let mut shadow_rec = HitRecord::<T>::default();
+        if !accelerator.hit(&shadow_ray, self.ray_epsilon, T::infinity(), &mut shadow_rec) {
+            return Ok(Vec3::<T>::zero());
+        }
+        let light_material = shadow_rec.material.upgrade().ok_or(Error::MissingMaterial)?;
+        let weight = power_heuristic(light_pdf, material.pdf(r, rec, &direction));
+        Ok(bsdf * light_material.emitted(&shadow_ray, &shadow_rec) * (weight / light_pdf))
+    }
+}
+
+/// Power heuristic weight for a sample drawn with density `pdf` while `other_pdf` could have
+/// produced the same direction
+pub fn power_heuristic<T>(pdf: T, other_pdf: T) -> T
+    where
+        T: FloatType<T>,
+{
+    let pdf_squared = pdf * pdf;
+    let other_squared = other_pdf * other_pdf;
+    if pdf_squared + other_squared <= T::zero() {
+        return T::zero();
+    }
+    pdf_squared / (pdf_squared + other_squared)
+}
+
+impl<T> Integrator<T> for PathTracer<T>
+    where
+        T: FloatType<T> + Send + Sync,
+{
+    /// With light sampling, every non-specular vertex samples the lights directly and the light
+    /// found by the continuation ray is weighted by `emission_weight`, so both strategies are
+    /// combined with multiple importance sampling.
+    fn radiance(
+        &self,
+        r: &Ray<T>,
+        scene: &HittableScene<T>,
+        accelerator: &dyn Hittable<T>,
+        sampler: &mut dyn Sampler,
+    ) -> Result<Vec3<T>> {
+        let lights = &scene.lights;
+        let sample_lights = self.light_sampling && !lights.is_empty();
+
+        let mut radiance = Vec3::<T>::zero();
+        let mut throughput = Vec3::<T>::one();
+        let mut emission_weight = T::one();
+        let mut ray = Ray {
+            origin: r.origin,
+            direction: r.direction,
+            time: r.time,
+        };
+        let mut depth = 0;
+        //The pixel footprint is approximated by a cone around the path, unfolded at every bounce
+        let mut path_length = T::zero();
+
+        loop {
+            let mut rec = HitRecord::<T>::default();
+            if !accelerator.hit(&ray, self.ray_epsilon, T::infinity(), &mut rec) {
+                radiance += throughput * scene.background.sample(&ray);
+                break;
+            }
+            path_length += rec.t * ray.direction.length();
+            rec.footprint = self.pixel_spread * path_length;
+
+            let material = rec.material.upgrade().ok_or(Error::MissingMaterial)?;
+            material.perturb_normal(&mut rec);
+            radiance += throughput * material.emitted(&ray, &rec) * emission_weight;
+            if depth >= self.max_depth {
+                break;
+            }
+            let bounce_dimension = CAMERA_DIMENSIONS + depth * BOUNCE_DIMENSIONS;
+            sampler.set_dimension(bounce_dimension + MATERIAL_DIMENSION);
+            let scatter = match material.scatter(&ray, &rec, sampler) {
+                Some(scatter) => scatter,
+                None => break,
+            };
+
+            if sample_lights && !scatter.is_specular {
+                sampler.set_dimension(bounce_dimension);
+                let direct = self.sample_direct_light(&ray, &rec, material.as_ref(), accelerator, lights, sampler)?;
+                radiance += throughput * direct;
+                let scattered = &scatter.scattered;
+                let light_pdf = lights.pdf_value(&scattered.origin, &scattered.direction, ray.time, self.ray_epsilon);
+                emission_weight = power_heuristic(scatter.pdf, light_pdf);
+            } else {
+                emission_weight = T::one();
+            }
+
+            throughput = throughput * scatter.attenuation;
+            depth += 1;
+
+            //Russian roulette, paths that can't contribute much survive less often but count for more
+            if depth > self.roulette_depth {
+                let max_component = T::max(throughput.x, T::max(throughput.y, throughput.z));
+                let survival = T::max(T::min(max_component, T::one()), T::from(0.05).unwrap());
+                sampler.set_dimension(bounce_dimension + ROULETTE_DIMENSION);
+                if max_component <= T::zero() || T::from(sampler.get_1d()).unwrap() >= survival {
+                    break;
+                }
+                throughput = throughput / survival;
+            }
+
+            ray = scatter.scattered;
+        }
+        Ok(radiance)
+    }
+}
@@ -0,0 +1,179 @@
+//! Checkpoints of progressive renders: the accumulated samples of every pixel plus what's
+//! needed to continue where the render left off.
+//! All values are little-endian. After the magic line come the settings hash, the seed and the
+//! film's area (x, y, width, height) as u64, then for every pixel, top row first, the radiance
+//! sum as three f64, the sample count as u32 and the luminance mean and squared difference sum
+//! as f64.
+
+use std::fs;
+use std::path::Path;
+
+use straal::{FloatType, Vec3};
+
+use crate::error::{Error, Result};
+use crate::film::{Film, PixelStats};
+use crate::tiles::Rect;
+
+const MAGIC: &[u8] = b"straaljager checkpoint 2\n";
+const HEADER_SIZE: usize = 48;
+const PIXEL_SIZE: usize = 3 * 8 + 4 + 2 * 8;
+
+pub struct Checkpoint<T> {
+    /// `RenderSettings::fingerprint` of the render, resuming with another scene or other settings
+    /// would mix samples of different images
+    pub settings_hash: u64,
+    /// The sampler seed, samples continue at each pixel's count so the seed is all the random
+    /// state there is
+    pub seed: u64,
+    pub film: Film<T>,
+}
+
+pub fn encode_checkpoint<T>(checkpoint: &Checkpoint<T>) -> Vec<u8>
+    where
+        T: FloatType<T>,
+{
+    let film = &checkpoint.film;
+    let mut output = Vec::with_capacity(MAGIC.len() + HEADER_SIZE + film.pixels.len() * PIXEL_SIZE);
+    output.extend_from_slice(MAGIC);
+    output.extend_from_slice(&checkpoint.settings_hash.to_le_bytes());
+    output.extend_from_slice(&checkpoint.seed.to_le_bytes());
+    for value in &[film.area.x, film.area.y, film.area.width, film.area.height] {
+        output.extend_from_slice(&(*value as u64).to_le_bytes());
+    }
+    for pixel in &film.pixels {
+        for c in &[pixel.sum.x, pixel.sum.y, pixel.sum.z] {
+            let value: f64 = num::cast(*c).unwrap_or(0.0);
+            output.extend_from_slice(&value.to_le_bytes());
+        }
+        output.extend_from_slice(&pixel.count.to_le_bytes());
+        let (mean, m2) = pixel.luminance_moments();
+        output.extend_from_slice(&mean.to_le_bytes());
+        output.extend_from_slice(&m2.to_le_bytes());
+    }
+    output
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    let mut raw = [0u8; 8];
+    raw.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(raw)
+}
+
+fn read_f64(bytes: &[u8], offset: usize) -> f64 {
+    f64::from_bits(read_u64(bytes, offset))
+}
+
+pub fn decode_checkpoint<T>(bytes: &[u8]) -> std::result::Result<Checkpoint<T>, String>
+    where
+        T: FloatType<T>,
+{
+    if !bytes.starts_with(MAGIC) {
+        return Err("not a checkpoint file".to_string());
+    }
+    let data = &bytes[MAGIC.len()..];
+    if data.len() < HEADER_SIZE {
+        return Err("the header is cut off".to_string());
+    }
+    let settings_hash = read_u64(data, 0);
+    let seed = read_u64(data, 8);
+    let area = Rect {
+        x: read_u64(data, 16) as usize,
+        y: read_u64(data, 24) as usize,
+        width: read_u64(data, 32) as usize,
+        height: read_u64(data, 40) as usize,
+    };
+    let (width, height) = (area.width, area.height);
+    let expected = width
+        .checked_mul(height)
+        .and_then(|count| count.checked_mul(PIXEL_SIZE))
+        .and_then(|size| size.checked_add(HEADER_SIZE));
+    if expected != Some(data.len()) {
+        return Err(format!("the size doesn't match an image of {}x{} pixels", width, height));
+    }
+
+    let pixels = data[HEADER_SIZE..]
+        .chunks(PIXEL_SIZE)
+        .map(|pixel| {
+            let sum = Vec3::<T>::new(read_f64(pixel, 0), read_f64(pixel, 8), read_f64(pixel, 16));
+            let mut count = [0u8; 4];
+            count.copy_from_slice(&pixel[24..28]);
+            PixelStats::from_parts(sum, u32::from_le_bytes(count), read_f64(pixel, 28), read_f64(pixel, 36))
+        })
+        .collect();
+    Ok(Checkpoint {
+        settings_hash,
+        seed,
+        film: Film { area, pixels },
+    })
+}
+
+/// Writes to a temporary file first, so a render killed while saving keeps its previous checkpoint
+pub fn write_checkpoint<T>(checkpoint: &Checkpoint<T>, file_path: &Path) -> Result<()>
+    where
+        T: FloatType<T>,
+{
+    let temporary_path = file_path.with_extension("tmp");
+    fs::write(&temporary_path, encode_checkpoint(checkpoint)).map_err(|e| Error::io(&temporary_path, e))?;
+    fs::rename(&temporary_path, file_path).map_err(|e| Error::io(file_path, e))
+}
+
+pub fn read_checkpoint<T>(file_path: &Path) -> Result<Checkpoint<T>>
+    where
+        T: FloatType<T>,
+{
+    let bytes = fs::read(file_path).map_err(|e| Error::io(file_path, e))?;
+    decode_checkpoint(&bytes).map_err(|message| Error::Checkpoint {
+        path: file_path.to_path_buf(),
+        message,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkpoint() -> Checkpoint<f32> {
+        let mut film = Film::new(Rect {
+            x: 5,
+            y: 1,
+            width: 3,
+            height: 2,
+        });
+        for (i, pixel) in film.pixels.iter_mut().enumerate() {
+            for s in 0..=i {
+                pixel.add(&Vec3::new(0.25 * s as f32, 1.5, i as f32));
+            }
+        }
+        Checkpoint {
+            settings_hash: 0x0123_4567_89ab_cdef,
+            seed: 42,
+            film,
+        }
+    }
+
+    #[test]
+    fn decoding_gives_back_the_encoded_checkpoint() {
+        let original = checkpoint();
+        let decoded = decode_checkpoint::<f32>(&encode_checkpoint(&original)).unwrap();
+        assert_eq!(decoded.settings_hash, original.settings_hash);
+        assert_eq!(decoded.seed, original.seed);
+        assert_eq!(decoded.film.area, original.film.area);
+        for (a, b) in decoded.film.pixels.iter().zip(&original.film.pixels) {
+            assert_eq!((a.sum.x, a.sum.y, a.sum.z), (b.sum.x, b.sum.y, b.sum.z));
+            assert_eq!(a.count, b.count);
+            assert_eq!(a.luminance_moments(), b.luminance_moments());
+        }
+    }
+
+    #[test]
+    fn truncated_and_foreign_files_are_rejected() {
+        let bytes = encode_checkpoint(&checkpoint());
+        for length in &[0, MAGIC.len() - 1, MAGIC.len() + HEADER_SIZE - 1, bytes.len() - 1] {
+            assert!(decode_checkpoint::<f32>(&bytes[..*length]).is_err(), "{} bytes", length);
+        }
+        let mut extended = bytes.clone();
+        extended.push(0);
+        assert!(decode_checkpoint::<f32>(&extended).is_err());
+        assert!(decode_checkpoint::<f32>(b"P6\n3 2\n255\n").is_err());
+    }
+}
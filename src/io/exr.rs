@@ -0,0 +1,151 @@
+use std::fs;
+use std::path::Path;
+
+use straal::{FloatType, Vec3};
+
+use crate::error::{Error, Result};
+
+const EXR_MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+
+/// Storage type of the channels in an OpenEXR file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExrPixelType {
+    Half,
+    Float,
+}
+
+impl ExrPixelType {
+    fn id(self) -> i32 {
+        match self {
+            ExrPixelType::Half => 1,
+            ExrPixelType::Float => 2,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ExrPixelType::Half => 2,
+            ExrPixelType::Float => 4,
+        }
+    }
+}
+
+/// Rounds to the nearest 16-bit float, overflowing to infinity
+pub fn f32_to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        //Infinity stays infinity, NaN stays NaN
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if half_exponent <= 0 {
+        //Too small for a normal half, becomes a subnormal or zero
+        if half_exponent < -10 {
+            return sign;
+        }
+        let full_mantissa = mantissa | 0x80_0000;
+        let shift = (14 - half_exponent) as u32;
+        let half = full_mantissa >> shift;
+        let remainder = full_mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round_up = remainder > halfway || (remainder == halfway && half & 1 == 1);
+        return sign | (half + round_up as u32) as u16;
+    }
+
+    let half = ((half_exponent as u32) << 10) | (mantissa >> 13);
+    let remainder = mantissa & 0x1fff;
+    //Rounding up may carry into the exponent, which is exactly what should happen
+    let round_up = remainder > 0x1000 || (remainder == 0x1000 && half & 1 == 1);
+    sign | (half + round_up as u32) as u16
+}
+
+fn write_attribute(output: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    output.extend_from_slice(name.as_bytes());
+    output.push(0);
+    output.extend_from_slice(kind.as_bytes());
+    output.push(0);
+    output.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    output.extend_from_slice(value);
+}
+
+/// Encodes a linear framebuffer, top row first, as an uncompressed scanline OpenEXR image
+pub fn encode_exr<T>(pixels: &[Vec3<T>], width: usize, height: usize, pixel_type: ExrPixelType) -> Vec<u8>
+    where
+        T: FloatType<T>,
+{
+    //Channels have to be listed, and stored, in alphabetical order
+    let channel_names = ["B", "G", "R"];
+    let mut channels = Vec::new();
+    for name in &channel_names {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&pixel_type.id().to_le_bytes());
+        //Not perceptually linear, three reserved bytes, no subsampling
+        channels.extend_from_slice(&[0, 0, 0, 0]);
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+
+    let mut window = Vec::with_capacity(16);
+    for v in &[0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&v.to_le_bytes());
+    }
+
+    let mut output = EXR_MAGIC.to_vec();
+    //Version 2, single part scanline file
+    output.extend_from_slice(&2u32.to_le_bytes());
+    write_attribute(&mut output, "channels", "chlist", &channels);
+    write_attribute(&mut output, "compression", "compression", &[0]);
+    write_attribute(&mut output, "dataWindow", "box2i", &window);
+    write_attribute(&mut output, "displayWindow", "box2i", &window);
+    write_attribute(&mut output, "lineOrder", "lineOrder", &[0]);
+    write_attribute(&mut output, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+    write_attribute(&mut output, "screenWindowCenter", "v2f", &[0; 8]);
+    write_attribute(&mut output, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    output.push(0);
+
+    //Without compression every chunk holds a single scanline
+    let line_size = width * channel_names.len() * pixel_type.size();
+    let chunk_size = 8 + line_size;
+    let table_end = output.len() + height * 8;
+    for y in 0..height {
+        output.extend_from_slice(&((table_end + y * chunk_size) as u64).to_le_bytes());
+    }
+
+    for y in 0..height {
+        output.extend_from_slice(&(y as i32).to_le_bytes());
+        output.extend_from_slice(&(line_size as i32).to_le_bytes());
+        let row = &pixels[y * width..(y + 1) * width];
+        for channel in &[2, 1, 0] {
+            for pixel in row {
+                let value: f32 = num::cast(pixel[*channel]).unwrap_or(0.0);
+                match pixel_type {
+                    ExrPixelType::Half => output.extend_from_slice(&f32_to_half(value).to_le_bytes()),
+                    ExrPixelType::Float => output.extend_from_slice(&value.to_le_bytes()),
+                }
+            }
+        }
+    }
+    output
+}
+
+pub fn write_exr_to_path<T>(
+    pixels: &[Vec3<T>],
+    width: usize,
+    height: usize,
+    file_path: &Path,
+    pixel_type: ExrPixelType,
+) -> Result<()>
+    where
+        T: FloatType<T>,
+{
+    fs::write(file_path, encode_exr(pixels, width, height, pixel_type)).map_err(|e| Error::io(file_path, e))
+}
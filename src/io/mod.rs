@@ -1,2 +1,18 @@
+pub mod checkpoint;
+pub mod exr;
+pub mod obj;
+pub mod pfm;
+pub mod png;
 pub mod ppm_file;
+pub mod radiance_hdr;
+pub mod scene_file;
+pub mod zlib;
+pub use checkpoint::*;
+pub use exr::*;
+pub use obj::*;
+pub use pfm::*;
+pub use png::*;
 pub use ppm_file::*;
+pub use radiance_hdr::*;
+pub use scene_file::*;
+pub use zlib::*;
@@ -0,0 +1,671 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fmt::Debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use straal::{FloatType, Vec3};
+
+use crate::geometry::{Hittable, MeshData};
+use crate::io::ImageError;
+use crate::material::*;
+use crate::textures::{ImageTexture, Texture, TextureCache, TextureFilter, WrapMode};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    /// A texture map referenced by a .mtl file that can't be read
+    Image(ImageError),
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io { path, error } => write!(f, "Could not read {}: {}", path.display(), error),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            ObjError::Image(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for ObjError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ObjError::Io { error, .. } => Some(error),
+            ObjError::Parse { .. } => None,
+            ObjError::Image(error) => Some(error),
+        }
+    }
+}
+
+impl From<ImageError> for ObjError {
+    fn from(error: ImageError) -> Self {
+        ObjError::Image(error)
+    }
+}
+
+/// Material description as found in a .mtl file, before it is turned into one of our materials
+#[derive(Clone, Debug)]
+pub struct MtlMaterial {
+    pub name: String,
+    pub diffuse: [f64; 3],
+    pub specular: [f64; 3],
+    pub emission: [f64; 3],
+    pub specular_exponent: f64,
+    pub refractive_index: f64,
+    pub dissolve: f64,
+    pub illum: u32,
+    /// `map_Kd`, resolved relative to the .mtl file
+    pub diffuse_map: Option<PathBuf>,
+    /// `map_Ke`, resolved relative to the .mtl file
+    pub emission_map: Option<PathBuf>,
+    /// `norm`, tangent space normals resolved relative to the .mtl file
+    pub normal_map: Option<PathBuf>,
+    /// `bump` or `map_Bump`, heights resolved relative to the .mtl file
+    pub bump_map: Option<PathBuf>,
+    /// The `-bm` option of the bump map, which scales the heights
+    pub bump_multiplier: f64,
+}
+
+impl MtlMaterial {
+    pub fn new(name: &str) -> MtlMaterial {
+        MtlMaterial {
+            name: name.to_string(),
+            diffuse: [0.8, 0.8, 0.8],
+            specular: [0.0, 0.0, 0.0],
+            emission: [0.0, 0.0, 0.0],
+            specular_exponent: 0.0,
+            refractive_index: 1.5,
+            dissolve: 1.0,
+            illum: 1,
+            diffuse_map: None,
+            emission_map: None,
+            normal_map: None,
+            bump_map: None,
+            bump_multiplier: 1.0,
+        }
+    }
+
+    /// Maps the mtl parameters onto the closest material we support:
+    /// - anything with a non-black `Ke` or a `map_Ke` becomes a diffuse light
+    /// - transparent materials (`d` < 1 or illum 4, 6, 7, 9) become dielectrics using `Ni`
+    /// - reflective materials (illum 3, 5, 8) become metals tinted by `Ks`, with the roughness
+    ///   derived from the specular exponent `Ns`
+    /// - everything else is lambertian using `Kd`
+    ///
+    /// Texture maps replace the colour they belong to rather than scaling it, and are loaded
+    /// through `cache`. A normal map, or otherwise a bump map, is added to anything but lights.
+    pub fn to_material<T>(&self, cache: &mut TextureCache<T>) -> Result<Arc<dyn Material<T>>, ObjError>
+        where
+            T: FloatType<T> + Send + Sync + 'static,
+    {
+        let to_vec = |c: [f64; 3]| Vec3::<T>::new(c[0], c[1], c[2]);
+        let mut load = |path: &PathBuf, srgb: bool| -> Result<Arc<dyn Texture<T>>, ObjError> {
+            let mip_map = cache.load(path, srgb)?;
+            Ok(Arc::new(ImageTexture::new(mip_map, WrapMode::Repeat, TextureFilter::Trilinear)))
+        };
+        let material: Arc<dyn Material<T>> = match self.illum {
+            _ if self.emission_map.is_some() => {
+                Arc::new(DiffuseLight::with_texture(load(self.emission_map.as_ref().unwrap(), true)?))
+            }
+            _ if self.emission.iter().any(|c| *c > 0.0) => Arc::new(DiffuseLight::create(&to_vec(self.emission))),
+            _ if self.dissolve < 1.0 => Arc::new(DielectricMaterial::create(T::from(self.refractive_index).unwrap())),
+            4 | 6 | 7 | 9 => Arc::new(DielectricMaterial::create(T::from(self.refractive_index).unwrap())),
+            3 | 5 | 8 => {
+                //Gives a lobe with `Ns` as its exponent
+                let roughness = (5.0 / (self.specular_exponent + 2.0)).sqrt();
+                Arc::new(MetalMaterial::create(&to_vec(self.specular), T::from(roughness).unwrap()))
+            }
+            _ => match &self.diffuse_map {
+                Some(path) => Arc::new(LambertianMaterial::with_texture(load(path, true)?)),
+                None => Arc::new(LambertianMaterial::create(&to_vec(self.diffuse))),
+            },
+        };
+        if material.is_emissive() {
+            return Ok(material);
+        }
+        let detail = match (&self.normal_map, &self.bump_map) {
+            (Some(path), _) => SurfaceDetail::NormalMap {
+                normals: load(path, false)?,
+                strength: T::one(),
+            },
+            (None, Some(path)) => SurfaceDetail::BumpMap {
+                height: load(path, false)?,
+                strength: T::from(self.bump_multiplier).unwrap(),
+            },
+            (None, None) => return Ok(material),
+        };
+        Ok(Arc::new(DetailedMaterial::new(material, detail)))
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|error| ObjError::Io {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+fn parse_error(path: &Path, line: usize, message: String) -> ObjError {
+    ObjError::Parse {
+        path: path.to_path_buf(),
+        line,
+        message,
+    }
+}
+
+/// Parses between `min` and `max` floats from the remaining arguments of a statement
+fn parse_floats(path: &Path, line: usize, keyword: &str, args: &[&str], min: usize, max: usize) -> Result<Vec<f64>, ObjError> {
+    if args.len() < min || args.len() > max {
+        let expected = if min == max {
+            format!("{}", min)
+        } else {
+            format!("{} to {}", min, max)
+        };
+        return Err(parse_error(path, line, format!("'{}' expects {} numbers, found {}", keyword, expected, args.len())));
+    }
+    args.iter()
+        .map(|a| {
+            a.parse::<f64>()
+                .map_err(|_| parse_error(path, line, format!("'{}' is not a valid number in '{}' statement", a, keyword)))
+        })
+        .collect()
+}
+
+/// Splits a line into its keyword and arguments, ignoring comments and empty lines
+fn tokenize(line: &str) -> Option<(&str, Vec<&str>)> {
+    let line = match line.find('#') {
+        Some(i) => &line[..i],
+        None => line,
+    };
+    let mut parts = line.split_whitespace();
+    let keyword = parts.next()?;
+    Some((keyword, parts.collect()))
+}
+
+pub fn load_mtl(path: &Path) -> Result<HashMap<String, MtlMaterial>, ObjError> {
+    let contents = read_file(path)?;
+    parse_mtl(path, &contents)
+}
+
+/// Parses the contents of an .mtl file, texture maps are relative to `path`'s directory
+fn parse_mtl(path: &Path, contents: &str) -> Result<HashMap<String, MtlMaterial>, ObjError> {
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut materials = HashMap::new();
+    let mut current: Option<MtlMaterial> = None;
+
+    for (i, line) in contents.lines().enumerate() {
+        let line_nr = i + 1;
+        let (keyword, args) = match tokenize(line) {
+            Some(t) => t,
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            if args.len() != 1 {
+                return Err(parse_error(path, line_nr, "'newmtl' expects a single material name".to_string()));
+            }
+            if let Some(m) = current.take() {
+                materials.insert(m.name.clone(), m);
+            }
+            current = Some(MtlMaterial::new(args[0]));
+            continue;
+        }
+
+        let material = match current.as_mut() {
+            Some(m) => m,
+            None => match keyword {
+                "Kd" | "Ks" | "Ke" | "Ns" | "Ni" | "d" | "Tr" | "illum" | "map_Kd" | "map_Ke" | "norm" | "bump"
+                | "map_Bump" => {
+                    return Err(parse_error(path, line_nr, format!("'{}' found before any 'newmtl'", keyword)));
+                }
+                _ => continue,
+            },
+        };
+
+        match keyword {
+            "Kd" | "Ks" | "Ke" => {
+                let c = parse_floats(path, line_nr, keyword, &args, 1, 3)?;
+                //A single value means a grey colour
+                let color = match c.len() {
+                    1 => [c[0], c[0], c[0]],
+                    3 => [c[0], c[1], c[2]],
+                    _ => {
+                        return Err(parse_error(path, line_nr, format!("'{}' expects 1 or 3 numbers, found 2", keyword)));
+                    }
+                };
+                match keyword {
+                    "Kd" => material.diffuse = color,
+                    "Ks" => material.specular = color,
+                    _ => material.emission = color,
+                }
+            }
+            "Ns" => material.specular_exponent = parse_floats(path, line_nr, keyword, &args, 1, 1)?[0],
+            "Ni" => material.refractive_index = parse_floats(path, line_nr, keyword, &args, 1, 1)?[0],
+            "d" => material.dissolve = parse_floats(path, line_nr, keyword, &args, 1, 1)?[0],
+            "Tr" => material.dissolve = 1.0 - parse_floats(path, line_nr, keyword, &args, 1, 1)?[0],
+            "illum" => {
+                if args.len() != 1 {
+                    return Err(parse_error(path, line_nr, "'illum' expects a single illumination model".to_string()));
+                }
+                material.illum = args[0].parse::<u32>().map_err(|_| {
+                    parse_error(path, line_nr, format!("'{}' is not a valid illumination model", args[0]))
+                })?;
+            }
+            "map_Kd" | "map_Ke" | "norm" | "bump" | "map_Bump" => {
+                //Options like -s or -clamp come before the file name, they are ignored except for
+                //the bump multiplier
+                let file = match args.last() {
+                    Some(file) => directory.join(file),
+                    None => return Err(parse_error(path, line_nr, format!("'{}' expects a file name", keyword))),
+                };
+                match keyword {
+                    "map_Kd" => material.diffuse_map = Some(file),
+                    "map_Ke" => material.emission_map = Some(file),
+                    "norm" => material.normal_map = Some(file),
+                    _ => {
+                        if let Some(i) = args.iter().position(|a| *a == "-bm") {
+                            let value = args.get(i + 1).and_then(|v| v.parse::<f64>().ok());
+                            material.bump_multiplier = value.filter(|v| v.is_finite()).ok_or_else(|| {
+                                parse_error(path, line_nr, "'-bm' expects a number".to_string())
+                            })?;
+                        }
+                        material.bump_map = Some(file);
+                    }
+                }
+            }
+            //The other texture maps and less common parameters are not supported (yet)
+            _ => {}
+        }
+    }
+
+    if let Some(m) = current.take() {
+        materials.insert(m.name.clone(), m);
+    }
+    Ok(materials)
+}
+
+/// Collects the triangles of one group/material combination. OBJ indexes positions, uvs and
+/// normals separately, so each unique combination becomes a single mesh vertex.
+struct MeshBuilder {
+    material: Option<String>,
+    vertex_lookup: HashMap<(usize, Option<usize>, Option<usize>), usize>,
+    positions: Vec<usize>,
+    uvs: Vec<Option<usize>>,
+    normals: Vec<Option<usize>>,
+    indices: Vec<[usize; 3]>,
+}
+
+impl MeshBuilder {
+    fn new(material: Option<String>) -> MeshBuilder {
+        MeshBuilder {
+            material,
+            vertex_lookup: HashMap::new(),
+            positions: Vec::new(),
+            uvs: Vec::new(),
+            normals: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    fn vertex(&mut self, key: (usize, Option<usize>, Option<usize>)) -> usize {
+        if let Some(index) = self.vertex_lookup.get(&key) {
+            return *index;
+        }
+        let index = self.positions.len();
+        self.positions.push(key.0);
+        self.uvs.push(key.1);
+        self.normals.push(key.2);
+        self.vertex_lookup.insert(key, index);
+        index
+    }
+
+    fn build<T>(
+        self,
+        positions: &[Vec3<T>],
+        uvs: &[(T, T)],
+        normals: &[Vec3<T>],
+        material: Arc<dyn Material<T>>,
+    ) -> MeshData<T>
+        where
+            T: FloatType<T> + Debug + Send + Sync + 'static,
+    {
+        let mesh_positions = self.positions.iter().map(|i| positions[*i]).collect();
+        //Attributes are only kept when every vertex of the mesh has them
+        let mesh_uvs = if self.uvs.iter().all(|i| i.is_some()) {
+            self.uvs.iter().map(|i| uvs[i.unwrap()]).collect()
+        } else {
+            Vec::new()
+        };
+        let mesh_normals = if self.normals.iter().all(|i| i.is_some()) {
+            self.normals.iter().map(|i| normals[i.unwrap()]).collect()
+        } else {
+            Vec::new()
+        };
+        MeshData::new(mesh_positions, mesh_normals, mesh_uvs, self.indices, material)
+    }
+}
+
+/// Resolves a 1-based (or negative, relative to the end) OBJ index into a 0-based one
+fn resolve_index(path: &Path, line: usize, index: &str, count: usize, kind: &str) -> Result<usize, ObjError> {
+    let value = index
+        .parse::<i64>()
+        .map_err(|_| parse_error(path, line, format!("'{}' is not a valid {} index", index, kind)))?;
+    let resolved = if value > 0 {
+        value - 1
+    } else {
+        count as i64 + value
+    };
+    if value == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(parse_error(
+            path,
+            line,
+            format!("{} index {} is out of range, {} {}s defined so far", kind, value, count, kind),
+        ));
+    }
+    Ok(resolved as usize)
+}
+
+pub struct ObjModel<T> {
+    pub meshes: Vec<(String, Arc<MeshData<T>>)>,
+    /// The .mtl files the materials were read from
+    pub mtl_files: Vec<PathBuf>,
+}
+
+impl<T> ObjModel<T>
+    where
+        T: FloatType<T> + Debug + Send + Sync + 'static,
+{
+    /// All triangles of all meshes, ready to be added to a scene
+    pub fn hittables(&self) -> Vec<Arc<dyn Hittable<T> + Send + Sync>> {
+        self.meshes
+            .iter()
+            .flat_map(|(_, mesh)| MeshData::triangles(mesh))
+            .collect()
+    }
+}
+
+/// Loads an .obj file and the .mtl files it references. Faces with more than three vertices are
+/// triangulated as a fan, and every group/material combination becomes its own mesh.
+/// Faces without a material use `default_material`, texture maps are loaded through `cache`.
+pub fn load_obj<T>(
+    path: &Path,
+    default_material: Arc<dyn Material<T>>,
+    cache: &mut TextureCache<T>,
+) -> Result<ObjModel<T>, ObjError>
+    where
+        T: FloatType<T> + Debug + Send + Sync + 'static,
+{
+    let contents = read_file(path)?;
+    parse_obj(path, &contents, default_material, cache, load_mtl)
+}
+
+/// Parses the contents of an .obj file. `path` is used in error messages and to find the .mtl
+/// files, which are read with `read_mtl`.
+fn parse_obj<T, F>(
+    path: &Path,
+    contents: &str,
+    default_material: Arc<dyn Material<T>>,
+    cache: &mut TextureCache<T>,
+    mut read_mtl: F,
+) -> Result<ObjModel<T>, ObjError>
+    where
+        T: FloatType<T> + Debug + Send + Sync + 'static,
+        F: FnMut(&Path) -> Result<HashMap<String, MtlMaterial>, ObjError>,
+{
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut positions: Vec<Vec3<T>> = Vec::new();
+    let mut uvs: Vec<(T, T)> = Vec::new();
+    let mut normals: Vec<Vec3<T>> = Vec::new();
+    let mut mtl_materials: HashMap<String, MtlMaterial> = HashMap::new();
+    let mut mtl_files = Vec::new();
+
+    let mut finished: Vec<(String, MeshBuilder)> = Vec::new();
+    let mut group = String::from("default");
+    let mut builder = MeshBuilder::new(None);
+
+    for (i, line) in contents.lines().enumerate() {
+        let line_nr = i + 1;
+        let (keyword, args) = match tokenize(line) {
+            Some(t) => t,
+            None => continue,
+        };
+
+        match keyword {
+            "v" => {
+                let p = parse_floats(path, line_nr, keyword, &args, 3, 4)?;
+                positions.push(Vec3::<T>::new(p[0], p[1], p[2]));
+            }
+            "vn" => {
+                let n = parse_floats(path, line_nr, keyword, &args, 3, 3)?;
+                normals.push(Vec3::<T>::new(n[0], n[1], n[2]).normalized());
+            }
+            "vt" => {
+                let t = parse_floats(path, line_nr, keyword, &args, 1, 3)?;
+                let v = if t.len() > 1 { t[1] } else { 0.0 };
+                uvs.push((T::from(t[0]).unwrap(), T::from(v).unwrap()));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(parse_error(path, line_nr, format!("A face needs at least 3 vertices, found {}", args.len())));
+                }
+                let mut face = Vec::with_capacity(args.len());
+                for vertex in &args {
+                    let parts: Vec<&str> = vertex.split('/').collect();
+                    if parts.len() > 3 || parts[0].is_empty() {
+                        return Err(parse_error(path, line_nr, format!("'{}' is not a valid face vertex", vertex)));
+                    }
+                    let p = resolve_index(path, line_nr, parts[0], positions.len(), "position")?;
+                    let t = match parts.get(1) {
+                        Some(t) if !t.is_empty() => Some(resolve_index(path, line_nr, t, uvs.len(), "texcoord")?),
+                        _ => None,
+                    };
+                    let n = match parts.get(2) {
+                        Some(n) if !n.is_empty() => Some(resolve_index(path, line_nr, n, normals.len(), "normal")?),
+                        _ => None,
+                    };
+                    face.push(builder.vertex((p, t, n)));
+                }
+                for k in 1..face.len() - 1 {
+                    builder.indices.push([face[0], face[k], face[k + 1]]);
+                }
+            }
+            "g" | "o" | "usemtl" => {
+                let material = if keyword == "usemtl" {
+                    if args.len() != 1 {
+                        return Err(parse_error(path, line_nr, "'usemtl' expects a single material name".to_string()));
+                    }
+                    if !mtl_materials.contains_key(args[0]) {
+                        return Err(parse_error(path, line_nr, format!("Unknown material '{}'", args[0])));
+                    }
+                    Some(args[0].to_string())
+                } else {
+                    builder.material.clone()
+                };
+                let next = MeshBuilder::new(material);
+                let previous = std::mem::replace(&mut builder, next);
+                if !previous.indices.is_empty() {
+                    finished.push((group.clone(), previous));
+                }
+                if keyword != "usemtl" && !args.is_empty() {
+                    group = args.join(" ");
+                }
+            }
+            "mtllib" => {
+                if args.is_empty() {
+                    return Err(parse_error(path, line_nr, "'mtllib' expects a file name".to_string()));
+                }
+                for lib in &args {
+                    let mtl_path = directory.join(lib);
+                    mtl_materials.extend(read_mtl(&mtl_path)?);
+                    mtl_files.push(mtl_path);
+                }
+            }
+            //Smoothing groups, lines, points and other statements don't affect triangle meshes
+            _ => {}
+        }
+    }
+    if !builder.indices.is_empty() {
+        finished.push((group, builder));
+    }
+
+    let mut converted: HashMap<String, Arc<dyn Material<T>>> = HashMap::new();
+    let mut meshes = Vec::with_capacity(finished.len());
+    for (name, builder) in finished {
+        let material = match &builder.material {
+            Some(m) => match converted.get(m) {
+                Some(material) => material.clone(),
+                None => {
+                    let material = mtl_materials[m].to_material(cache)?;
+                    converted.insert(m.clone(), material.clone());
+                    material
+                }
+            },
+            None => default_material.clone(),
+        };
+        meshes.push((name, Arc::new(builder.build(&positions, &uvs, &normals, material))));
+    }
+
+    Ok(ObjModel { meshes, mtl_files })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MTL: &str = "newmtl red\nKd 1 0 0\n\nnewmtl glass\nNi 1.3\nd 0.5\n";
+
+    fn parse(contents: &str) -> Result<ObjModel<f64>, ObjError> {
+        parse_obj(Path::new("dir/test.obj"), contents, Arc::new(DummyMaterial), &mut TextureCache::new(), |path: &Path| {
+            assert_eq!(path, Path::new("dir/test.mtl"));
+            parse_mtl(path, MTL)
+        })
+    }
+
+    fn error_line(result: Result<ObjModel<f64>, ObjError>) -> usize {
+        match result {
+            Err(ObjError::Parse { line, .. }) => line,
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("parsing should have failed"),
+        }
+    }
+
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    #[test]
+    fn polygons_are_triangulated_as_a_fan() {
+        let model = parse(&format!("{}v 0.5 1.5 0\nf 1 2 3 4 5\n", SQUARE)).unwrap();
+        assert_eq!(model.meshes.len(), 1);
+        assert_eq!(model.meshes[0].1.indices, vec![[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_vertex() {
+        let model = parse(&format!("{}f -4 -3 -2\nv 2 2 2\nf -1 -2 -3\n", SQUARE)).unwrap();
+        let mesh = &model.meshes[0].1;
+        let triangle = |k: usize| mesh.indices[k].iter().map(|i| mesh.positions[*i]).collect::<Vec<_>>();
+        assert_eq!(triangle(0), vec![Vec3::new(0, 0, 0), Vec3::new(1, 0, 0), Vec3::new(1, 1, 0)]);
+        assert_eq!(triangle(1), vec![Vec3::new(2, 2, 2), Vec3::new(0, 1, 0), Vec3::new(1, 1, 0)]);
+    }
+
+    #[test]
+    fn vertex_forms_keep_the_attributes_they_reference() {
+        let attributes = "vt 0 0\nvt 1 0\nvt 1 1\nvn 0 0 2\n";
+        let full = parse(&format!("{}{}f 1/1/1 2/2/1 3/3/1\n", SQUARE, attributes)).unwrap();
+        let mesh = &full.meshes[0].1;
+        assert_eq!(mesh.uvs, vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)]);
+        assert_eq!(mesh.normals, vec![Vec3::new(0, 0, 1); 3]);
+
+        let uv_only = parse(&format!("{}{}f 1/1 2/2 3/3\n", SQUARE, attributes)).unwrap();
+        assert_eq!(uv_only.meshes[0].1.uvs.len(), 3);
+        assert!(uv_only.meshes[0].1.normals.is_empty());
+
+        let normal_only = parse(&format!("{}{}f 1//1 2//1 3//1\n", SQUARE, attributes)).unwrap();
+        assert!(normal_only.meshes[0].1.uvs.is_empty());
+        assert_eq!(normal_only.meshes[0].1.normals.len(), 3);
+
+        //Vertices are shared between faces only when all their indices match
+        let mixed = parse(&format!("{}{}f 1/1 2/2 3/3\nf 1/1 3/3 4/1\nf 1/2 2/2 3/3\n", SQUARE, attributes)).unwrap();
+        assert_eq!(mixed.meshes[0].1.positions.len(), 5);
+    }
+
+    #[test]
+    fn groups_and_materials_split_meshes() {
+        let source = "mtllib test.mtl\nf 1 2 3\ng top\nusemtl red\nf 1 3 4\nusemtl glass\nf 2 3 4\n";
+        let model = parse(&format!("{}{}", SQUARE, source)).unwrap();
+        let names: Vec<&str> = model.meshes.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["default", "top", "top"]);
+        assert_eq!(model.hittables().len(), 3);
+        assert_eq!(model.mtl_files, vec![PathBuf::from("dir/test.mtl")]);
+    }
+
+    #[test]
+    fn errors_report_the_line_they_occur_on() {
+        assert_eq!(error_line(parse("v 0 0 0\n\nv 1 x 0\n")), 3);
+        assert_eq!(error_line(parse(&format!("{}# comment\nf 1 2 5\n", SQUARE))), 6);
+        assert_eq!(error_line(parse(&format!("{}f 1 0 2\n", SQUARE))), 5);
+        assert_eq!(error_line(parse(&format!("{}f 1 2\n", SQUARE))), 5);
+        assert_eq!(error_line(parse(&format!("{}f 1 2 3/1\n", SQUARE))), 5);
+        assert_eq!(error_line(parse(&format!("mtllib test.mtl\n{}usemtl blue\n", SQUARE))), 6);
+        //Materials only exist once their library has been loaded
+        assert_eq!(error_line(parse("usemtl red\n")), 1);
+    }
+
+    #[test]
+    fn mtl_files_are_parsed() {
+        let source = "newmtl a\nKd 0.5\nKs 0.1 0.2 0.3\nNs 10 # shiny\nillum 3\nnewmtl b\nTr 0.25\n";
+        let materials = parse_mtl(Path::new("test.mtl"), source).unwrap();
+        assert_eq!(materials["a"].diffuse, [0.5, 0.5, 0.5]);
+        assert_eq!(materials["a"].specular, [0.1, 0.2, 0.3]);
+        assert_eq!(materials["a"].specular_exponent, 10.0);
+        assert_eq!(materials["a"].illum, 3);
+        assert_eq!(materials["b"].dissolve, 0.75);
+    }
+
+    #[test]
+    fn mtl_errors_report_the_line_they_occur_on() {
+        let line = |contents: &str| match parse_mtl(Path::new("test.mtl"), contents) {
+            Err(ObjError::Parse { line, .. }) => line,
+            _ => panic!("parsing should have failed"),
+        };
+        assert_eq!(line("Kd 1 1 1\n"), 1);
+        assert_eq!(line("newmtl a\nKd 1 1\n"), 2);
+        assert_eq!(line("newmtl a\n\nillum x\n"), 3);
+        assert_eq!(line("newmtl a\nNs\n"), 2);
+    }
+
+    #[test]
+    fn texture_maps_are_relative_to_the_mtl_file() {
+        let source = "newmtl a\nmap_Kd -clamp on wood.png\nnorm maps/normals.png\n\
+            newmtl b\nbump -bm 0.25 -s 2 2 height.png\nnewmtl c\nmap_Bump height.png\n";
+        let materials = parse_mtl(Path::new("dir/test.mtl"), source).unwrap();
+        assert_eq!(materials["a"].diffuse_map, Some(PathBuf::from("dir/wood.png")));
+        assert_eq!(materials["a"].normal_map, Some(PathBuf::from("dir/maps/normals.png")));
+        assert_eq!(materials["a"].bump_map, None);
+        assert_eq!(materials["b"].bump_map, Some(PathBuf::from("dir/height.png")));
+        assert_eq!(materials["b"].bump_multiplier, 0.25);
+        assert_eq!(materials["c"].bump_map, Some(PathBuf::from("dir/height.png")));
+        assert_eq!(materials["c"].bump_multiplier, 1.0);
+
+        for bad in &["bump -bm height.png", "bump -bm inf height.png", "bump height.png -bm"] {
+            let result = parse_mtl(Path::new("test.mtl"), &format!("newmtl a\n{}\n", bad));
+            assert!(matches!(result, Err(ObjError::Parse { line: 2, .. })), "{}", bad);
+        }
+        assert!(matches!(parse_mtl(Path::new("test.mtl"), "norm\n"), Err(ObjError::Parse { line: 1, .. })));
+    }
+}
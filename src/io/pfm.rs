@@ -0,0 +1,139 @@
+use std::fs;
+use std::path::Path;
+
+use straal::{FloatType, Vec3};
+
+use crate::error::{Error, Result};
+use crate::io::{HeaderReader, ImageBuffer, NetpbmError, ParseResult};
+
+/// Encodes a linear framebuffer, top row first, as a little endian RGB Portable Float Map.
+/// PFM stores its rows bottom to top.
+pub fn encode_pfm<T>(pixels: &[Vec3<T>], width: usize, height: usize) -> Vec<u8>
+    where
+        T: FloatType<T>,
+{
+    let mut output = format!("PF\n{} {}\n-1.0\n", width, height).into_bytes();
+    output.reserve(pixels.len() * 12);
+    for y in (0..height).rev() {
+        for pixel in &pixels[y * width..(y + 1) * width] {
+            for c in &[pixel.x, pixel.y, pixel.z] {
+                let value: f32 = num::cast(*c).unwrap_or(0.0);
+                output.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+    output
+}
+
+pub fn write_pfm_to_path<T>(pixels: &[Vec3<T>], width: usize, height: usize, file_path: &Path) -> Result<()>
+    where
+        T: FloatType<T>,
+{
+    fs::write(file_path, encode_pfm(pixels, width, height)).map_err(|e| Error::io(file_path, e))
+}
+
+/// Decodes a colour (PF) or greyscale (Pf) Portable Float Map, whose values are linear. The sign
+/// of the scale gives the byte order, negative for little endian, its magnitude is ignored.
+pub fn decode_pfm<T>(bytes: &[u8]) -> ParseResult<ImageBuffer<T>>
+    where
+        T: FloatType<T>,
+{
+    let mut header = HeaderReader::new(bytes);
+    let magic = header.token().unwrap_or("");
+    let channels = match magic {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => {
+            return Err(NetpbmError::UnsupportedType {
+                magic: magic.to_string(),
+                expected: "PF or Pf",
+            })
+        }
+    };
+    let (width, height) = header.size()?;
+    let scale: f32 = header.number("scale")?;
+    let data = header.binary_data(width * height * channels * 4)?;
+    let value = |i: usize| -> T {
+        let mut raw = [0u8; 4];
+        raw.copy_from_slice(&data[i * 4..i * 4 + 4]);
+        let v = if scale < 0.0 { f32::from_le_bytes(raw) } else { f32::from_be_bytes(raw) };
+        T::from(v).unwrap()
+    };
+
+    let mut pixels = Vec::with_capacity(width * height);
+    //Rows are stored bottom to top
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let i = (y * width + x) * channels;
+            pixels.push(if channels == 3 {
+                Vec3::<T> {
+                    x: value(i),
+                    y: value(i + 1),
+                    z: value(i + 2),
+                }
+            } else {
+                Vec3::all(value(i))
+            });
+        }
+    }
+    Ok(ImageBuffer { width, height, pixels })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pfm(header: &str, values: &[f32], little_endian: bool) -> Vec<u8> {
+        let mut bytes = header.as_bytes().to_vec();
+        for v in values {
+            bytes.extend_from_slice(&if little_endian { v.to_le_bytes() } else { v.to_be_bytes() });
+        }
+        bytes
+    }
+
+    #[test]
+    fn decodes_little_endian_for_a_negative_scale() {
+        let bytes = pfm("PF\n1 2\n-1.0\n", &[0.5, 1.0, 2.0, 4.0, 8.0, 16.0], true);
+        let image = decode_pfm::<f64>(&bytes).unwrap();
+        assert_eq!((image.width, image.height), (1, 2));
+        //The first row in the file is the bottom one
+        assert_eq!(image.pixels[0], Vec3::new(4.0, 8.0, 16.0));
+        assert_eq!(image.pixels[1], Vec3::new(0.5, 1.0, 2.0));
+    }
+
+    #[test]
+    fn decodes_big_endian_for_a_positive_scale() {
+        let bytes = pfm("PF\n1 1\n2.5\n", &[0.25, 3.0, 100.0], false);
+        let image = decode_pfm::<f64>(&bytes).unwrap();
+        assert_eq!(image.pixels[0], Vec3::new(0.25, 3.0, 100.0));
+    }
+
+    #[test]
+    fn decodes_greyscale() {
+        let bytes = pfm("Pf\n2 1\n-1.0\n", &[0.5, 7.0], true);
+        let image = decode_pfm::<f64>(&bytes).unwrap();
+        assert_eq!(image.pixels, vec![Vec3::all(0.5), Vec3::all(7.0)]);
+    }
+
+    #[test]
+    fn round_trips_through_the_encoder() {
+        let pixels = vec![
+            Vec3::new(0.0, 1.5, 3.0),
+            Vec3::new(10.0, 0.125, 2.0),
+            Vec3::new(6.0, 5.0, 4.0),
+            Vec3::new(0.5, 0.25, 1.0),
+        ];
+        let image = decode_pfm::<f64>(&encode_pfm(&pixels, 2, 2)).unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(image.pixels, pixels);
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let bytes = pfm("PF\n2 1\n-1.0\n", &[1.0, 2.0, 3.0], true);
+        assert_eq!(
+            decode_pfm::<f64>(&bytes).err(),
+            Some(NetpbmError::Truncated { expected: 24, found: 12 })
+        );
+    }
+}
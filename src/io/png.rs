@@ -0,0 +1,522 @@
+use std::fs;
+use std::path::Path;
+
+use straal::{FloatType, Vec3};
+
+use crate::error::{Error, Result};
+use crate::io::{zlib_compress, zlib_decompress, ImageBuffer};
+use crate::math::{srgb_color, srgb_to_linear};
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+const BYTES_PER_PIXEL: usize = 3;
+/// Start column and row and spacing of the seven Adam7 interlacing passes
+const ADAM7_PASSES: [(usize, usize, usize, usize); 7] =
+    [(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)];
+
+/// Remainders of every byte value, computed once at compile time
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            c = if c & 1 != 0 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+            bit += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes
+        .iter()
+        .fold(!0u32, |c, b| CRC32_TABLE[((c ^ *b as u32) & 0xff) as usize] ^ (c >> 8))
+}
+
+fn write_chunk(output: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = output.len();
+    output.extend_from_slice(kind);
+    output.extend_from_slice(data);
+    //The checksum covers the chunk type and data, not the length
+    let crc = crc32(&output[start..]);
+    output.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Applies PNG filter `filter` to `row`, given the unfiltered row above it
+fn filter_row(filter: u8, row: &[u8], previous: &[u8], output: &mut Vec<u8>) {
+    for i in 0..row.len() {
+        let a = if i >= BYTES_PER_PIXEL { row[i - BYTES_PER_PIXEL] } else { 0 };
+        let b = previous[i];
+        let c = if i >= BYTES_PER_PIXEL { previous[i - BYTES_PER_PIXEL] } else { 0 };
+        let predicted = match filter {
+            0 => 0,
+            1 => a,
+            2 => b,
+            3 => ((a as u16 + b as u16) / 2) as u8,
+            _ => paeth(a, b, c),
+        };
+        output.push(row[i].wrapping_sub(predicted));
+    }
+}
+
+/// Filters every scanline with the filter type that gives the smallest sum of absolute
+/// differences, the usual heuristic for picking filters that compress well
+fn filter_scanlines(image: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let stride = width * BYTES_PER_PIXEL;
+    let mut output = Vec::with_capacity((stride + 1) * height);
+    let empty_row = vec![0u8; stride];
+    let mut candidate = Vec::with_capacity(stride);
+    let mut best = Vec::with_capacity(stride);
+
+    for y in 0..height {
+        let row = &image[y * stride..(y + 1) * stride];
+        let previous = if y == 0 { &empty_row[..] } else { &image[(y - 1) * stride..y * stride] };
+        let mut best_filter = 0;
+        let mut best_cost = u64::MAX;
+        for filter in 0..5 {
+            candidate.clear();
+            filter_row(filter, row, previous, &mut candidate);
+            let cost: u64 = candidate.iter().map(|v| (*v as i8).unsigned_abs() as u64).sum();
+            if cost < best_cost {
+                best_cost = cost;
+                best_filter = filter;
+                std::mem::swap(&mut best, &mut candidate);
+            }
+        }
+        output.push(best_filter);
+        output.extend_from_slice(&best);
+    }
+    output
+}
+
+/// Encodes a linear framebuffer, top row first, as an 8-bit sRGB PNG
+pub fn encode_png<T>(pixels: &[Vec3<T>], width: usize, height: usize) -> Vec<u8>
+    where
+        T: FloatType<T>,
+{
+    let mut image = Vec::with_capacity(pixels.len() * BYTES_PER_PIXEL);
+    for pixel in pixels {
+        let srgb = srgb_color(pixel);
+        for c in &[srgb.x, srgb.y, srgb.z] {
+            let value: f64 = num::cast(*c * T::from(255.0).unwrap() + T::from(0.5).unwrap()).unwrap_or(0.0);
+            image.push(value as u8);
+        }
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    //8 bits per channel, RGB, deflate, adaptive filtering, no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut output = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut output, b"IHDR", &header);
+    //Perceptual rendering intent, tells viewers the values are already sRGB encoded
+    write_chunk(&mut output, b"sRGB", &[0]);
+    write_chunk(&mut output, b"IDAT", &zlib_compress(&filter_scanlines(&image, width, height)));
+    write_chunk(&mut output, b"IEND", &[]);
+    output
+}
+
+pub fn write_png_to_path<T>(pixels: &[Vec3<T>], width: usize, height: usize, file_path: &Path) -> Result<()>
+    where
+        T: FloatType<T>,
+{
+    fs::write(file_path, encode_png(pixels, width, height)).map_err(|e| Error::io(file_path, e))
+}
+
+/// The parts of an IHDR chunk needed to decode the pixel data
+struct PngHeader {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl PngHeader {
+    fn parse(data: &[u8]) -> std::result::Result<PngHeader, String> {
+        if data.len() != 13 {
+            return Err("IHDR chunk has the wrong size".to_string());
+        }
+        let read_u32 = |i: usize| u32::from_be_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]) as usize;
+        let header = PngHeader {
+            width: read_u32(0),
+            height: read_u32(4),
+            bit_depth: data[8],
+            color_type: data[9],
+            interlaced: data[12] == 1,
+        };
+        let valid_depths: &[u8] = match header.color_type {
+            0 => &[1, 2, 4, 8, 16],
+            3 => &[1, 2, 4, 8],
+            2 | 4 | 6 => &[8, 16],
+            other => return Err(format!("unknown color type {}", other)),
+        };
+        if !valid_depths.contains(&header.bit_depth) {
+            return Err(format!(
+                "bit depth {} isn't allowed for color type {}",
+                header.bit_depth, header.color_type
+            ));
+        }
+        if data[10] != 0 || data[11] != 0 || data[12] > 1 {
+            return Err("unknown compression, filter or interlace method".to_string());
+        }
+        if header.width == 0 || header.height == 0 {
+            return Err("image has no pixels".to_string());
+        }
+        if header.width.checked_mul(header.height).and_then(|c| c.checked_mul(8)).is_none() {
+            return Err(format!("image of {}x{} pixels is too large", header.width, header.height));
+        }
+        Ok(header)
+    }
+
+    fn channels(&self) -> usize {
+        match self.color_type {
+            0 | 3 => 1,
+            4 => 2,
+            2 => 3,
+            _ => 4,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize
+    }
+
+    /// Bytes in one scanline of `width` pixels, without the filter type byte
+    fn stride(&self, width: usize) -> usize {
+        (width * self.bits_per_pixel()).div_ceil(8)
+    }
+}
+
+/// Reverses the filtering of `height` scanlines, each preceded by its filter type. The
+/// unfiltered rows are returned without the filter type bytes.
+fn unfilter_scanlines(
+    data: &[u8],
+    stride: usize,
+    height: usize,
+    bytes_per_pixel: usize,
+) -> std::result::Result<Vec<u8>, String> {
+    let mut output = vec![0u8; stride * height];
+    for y in 0..height {
+        let filter = data[y * (stride + 1)];
+        let row = &data[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        let (above, current) = output.split_at_mut(y * stride);
+        let previous = if y == 0 { None } else { Some(&above[(y - 1) * stride..]) };
+        let current = &mut current[..stride];
+        for i in 0..stride {
+            let a = if i >= bytes_per_pixel { current[i - bytes_per_pixel] } else { 0 };
+            let b = previous.map_or(0, |p| p[i]);
+            let c = if i >= bytes_per_pixel { previous.map_or(0, |p| p[i - bytes_per_pixel]) } else { 0 };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                other => return Err(format!("unknown filter type {} in scanline {}", other, y)),
+            };
+            current[i] = row[i].wrapping_add(predicted);
+        }
+    }
+    Ok(output)
+}
+
+/// Sample `index` of a scanline, samples smaller than a byte are packed most significant first
+fn read_sample(row: &[u8], index: usize, bit_depth: u8) -> u16 {
+    match bit_depth {
+        16 => (row[index * 2] as u16) << 8 | row[index * 2 + 1] as u16,
+        8 => row[index] as u16,
+        _ => {
+            let bit = index * bit_depth as usize;
+            let shift = 8 - bit_depth as usize - bit % 8;
+            (row[bit / 8] >> shift) as u16 & ((1 << bit_depth) - 1)
+        }
+    }
+}
+
+/// Decodes a PNG, dropping any alpha channel. Values are taken to be sRGB encoded when `srgb`
+/// is set and converted to linear, otherwise they are returned as stored, which is what data
+/// like roughness or normal maps needs.
+pub fn decode_png<T>(bytes: &[u8], srgb: bool) -> std::result::Result<ImageBuffer<T>, String>
+    where
+        T: FloatType<T>,
+{
+    if bytes.len() < PNG_SIGNATURE.len() || bytes[..PNG_SIGNATURE.len()] != PNG_SIGNATURE {
+        return Err("not a PNG file".to_string());
+    }
+    let mut position = PNG_SIGNATURE.len();
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut compressed = Vec::new();
+    loop {
+        if position + 8 > bytes.len() {
+            return Err("file ends before the IEND chunk".to_string());
+        }
+        let read_u32 = |i: usize| u32::from_be_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        let length = read_u32(position) as usize;
+        let kind = &bytes[position + 4..position + 8];
+        let end = position + 8 + length;
+        if end + 4 > bytes.len() {
+            return Err(format!("{} chunk runs past the end of the file", String::from_utf8_lossy(kind)));
+        }
+        let data = &bytes[position + 8..end];
+        if read_u32(end) != crc32(&bytes[position + 4..end]) {
+            return Err(format!("{} chunk is corrupt, its checksum doesn't match", String::from_utf8_lossy(kind)));
+        }
+        match kind {
+            b"IHDR" => header = Some(PngHeader::parse(data)?),
+            b"PLTE" => palette = data,
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            //Ancillary chunks have a lowercase first letter and can be skipped, critical ones can't
+            _ if kind[0].is_ascii_uppercase() => {
+                return Err(format!("unsupported critical chunk {}", String::from_utf8_lossy(kind)));
+            }
+            _ => {}
+        }
+        position = end + 4;
+    }
+    let header = header.ok_or_else(|| "missing IHDR chunk".to_string())?;
+    if header.color_type == 3 && palette.is_empty() {
+        return Err("paletted image without a PLTE chunk".to_string());
+    }
+    let data = zlib_decompress(&compressed)?;
+
+    //Every possible sample value converted once, instead of once per pixel
+    let levels = |bit_depth: u8| -> Vec<T> {
+        let max_value = (1u32 << bit_depth) - 1;
+        (0..=max_value)
+            .map(|v| {
+                let c = T::from(v).unwrap() / T::from(max_value).unwrap();
+                if srgb {
+                    srgb_to_linear(c)
+                } else {
+                    c
+                }
+            })
+            .collect()
+    };
+    //Palette entries are always 8 bits, whatever the depth of the indices
+    let palette_levels = levels(8);
+    let colors: Vec<Vec3<T>> = palette
+        .chunks_exact(3)
+        .map(|rgb| Vec3::<T> {
+            x: palette_levels[rgb[0] as usize],
+            y: palette_levels[rgb[1] as usize],
+            z: palette_levels[rgb[2] as usize],
+        })
+        .collect();
+    let levels = levels(header.bit_depth);
+
+    let (width, height) = (header.width, header.height);
+    let mut pixels = vec![Vec3::<T>::zero(); width * height];
+    let single_pass = [(0, 0, 1, 1)];
+    let passes: &[(usize, usize, usize, usize)] = if header.interlaced { &ADAM7_PASSES } else { &single_pass };
+    let bytes_per_pixel = header.bits_per_pixel().div_ceil(8).max(1);
+    let mut offset = 0;
+    for (x0, y0, dx, dy) in passes {
+        let pass_width = (width + dx - 1 - x0) / dx;
+        let pass_height = (height + dy - 1 - y0) / dy;
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+        let stride = header.stride(pass_width);
+        let size = (stride + 1) * pass_height;
+        if offset + size > data.len() {
+            return Err(format!("expected {} bytes of pixel data, found {}", offset + size, data.len()));
+        }
+        let rows = unfilter_scanlines(&data[offset..offset + size], stride, pass_height, bytes_per_pixel)?;
+        offset += size;
+
+        let channels = header.channels();
+        for (py, row) in rows.chunks(stride).enumerate() {
+            for px in 0..pass_width {
+                let sample = |c: usize| read_sample(row, px * channels + c, header.bit_depth) as usize;
+                let color = match header.color_type {
+                    0 | 4 => Vec3::all(levels[sample(0)]),
+                    3 => *colors
+                        .get(sample(0))
+                        .ok_or_else(|| format!("palette index {} is out of range", sample(0)))?,
+                    _ => Vec3::<T> {
+                        x: levels[sample(0)],
+                        y: levels[sample(1)],
+                        z: levels[sample(2)],
+                    },
+                };
+                pixels[(y0 + py * dy) * width + x0 + px * dx] = color;
+            }
+        }
+    }
+    Ok(ImageBuffer { width, height, pixels })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Packs `samples`, every channel of every pixel top row first, into a PNG with unfiltered
+    /// scanlines, split into the Adam7 passes when `interlaced` is set
+    fn encode_samples(header: &PngHeader, palette: &[u8], samples: &[u16]) -> Vec<u8> {
+        let channels = header.channels();
+        let single_pass = [(0, 0, 1, 1)];
+        let passes: &[(usize, usize, usize, usize)] = if header.interlaced { &ADAM7_PASSES } else { &single_pass };
+        let mut scanlines = Vec::new();
+        for (x0, y0, dx, dy) in passes {
+            let columns: Vec<usize> = (*x0..header.width).step_by(*dx).collect();
+            if columns.is_empty() {
+                continue;
+            }
+            for y in (*y0..header.height).step_by(*dy) {
+                let mut row = vec![0u8; header.stride(columns.len())];
+                for (i, x) in columns.iter().enumerate() {
+                    for c in 0..channels {
+                        let value = samples[(y * header.width + x) * channels + c];
+                        let index = i * channels + c;
+                        match header.bit_depth {
+                            16 => row[index * 2..index * 2 + 2].copy_from_slice(&value.to_be_bytes()),
+                            8 => row[index] = value as u8,
+                            depth => {
+                                let bit = index * depth as usize;
+                                row[bit / 8] |= (value as u8) << (8 - depth as usize - bit % 8);
+                            }
+                        }
+                    }
+                }
+                scanlines.push(0);
+                scanlines.extend_from_slice(&row);
+            }
+        }
+
+        let mut ihdr = Vec::new();
+        ihdr.extend_from_slice(&(header.width as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(header.height as u32).to_be_bytes());
+        ihdr.extend_from_slice(&[header.bit_depth, header.color_type, 0, 0, header.interlaced as u8]);
+        let mut output = PNG_SIGNATURE.to_vec();
+        write_chunk(&mut output, b"IHDR", &ihdr);
+        if !palette.is_empty() {
+            write_chunk(&mut output, b"PLTE", palette);
+        }
+        write_chunk(&mut output, b"IDAT", &zlib_compress(&scanlines));
+        write_chunk(&mut output, b"IEND", &[]);
+        output
+    }
+
+    fn header(width: usize, height: usize, bit_depth: u8, color_type: u8, interlaced: bool) -> PngHeader {
+        PngHeader {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlaced,
+        }
+    }
+
+    #[test]
+    fn crc32_matches_known_values() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn encoded_images_are_framed_by_the_header_and_end_chunks() {
+        let pixels: Vec<Vec3<f64>> = vec![Vec3::new(0.0, 0.5, 1.0), Vec3::new(2.0, -1.0, 0.25)];
+        let png = encode_png(&pixels, 2, 1);
+        assert_eq!(png[..8], PNG_SIGNATURE);
+        //Length, type, width, height, bit depth, color type and the rest of the header
+        assert_eq!(png[8..16], [0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+        assert_eq!(png[16..29], [0, 0, 0, 2, 0, 0, 0, 1, 8, 2, 0, 0, 0]);
+        assert_eq!(png[29..33], crc32(&png[12..29]).to_be_bytes());
+        assert_eq!(png[png.len() - 12..], [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]);
+    }
+
+    #[test]
+    fn encoded_images_round_trip() {
+        //Values on the 8-bit sRGB levels survive quantisation, the gradient makes every filter worth trying
+        let (width, height) = (13, 6);
+        let level = |v: usize| srgb_to_linear((v % 256) as f64 / 255.0);
+        let pixels: Vec<Vec3<f64>> = (0..width * height)
+            .map(|i| Vec3::<f64> {
+                x: level(i * 3),
+                y: level(i * 7 + 40),
+                z: level(255 - i),
+            })
+            .collect();
+        let image = decode_png::<f64>(&encode_png(&pixels, width, height), true).unwrap();
+        assert_eq!((image.width, image.height), (width, height));
+        for (decoded, expected) in image.pixels.iter().zip(&pixels) {
+            assert!((*decoded - *expected).length() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn decodes_sub_byte_greyscale() {
+        for &bit_depth in &[1, 2, 4] {
+            for &interlaced in &[false, true] {
+                let (width, height) = (11, 9);
+                let max_value = (1u16 << bit_depth) - 1;
+                let samples: Vec<u16> = (0..width * height).map(|i| (i * 5 / 3) as u16 & max_value).collect();
+                let header = header(width, height, bit_depth, 0, interlaced);
+                let image = decode_png::<f64>(&encode_samples(&header, &[], &samples), false).unwrap();
+                for (pixel, sample) in image.pixels.iter().zip(&samples) {
+                    assert_eq!(*pixel, Vec3::all(*sample as f64 / max_value as f64));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_interlaced_palette_indices() {
+        let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255];
+        let (width, height) = (10, 10);
+        let samples: Vec<u16> = (0..width * height).map(|i| (i % 7 % 4) as u16).collect();
+        let png = encode_samples(&header(width, height, 2, 3, true), &palette, &samples);
+        let image = decode_png::<f64>(&png, false).unwrap();
+        for (pixel, sample) in image.pixels.iter().zip(&samples) {
+            let rgb = &palette[*sample as usize * 3..*sample as usize * 3 + 3];
+            assert_eq!(*pixel, Vec3::new(rgb[0] as f64, rgb[1] as f64, rgb[2] as f64) / 255.0);
+        }
+    }
+
+    #[test]
+    fn decodes_interlaced_sixteen_bit_images_smaller_than_a_pass() {
+        //Some passes are empty when the image is smaller than the 8x8 Adam7 block
+        let (width, height) = (3, 2);
+        let samples: Vec<u16> = (0..width * height * 3).map(|i| (i as u16) * 3000).collect();
+        let png = encode_samples(&header(width, height, 16, 2, true), &[], &samples);
+        let image = decode_png::<f64>(&png, false).unwrap();
+        for (pixel, rgb) in image.pixels.iter().zip(samples.chunks(3)) {
+            assert_eq!(*pixel, Vec3::new(rgb[0] as f64, rgb[1] as f64, rgb[2] as f64) / 65535.0);
+        }
+    }
+
+    #[test]
+    fn rejects_corrupt_chunks() {
+        let mut png = encode_png(&[Vec3::<f64>::all(0.5)], 1, 1);
+        assert!(decode_png::<f64>(&png, true).is_ok());
+        png[PNG_SIGNATURE.len() + 8] ^= 1;
+        assert!(decode_png::<f64>(&png, true).is_err());
+    }
+}
@@ -1,14 +1,66 @@
-use std::error::Error;
-use std::fs::File;
-use std::io::Write;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use rand::{self, Rng};
 use straal::{FloatType, IVec3, Vec3};
 
+use crate::error::{Error, Result};
+use crate::io::{decode_pfm, decode_png, decode_radiance_hdr};
+use crate::math::{gamma_color, srgb_to_linear};
+
+/// An image file that can't be read or decoded, whatever its format
+#[derive(Debug)]
+pub enum ImageError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        message: String,
+    },
+    Netpbm {
+        path: PathBuf,
+        error: NetpbmError,
+    },
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageError::Io { path, error } => write!(f, "Could not read {}: {}", path.display(), error),
+            ImageError::Parse { path, message } => write!(f, "{}: {}", path.display(), message),
+            ImageError::Netpbm { path, error } => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl error::Error for ImageError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ImageError::Io { error, .. } => Some(error),
+            ImageError::Parse { .. } => None,
+            ImageError::Netpbm { error, .. } => Some(error),
+        }
+    }
+}
+
+/// Linear pixels, top row first, as rendered or read from an image file
+pub struct ImageBuffer<T> {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Vec3<T>>,
+}
+
+/// 8-bit encoding of a linear color with a gamma of 2
 pub fn to_ppm_color<T>(v: &Vec3<T>) -> IVec3<i32>
 where
     T: FloatType<T>,
 {
+    let v = gamma_color(v);
     let max = T::from(255.99).unwrap();
     let x = num::cast(max * v.x);
     let y = num::cast(max * v.y);
@@ -28,45 +80,378 @@ where
 }
 
 pub fn write_ppm_file<T>(
-    pixels: &Vec<Vec3<T>>,
+    pixels: &[Vec3<T>],
     width: usize,
     height: usize,
     file_name: Option<&str>,
-) where
+) -> Result<()>
+where
     T: FloatType<T>,
 {
-    let real_file_name = match file_name {
-        None => {
-            let unique_id: u32 = rand::thread_rng().gen_range(0, 999999);
-            format!("output_{:0>6}.ppm", unique_id)
-        }
-        Some(n) => n.to_string() + ".ppm",
+    let file_path = match file_name {
+        None => unique_output_path("ppm")?,
+        Some(n) => output_directory()?.join(n.to_string() + ".ppm"),
     };
+    write_ppm_to_path(pixels, width, height, &file_path)
+}
 
-    let file_path = "./output/";
+/// The default ./output/ directory, created when it doesn't exist yet
+fn output_directory() -> Result<PathBuf> {
+    let directory = PathBuf::from("./output/");
+    fs::create_dir_all(&directory).map_err(|e| Error::io(&directory, e))?;
+    Ok(directory)
+}
 
-    println!("Writing pixels to: {}{}", file_path, real_file_name);
+/// The first ./output/output_NNNNNN file with the given extension that doesn't exist yet, so
+/// renders are numbered in the order they were made
+pub fn unique_output_path(extension: &str) -> Result<PathBuf> {
+    let directory = output_directory()?;
+    let mut number = 0u32;
+    loop {
+        let path = directory.join(format!("output_{:0>6}.{}", number, extension));
+        if !path.exists() {
+            return Ok(path);
+        }
+        number += 1;
+    }
+}
 
+/// Writes an ASCII (P3) PPM with 8-bit sRGB values
+pub fn write_ppm_to_path<T>(pixels: &[Vec3<T>], width: usize, height: usize, file_path: &Path) -> Result<()>
+where
+    T: FloatType<T>,
+{
     let mut output = String::with_capacity(20 + pixels.len() * 12); //Assumed max size of output file
     output.push_str(&format!("P3\n{} {}\n255\n", width, height)); //Header
 
     for pixel in pixels {
-        let ppm_color = to_ppm_color(&pixel);
+        let ppm_color = to_ppm_color(pixel);
         output.push_str(&format!(
             "{} {} {}\n",
             ppm_color.x, ppm_color.y, ppm_color.z
         ));
     }
 
-    match File::create(file_path.to_owned() + &real_file_name) {
-        Ok(mut file) => match file.write_all(&output.as_bytes()) {
-            Ok(_s) => {
-                println!("Succeeded in writing file");
+    fs::write(file_path, output).map_err(|e| Error::io(file_path, e))
+}
+
+/// Encodes a linear framebuffer as a binary (P6) PPM, with the same 8-bit values as the text
+/// version but clamped to the 0-255 a byte can hold
+pub fn encode_ppm_binary<T>(pixels: &[Vec3<T>], width: usize, height: usize) -> Vec<u8>
+where
+    T: FloatType<T>,
+{
+    let mut output = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    output.reserve(pixels.len() * 3);
+    for pixel in pixels {
+        let ppm_color = to_ppm_color(pixel);
+        for c in &[ppm_color.x, ppm_color.y, ppm_color.z] {
+            output.push((*c).clamp(0, 255) as u8);
+        }
+    }
+    output
+}
+
+pub fn write_ppm_binary_to_path<T>(pixels: &[Vec3<T>], width: usize, height: usize, file_path: &Path) -> Result<()>
+where
+    T: FloatType<T>,
+{
+    fs::write(file_path, encode_ppm_binary(pixels, width, height)).map_err(|e| Error::io(file_path, e))
+}
+
+/// What is wrong with the contents of a PPM or PFM file
+#[derive(Debug, PartialEq)]
+pub enum NetpbmError {
+    UnsupportedType {
+        magic: String,
+        expected: &'static str,
+    },
+    Missing(&'static str),
+    Invalid {
+        what: &'static str,
+        token: String,
+    },
+    TooLarge {
+        width: usize,
+        height: usize,
+    },
+    MaxValue(u32),
+    /// Less pixel data than the header promises
+    Truncated {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for NetpbmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetpbmError::UnsupportedType { magic, expected } => {
+                write!(f, "unsupported file type '{}', expected {}", magic, expected)
+            }
+            NetpbmError::Missing(what) => write!(f, "missing {}", what),
+            NetpbmError::Invalid { what, token } => write!(f, "invalid {} '{}'", what, token),
+            NetpbmError::TooLarge { width, height } => write!(f, "image of {}x{} pixels is too large", width, height),
+            NetpbmError::MaxValue(max_value) => {
+                write!(f, "maximum value must be between 1 and 65535, got {}", max_value)
             }
-            Err(e) => {
-                println!("{}", e);
+            NetpbmError::Truncated { expected, found } => {
+                write!(f, "expected {} bytes of pixel data, found {}", expected, found)
+            }
+        }
+    }
+}
+
+impl error::Error for NetpbmError {}
+
+pub(crate) type ParseResult<V> = std::result::Result<V, NetpbmError>;
+
+/// Splits the whitespace separated header fields of Netpbm style files, skipping comments
+pub(crate) struct HeaderReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> HeaderReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> HeaderReader<'a> {
+        HeaderReader { bytes, position: 0 }
+    }
+
+    pub(crate) fn token(&mut self) -> Option<&'a str> {
+        loop {
+            match self.bytes.get(self.position) {
+                Some(b'#') => {
+                    while self.position < self.bytes.len() && self.bytes[self.position] != b'\n' {
+                        self.position += 1;
+                    }
+                }
+                Some(c) if c.is_ascii_whitespace() => self.position += 1,
+                Some(_) => break,
+                None => return None,
             }
-        },
-        Err(e) => panic!("Could not create file: /n{}", e.description()),
+        }
+        let start = self.position;
+        while self.position < self.bytes.len() && !self.bytes[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.position]).ok()
+    }
+
+    pub(crate) fn number<N>(&mut self, what: &'static str) -> ParseResult<N>
+    where
+        N: FromStr,
+    {
+        match self.token() {
+            Some(token) => token.parse().map_err(|_| NetpbmError::Invalid {
+                what,
+                token: token.to_string(),
+            }),
+            None => Err(NetpbmError::Missing(what)),
+        }
+    }
+
+    /// Width and height, checked to fit in memory as 12 bytes per pixel
+    pub(crate) fn size(&mut self) -> ParseResult<(usize, usize)> {
+        let width: usize = self.number("width")?;
+        let height: usize = self.number("height")?;
+        match width.checked_mul(height).and_then(|c| c.checked_mul(12)) {
+            Some(_) => Ok((width, height)),
+            None => Err(NetpbmError::TooLarge { width, height }),
+        }
+    }
+
+    /// Binary data starts after exactly one whitespace character following the header
+    pub(crate) fn binary_data(&self, needed: usize) -> ParseResult<&'a [u8]> {
+        let data = &self.bytes[(self.position + 1).min(self.bytes.len())..];
+        if data.len() < needed {
+            return Err(NetpbmError::Truncated {
+                expected: needed,
+                found: data.len(),
+            });
+        }
+        Ok(&data[..needed])
+    }
+}
+
+/// Decodes P3 and P6 files, whose values are taken to be sRGB encoded when `srgb` is set
+pub fn decode_ppm<T>(bytes: &[u8], srgb: bool) -> ParseResult<ImageBuffer<T>>
+where
+    T: FloatType<T>,
+{
+    let mut header = HeaderReader::new(bytes);
+    let magic = header.token().unwrap_or("");
+    if magic != "P3" && magic != "P6" {
+        return Err(NetpbmError::UnsupportedType {
+            magic: magic.to_string(),
+            expected: "P3 or P6",
+        });
+    }
+    let (width, height) = header.size()?;
+    let count = width * height;
+
+    let max_value: u32 = header.number("maximum value")?;
+    if max_value == 0 || max_value > 65535 {
+        return Err(NetpbmError::MaxValue(max_value));
+    }
+    let samples: Vec<u32> = if magic == "P3" {
+        (0..count * 3)
+            .map(|_| header.number("sample"))
+            .collect::<ParseResult<Vec<u32>>>()?
+    } else if max_value < 256 {
+        header.binary_data(count * 3)?.iter().map(|v| *v as u32).collect()
+    } else {
+        header
+            .binary_data(count * 6)?
+            .chunks(2)
+            .map(|v| (v[0] as u32) << 8 | v[1] as u32)
+            .collect()
+    };
+    let mut pixels = Vec::with_capacity(count);
+    let to_linear = |v: u32| {
+        let c = T::from(v.min(max_value)).unwrap() / T::from(max_value).unwrap();
+        if srgb {
+            srgb_to_linear(c)
+        } else {
+            c
+        }
+    };
+    for rgb in samples.chunks(3) {
+        pixels.push(Vec3::<T> {
+            x: to_linear(rgb[0]),
+            y: to_linear(rgb[1]),
+            z: to_linear(rgb[2]),
+        });
+    }
+    Ok(ImageBuffer { width, height, pixels })
+}
+
+/// Reads a P3, P6 or PFM file
+pub fn read_ppm_file<T>(path: &Path) -> Result<ImageBuffer<T>>
+where
+    T: FloatType<T>,
+{
+    let bytes = fs::read(path).map_err(|error| ImageError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    let image = if bytes.starts_with(b"PF") || bytes.starts_with(b"Pf") {
+        decode_pfm(&bytes)
+    } else {
+        decode_ppm(&bytes, true)
+    };
+    let image = image.map_err(|error| ImageError::Netpbm {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    Ok(image)
+}
+
+/// Reads a PPM, PFM, PNG or Radiance HDR image, going by the start of the file rather than its
+/// extension. 8 and 16 bit values are converted from sRGB to linear when `srgb` is set.
+pub fn read_image_file<T>(path: &Path, srgb: bool) -> std::result::Result<ImageBuffer<T>, ImageError>
+where
+    T: FloatType<T>,
+{
+    let bytes = fs::read(path).map_err(|error| ImageError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    let parse_error = |message| ImageError::Parse {
+        path: path.to_path_buf(),
+        message,
+    };
+    let netpbm_error = |error| ImageError::Netpbm {
+        path: path.to_path_buf(),
+        error,
+    };
+    if bytes.starts_with(b"\x89PNG") {
+        decode_png(&bytes, srgb).map_err(parse_error)
+    } else if bytes.starts_with(b"#?") {
+        decode_radiance_hdr(&bytes).map_err(parse_error)
+    } else if bytes.starts_with(b"PF") || bytes.starts_with(b"Pf") {
+        decode_pfm(&bytes).map_err(netpbm_error)
+    } else if bytes.starts_with(b"P") {
+        decode_ppm(&bytes, srgb).map_err(netpbm_error)
+    } else {
+        Err(parse_error("unsupported image format, expected PNG, PPM, PFM or Radiance HDR".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_ascii_ppm_with_comments() {
+        let bytes = b"P3\n# two pixels\n2 1\n255\n255 0 0\n0 0 255\n";
+        let image = decode_ppm::<f64>(bytes, true).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.pixels[0], Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(image.pixels[1], Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn decodes_binary_ppm_as_srgb() {
+        let mut bytes = b"P6 1 2 255\n".to_vec();
+        bytes.extend_from_slice(&[0, 255, 0, 255, 255, 188]);
+        let image = decode_ppm::<f64>(&bytes, true).unwrap();
+        assert_eq!((image.width, image.height), (1, 2));
+        assert_eq!(image.pixels[0], Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(image.pixels[1].x, 1.0);
+        assert!((image.pixels[1].z - 0.5).abs() < 0.005);
+    }
+
+    #[test]
+    fn decodes_sixteen_bit_binary_ppm() {
+        let mut bytes = b"P6\n1 1\n65535\n".to_vec();
+        bytes.extend_from_slice(&[0xff, 0xff, 0x00, 0x00, 0x00, 0x80]);
+        let image = decode_ppm::<f64>(&bytes, true).unwrap();
+        assert_eq!(image.pixels[0], Vec3::new(1.0, 0.0, srgb_to_linear(128.0 / 65535.0)));
+    }
+
+    #[test]
+    fn data_images_keep_their_values() {
+        let image = decode_ppm::<f64>(b"P3\n2 1\n255\n255 51 0\n0 0 255\n", false).unwrap();
+        assert_eq!(image.pixels, vec![Vec3::new(1.0, 0.2, 0.0), Vec3::new(0.0, 0.0, 1.0)]);
+        let mut bytes = b"P6\n1 1\n65535\n".to_vec();
+        bytes.extend_from_slice(&[0xff, 0xff, 0x00, 0x00, 0x80, 0x00]);
+        let image = decode_ppm::<f64>(&bytes, false).unwrap();
+        assert_eq!(image.pixels[0], Vec3::new(1.0, 0.0, 32768.0 / 65535.0));
+    }
+
+    #[test]
+    fn binary_ppm_is_gamma_encoded_and_clamped() {
+        let pixels: Vec<Vec3<f64>> = vec![Vec3::new(0.25, 0.0, 1.0), Vec3::new(4.0, -1.0, 0.01)];
+        let encoded = encode_ppm_binary(&pixels, 2, 1);
+        assert_eq!(encoded[..11], *b"P6\n2 1\n255\n");
+        assert_eq!(encoded[11..], [127, 0, 255, 255, 0, 25]);
+    }
+
+    #[test]
+    fn rejects_malformed_ppm() {
+        assert_eq!(
+            decode_ppm::<f64>(b"P6\n2 2\n255\n\0\0\0", false).err(),
+            Some(NetpbmError::Truncated { expected: 12, found: 3 })
+        );
+        assert_eq!(decode_ppm::<f64>(b"P3\n1 1\n0\n", false).err(), Some(NetpbmError::MaxValue(0)));
+        assert_eq!(
+            decode_ppm::<f64>(b"P3\n1 x\n", false).err(),
+            Some(NetpbmError::Invalid {
+                what: "height",
+                token: "x".to_string()
+            })
+        );
+        assert_eq!(decode_ppm::<f64>(b"P3\n1 1\n255\n1 2", false).err(), Some(NetpbmError::Missing("sample")));
+        assert_eq!(
+            decode_ppm::<f64>(b"P3\n99999999999 99999999999\n", false).err(),
+            Some(NetpbmError::TooLarge {
+                width: 99999999999,
+                height: 99999999999
+            })
+        );
+        assert!(matches!(
+            decode_ppm::<f64>(b"PF\n1 1\n-1.0\n", false),
+            Err(NetpbmError::UnsupportedType { .. })
+        ));
     }
 }
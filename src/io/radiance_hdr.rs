@@ -0,0 +1,266 @@
+use std::fs;
+use std::path::Path;
+
+use straal::{FloatType, Vec3};
+
+use crate::error::{Error, Result};
+use crate::io::ImageBuffer;
+
+/// Scanlines outside this width range can't be run length encoded
+const MIN_RLE_WIDTH: usize = 8;
+const MAX_RLE_WIDTH: usize = 0x7fff;
+const MIN_RUN: usize = 4;
+const MAX_RUN: usize = 127;
+const MAX_LITERAL: usize = 128;
+
+/// Shared exponent encoding: three mantissas scaled by a common power of two
+pub fn to_rgbe<T>(v: &Vec3<T>) -> [u8; 4]
+    where
+        T: FloatType<T>,
+{
+    let r: f64 = num::cast::<T, f64>(v.x).unwrap_or(0.0).max(0.0);
+    let g: f64 = num::cast::<T, f64>(v.y).unwrap_or(0.0).max(0.0);
+    let b: f64 = num::cast::<T, f64>(v.z).unwrap_or(0.0).max(0.0);
+    let max = r.max(g).max(b);
+    if max < 1.0e-32 {
+        return [0, 0, 0, 0];
+    }
+
+    //max = mantissa * 2^exponent with the mantissa in [0.5, 1)
+    let mut exponent = max.log2().floor() as i32 + 1;
+    let mut mantissa = max / 2f64.powi(exponent);
+    if mantissa >= 1.0 {
+        exponent += 1;
+        mantissa /= 2.0;
+    } else if mantissa < 0.5 {
+        exponent -= 1;
+        mantissa *= 2.0;
+    }
+    if exponent > 127 {
+        return [255, 255, 255, 255];
+    }
+    let scale = mantissa * 256.0 / max;
+    [(r * scale) as u8, (g * scale) as u8, (b * scale) as u8, (exponent + 128) as u8]
+}
+
+/// Inverse of `to_rgbe`, values are taken from the middle of the range they were rounded from
+pub fn from_rgbe<T>(rgbe: &[u8]) -> Vec3<T>
+    where
+        T: FloatType<T>,
+{
+    if rgbe[3] == 0 {
+        return Vec3::zero();
+    }
+    let scale = 2f64.powi(rgbe[3] as i32 - 136);
+    let c = |v: u8| T::from((v as f64 + 0.5) * scale).unwrap();
+    Vec3::<T> {
+        x: c(rgbe[0]),
+        y: c(rgbe[1]),
+        z: c(rgbe[2]),
+    }
+}
+
+/// Run length encodes one component of a scanline: runs are a count above 128 followed by
+/// the repeated byte, literals a count up to 128 followed by the bytes
+fn write_rle_component(data: &[u8], output: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < data.len() {
+        let mut run_start = i;
+        let mut run_length = 0;
+        while run_start < data.len() {
+            run_length = 1;
+            while run_start + run_length < data.len()
+                && run_length < MAX_RUN
+                && data[run_start + run_length] == data[run_start]
+            {
+                run_length += 1;
+            }
+            if run_length >= MIN_RUN {
+                break;
+            }
+            run_start += run_length;
+        }
+
+        while i < run_start {
+            let count = MAX_LITERAL.min(run_start - i);
+            output.push(count as u8);
+            output.extend_from_slice(&data[i..i + count]);
+            i += count;
+        }
+        if run_start < data.len() {
+            output.push((128 + run_length) as u8);
+            output.push(data[run_start]);
+            i = run_start + run_length;
+        }
+    }
+}
+
+/// Encodes a linear framebuffer, top row first, as a Radiance RGBE image
+pub fn encode_radiance_hdr<T>(pixels: &[Vec3<T>], width: usize, height: usize) -> Vec<u8>
+    where
+        T: FloatType<T>,
+{
+    let mut output = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width).into_bytes();
+    let use_rle = (MIN_RLE_WIDTH..=MAX_RLE_WIDTH).contains(&width);
+    let mut component = Vec::with_capacity(width);
+
+    for y in 0..height {
+        let row: Vec<[u8; 4]> = pixels[y * width..(y + 1) * width].iter().map(to_rgbe).collect();
+        if !use_rle {
+            for rgbe in &row {
+                output.extend_from_slice(rgbe);
+            }
+            continue;
+        }
+        output.extend_from_slice(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8]);
+        for c in 0..4 {
+            component.clear();
+            component.extend(row.iter().map(|rgbe| rgbe[c]));
+            write_rle_component(&component, &mut output);
+        }
+    }
+    output
+}
+
+pub fn write_radiance_hdr_to_path<T>(
+    pixels: &[Vec3<T>],
+    width: usize,
+    height: usize,
+    file_path: &Path,
+) -> Result<()>
+    where
+        T: FloatType<T>,
+{
+    fs::write(file_path, encode_radiance_hdr(pixels, width, height)).map_err(|e| Error::io(file_path, e))
+}
+
+/// Reads one run length encoded component of a scanline into every fourth byte of `row`
+fn read_rle_component(
+    data: &[u8],
+    position: &mut usize,
+    row: &mut [u8],
+    component: usize,
+) -> std::result::Result<(), String> {
+    let width = row.len() / 4;
+    let mut x = 0;
+    let truncated = || "file ends in the middle of a scanline".to_string();
+    while x < width {
+        let count = *data.get(*position).ok_or_else(truncated)? as usize;
+        *position += 1;
+        let (length, is_run) = if count > 128 { (count - 128, true) } else { (count, false) };
+        if length == 0 || x + length > width {
+            return Err("run length encoded scanline has a bad run length".to_string());
+        }
+        for i in 0..length {
+            row[(x + i) * 4 + component] = *data.get(*position).ok_or_else(truncated)?;
+            if !is_run {
+                *position += 1;
+            }
+        }
+        if is_run {
+            *position += 1;
+        }
+        x += length;
+    }
+    Ok(())
+}
+
+/// Reads a scanline stored as plain RGBE pixels, where a pixel of (1, 1, 1, n) repeats the
+/// previous one, the run length encoding of old files
+fn read_flat_scanline(data: &[u8], position: &mut usize, row: &mut [u8]) -> std::result::Result<(), String> {
+    let width = row.len() / 4;
+    let mut x = 0;
+    let mut shift = 0;
+    while x < width {
+        let pixel = data.get(*position..*position + 4).ok_or("file ends in the middle of a scanline")?;
+        *position += 4;
+        if pixel[0] == 1 && pixel[1] == 1 && pixel[2] == 1 && x > 0 {
+            let count = (pixel[3] as usize) << shift;
+            if x + count > width {
+                return Err("repeated pixels run past the end of the scanline".to_string());
+            }
+            let (before, after) = row.split_at_mut(x * 4);
+            for repeated in after[..count * 4].chunks_mut(4) {
+                repeated.copy_from_slice(&before[(x - 1) * 4..x * 4]);
+            }
+            x += count;
+            shift += 8;
+        } else {
+            row[x * 4..x * 4 + 4].copy_from_slice(pixel);
+            x += 1;
+            shift = 0;
+        }
+    }
+    Ok(())
+}
+
+/// Decodes a Radiance RGBE image, run length encoded or not, into linear pixels with the top
+/// row first. Only the usual `-Y height +X width` orientation and its upside down `+Y` variant
+/// are supported.
+pub fn decode_radiance_hdr<T>(bytes: &[u8]) -> std::result::Result<ImageBuffer<T>, String>
+    where
+        T: FloatType<T>,
+{
+    if !bytes.starts_with(b"#?") {
+        return Err("not a Radiance HDR file".to_string());
+    }
+    //Header lines end at an empty line, followed by the resolution line
+    let mut lines = bytes.split(|b| *b == b'\n');
+    let mut position = 0;
+    loop {
+        let line = lines.next().ok_or("file ends in the header")?;
+        position += line.len() + 1;
+        let line = String::from_utf8_lossy(line);
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format.trim() != "32-bit_rle_rgbe" {
+                return Err(format!("unsupported pixel format '{}'", format.trim()));
+            }
+        }
+    }
+    let resolution = String::from_utf8_lossy(lines.next().ok_or("missing resolution line")?).to_string();
+    position += resolution.len() + 1;
+    let fields: Vec<&str> = resolution.split_whitespace().collect();
+    let (flipped, height, width) = match fields.as_slice() {
+        [y, height, "+X", width] if *y == "-Y" || *y == "+Y" => (
+            *y == "+Y",
+            height.parse::<usize>().map_err(|_| format!("invalid height '{}'", height))?,
+            width.parse::<usize>().map_err(|_| format!("invalid width '{}'", width))?,
+        ),
+        _ => return Err(format!("unsupported resolution line '{}'", resolution.trim())),
+    };
+    if width == 0 || height == 0 {
+        return Err("image has no pixels".to_string());
+    }
+    let count = width
+        .checked_mul(height)
+        .filter(|c| c.checked_mul(12).is_some())
+        .ok_or_else(|| format!("image of {}x{} pixels is too large", width, height))?;
+
+    let mut pixels = Vec::with_capacity(count);
+    let mut row = vec![0u8; width * 4];
+    for _ in 0..height {
+        let marker = bytes.get(position..position + 4);
+        let is_rle = (MIN_RLE_WIDTH..=MAX_RLE_WIDTH).contains(&width)
+            && matches!(marker, Some(m) if m[0] == 2 && m[1] == 2 && m[2] & 0x80 == 0);
+        if is_rle {
+            let marker = marker.unwrap();
+            if ((marker[2] as usize) << 8 | marker[3] as usize) != width {
+                return Err("scanline length doesn't match the image width".to_string());
+            }
+            position += 4;
+            for component in 0..4 {
+                read_rle_component(bytes, &mut position, &mut row, component)?;
+            }
+        } else {
+            read_flat_scanline(bytes, &mut position, &mut row)?;
+        }
+        pixels.extend(row.chunks(4).map(from_rgbe));
+    }
+    if flipped {
+        pixels = pixels.chunks(width).rev().flatten().copied().collect();
+    }
+    Ok(ImageBuffer { width, height, pixels })
+}
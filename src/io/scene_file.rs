@@ -0,0 +1,1461 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fmt::Debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::Deserialize;
+use straal::{FloatType, Vec3};
+
+use crate::geometry::*;
+use crate::io::{load_obj, ImageError, ObjError};
+use crate::material::*;
+use crate::math::Camera;
+use crate::sampler::SamplerKind;
+use crate::textures::*;
+use crate::tiles::{Rect, TileOrder};
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        error: toml::de::Error,
+    },
+    Obj(ObjError),
+    Image(ImageError),
+    UnknownMaterial {
+        shape: usize,
+        material: String,
+    },
+    UnknownTexture {
+        context: String,
+        texture: String,
+    },
+    Invalid(String),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io { path, error } => write!(f, "Could not read {}: {}", path.display(), error),
+            SceneError::Parse { path, error } => write!(f, "Could not parse {}: {}", path.display(), error),
+            SceneError::Obj(error) => write!(f, "{}", error),
+            SceneError::Image(error) => write!(f, "{}", error),
+            SceneError::UnknownMaterial { shape, material } => {
+                write!(f, "Shape {} references unknown material '{}'", shape, material)
+            }
+            SceneError::UnknownTexture { context, texture } => {
+                write!(f, "The {} references unknown texture '{}'", context, texture)
+            }
+            SceneError::Invalid(message) => write!(f, "Invalid scene: {}", message),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneError::Io { error, .. } => Some(error),
+            SceneError::Parse { error, .. } => Some(error),
+            SceneError::Obj(error) => Some(error),
+            SceneError::Image(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<ObjError> for SceneError {
+    fn from(error: ObjError) -> Self {
+        SceneError::Obj(error)
+    }
+}
+
+impl From<ImageError> for SceneError {
+    fn from(error: ImageError) -> Self {
+        SceneError::Image(error)
+    }
+}
+
+fn default_up() -> [f64; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_time1() -> f64 {
+    1.0
+}
+
+fn default_srgb() -> bool {
+    true
+}
+
+fn default_detail_strength() -> f64 {
+    1.0
+}
+
+fn default_texture_scale() -> f64 {
+    1.0
+}
+
+fn default_uv_scale() -> [f64; 2] {
+    [1.0, 1.0]
+}
+
+fn default_triplanar_sharpness() -> f64 {
+    4.0
+}
+
+fn default_noise_octaves() -> u32 {
+    1
+}
+
+fn default_noise_colors() -> [[f64; 3]; 2] {
+    [[0.0, 0.0, 0.0], [1.0, 1.0, 1.0]]
+}
+
+fn default_marble_octaves() -> u32 {
+    7
+}
+
+fn default_marble_distortion() -> f64 {
+    4.0
+}
+
+fn default_marble_colors() -> [[f64; 3]; 2] {
+    [[0.9, 0.9, 0.88], [0.25, 0.25, 0.3]]
+}
+
+fn default_wood_octaves() -> u32 {
+    4
+}
+
+fn default_wood_distortion() -> f64 {
+    0.5
+}
+
+fn default_wood_colors() -> [[f64; 3]; 2] {
+    [[0.75, 0.55, 0.33], [0.42, 0.26, 0.13]]
+}
+
+/// Camera parameters, as accepted by `Camera::new`. The aspect ratio follows from the resolution
+/// and the focus distance defaults to the distance between `look_from` and `look_at`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDescription {
+    pub look_from: [f64; 3],
+    pub look_at: [f64; 3],
+    #[serde(default = "default_up")]
+    pub up: [f64; 3],
+    pub vertical_fov: f64,
+    #[serde(default)]
+    pub aperture: f64,
+    #[serde(default)]
+    pub focus_distance: Option<f64>,
+    #[serde(default)]
+    pub time0: f64,
+    #[serde(default = "default_time1")]
+    pub time1: f64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    /// Samples per pixel, the maximum when sampling adaptively
+    pub samples: u32,
+    pub max_depth: u32,
+    /// Bounces after which paths are terminated by Russian roulette
+    pub roulette_depth: u32,
+    /// Hits closer than this to a ray's origin are ignored, scale it with the scene
+    pub ray_epsilon: f64,
+    /// Sample lights directly at every diffuse bounce, turning it off gives the brute force
+    /// estimate to compare against
+    pub light_sampling: bool,
+    /// How the sample values of a pixel are spread out: independent, stratified, halton or sobol
+    pub sampler: SamplerKind,
+    /// Stop sampling pixels once they converged, see `AdaptiveSettings`
+    pub adaptive: Option<AdaptiveSettings>,
+    /// Edge length of the square tiles the image is divided into for the render threads
+    pub tile_size: usize,
+    pub tile_order: TileOrder,
+    /// Only render `[x, y, width, height]` of the image, counted from the top left pixel
+    pub crop: Option<[usize; 4]>,
+}
+
+/// Adaptive sampling: every pixel takes at least `min_samples`, then keeps sampling until the
+/// standard error of its mean luminance is below `threshold` times the mean, or it reaches the
+/// `samples` of the render settings
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdaptiveSettings {
+    pub min_samples: u32,
+    pub threshold: f64,
+}
+
+impl Default for AdaptiveSettings {
+    fn default() -> Self {
+        AdaptiveSettings {
+            min_samples: 16,
+            threshold: 0.01,
+        }
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            width: 600,
+            height: 480,
+            samples: 50,
+            max_depth: 50,
+            roulette_depth: 5,
+            ray_epsilon: 0.01,
+            light_sampling: true,
+            sampler: SamplerKind::default(),
+            adaptive: None,
+            tile_size: 32,
+            tile_order: TileOrder::default(),
+            crop: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TextureDescription {
+    Constant {
+        color: [f64; 3],
+    },
+    /// Alternates between two textures in cells of 1 / `scale`
+    Checker {
+        even: TextureReference,
+        odd: TextureReference,
+        #[serde(default = "default_texture_scale")]
+        scale: f64,
+        #[serde(default)]
+        mapping: CheckerMapping,
+    },
+    /// Perlin noise between two colours, with `turbulence` summing the absolute value of every octave
+    Noise {
+        #[serde(default = "default_texture_scale")]
+        scale: f64,
+        #[serde(default = "default_noise_octaves")]
+        octaves: u32,
+        #[serde(default)]
+        turbulence: bool,
+        #[serde(default = "default_noise_colors")]
+        colors: [[f64; 3]; 2],
+        #[serde(default)]
+        seed: u64,
+    },
+    Marble {
+        #[serde(default = "default_texture_scale")]
+        scale: f64,
+        #[serde(default = "default_marble_octaves")]
+        octaves: u32,
+        #[serde(default = "default_marble_distortion")]
+        distortion: f64,
+        #[serde(default = "default_marble_colors")]
+        colors: [[f64; 3]; 2],
+        #[serde(default)]
+        seed: u64,
+    },
+    /// Rings around the y axis
+    Wood {
+        #[serde(default = "default_texture_scale")]
+        scale: f64,
+        #[serde(default = "default_wood_octaves")]
+        octaves: u32,
+        #[serde(default = "default_wood_distortion")]
+        distortion: f64,
+        #[serde(default = "default_wood_colors")]
+        colors: [[f64; 3]; 2],
+        #[serde(default)]
+        seed: u64,
+    },
+    /// PNG, PPM, PFM or Radiance HDR file, relative to the scene file. Turn off `srgb` for maps
+    /// that hold data rather than colours, like roughness.
+    Image {
+        file: String,
+        #[serde(default)]
+        wrap: WrapMode,
+        #[serde(default)]
+        filter: TextureFilter,
+        #[serde(default = "default_srgb")]
+        srgb: bool,
+    },
+    /// Another texture times `factor`
+    Scale {
+        texture: TextureReference,
+        factor: f64,
+    },
+    Add {
+        textures: Vec<TextureReference>,
+    },
+    /// Product of the textures, per channel
+    Multiply {
+        textures: Vec<TextureReference>,
+    },
+    /// `layer` over `base` where `mask` is one
+    Mix {
+        base: TextureReference,
+        layer: TextureReference,
+        mask: TextureReference,
+    },
+    /// Maps the value of `input` to colours, interpolating between the stops around it
+    Ramp {
+        input: TextureReference,
+        stops: Vec<RampStop>,
+    },
+    /// Looks `texture` up at texture coordinates that are scaled, rotated counterclockwise by
+    /// `rotation` degrees and offset, in that order
+    UvTransform {
+        texture: TextureReference,
+        #[serde(default)]
+        offset: [f64; 2],
+        #[serde(default = "default_uv_scale")]
+        scale: [f64; 2],
+        #[serde(default)]
+        rotation: f64,
+    },
+    /// Projects `texture` along the three axes, repeating `scale` times per unit of distance, and
+    /// blends the projections by the surface normal
+    Triplanar {
+        texture: TextureReference,
+        #[serde(default = "default_texture_scale")]
+        scale: f64,
+        #[serde(default = "default_triplanar_sharpness")]
+        sharpness: f64,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RampStop {
+    pub position: f64,
+    pub color: [f64; 3],
+}
+
+/// Material inputs are a colour, a single value for all channels, the name of a texture from the
+/// `textures` table or an inline texture
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum TextureReference {
+    Value(f64),
+    Color([f64; 3]),
+    Named(String),
+    Inline(Box<TextureDescription>),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum MaterialDescription {
+    Lambertian {
+        albedo: TextureReference,
+        #[serde(default)]
+        detail: Option<SurfaceDetailDescription>,
+    },
+    Metal {
+        albedo: TextureReference,
+        roughness: TextureReference,
+        #[serde(default)]
+        detail: Option<SurfaceDetailDescription>,
+    },
+    Dielectric {
+        refractive_index: f64,
+        #[serde(default)]
+        detail: Option<SurfaceDetailDescription>,
+    },
+    DiffuseLight { emit: TextureReference },
+}
+
+/// Normal or bump map shading a material as if its surface had more relief than the geometry
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SurfaceDetailDescription {
+    /// Tangent space normals, which should be read with `srgb = false` when they come from an image
+    NormalMap {
+        normals: TextureReference,
+        #[serde(default = "default_detail_strength")]
+        strength: f64,
+    },
+    /// Heights in world units, times `strength`
+    BumpMap {
+        height: TextureReference,
+        #[serde(default = "default_detail_strength")]
+        strength: f64,
+    },
+}
+
+/// Shapes either refer to a material from the `materials` table by name or define one inline
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum MaterialReference {
+    Named(String),
+    Inline(MaterialDescription),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ShapeDescription {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: MaterialReference,
+    },
+    MovingSphere {
+        center0: [f64; 3],
+        center1: [f64; 3],
+        #[serde(default)]
+        time0: f64,
+        #[serde(default = "default_time1")]
+        time1: f64,
+        radius: f64,
+        material: MaterialReference,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        material: MaterialReference,
+    },
+    /// Parallelogram spanned by two edges from a corner, facing along `edge_u` x `edge_v`
+    Quad {
+        corner: [f64; 3],
+        edge_u: [f64; 3],
+        edge_v: [f64; 3],
+        material: MaterialReference,
+    },
+    /// Wavefront .obj file, relative to the scene file. The material is used for faces that
+    /// don't have one assigned by the .mtl file.
+    Mesh {
+        file: String,
+        material: MaterialReference,
+    },
+}
+
+/// Either "sky" for the default gradient or a constant colour
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum BackgroundDescription {
+    Named(String),
+    Color([f64; 3]),
+}
+
+impl Default for BackgroundDescription {
+    fn default() -> Self {
+        BackgroundDescription::Named(String::from("sky"))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneDescription {
+    pub camera: CameraDescription,
+    #[serde(default)]
+    pub background: BackgroundDescription,
+    #[serde(default)]
+    pub render: RenderSettings,
+    #[serde(default)]
+    pub textures: HashMap<String, TextureDescription>,
+    #[serde(default)]
+    pub materials: HashMap<String, MaterialDescription>,
+    #[serde(default)]
+    pub shapes: Vec<ShapeDescription>,
+}
+
+/// What textures are built from: the textures of the `textures` table built so far, the
+/// directory image files are relative to and the images loaded so far
+pub struct TextureContext<'a, T> {
+    pub named: HashMap<&'a str, Arc<dyn Texture<T>>>,
+    pub base_directory: &'a Path,
+    pub cache: TextureCache<T>,
+}
+
+impl<'a, T> TextureContext<'a, T>
+    where
+        T: FloatType<T>,
+{
+    pub fn new(base_directory: &'a Path) -> TextureContext<'a, T> {
+        TextureContext {
+            named: HashMap::new(),
+            base_directory,
+            cache: TextureCache::new(),
+        }
+    }
+}
+
+pub struct LoadedScene<T> {
+    pub scene: HittableScene<T>,
+    pub camera: Camera<T>,
+    pub settings: RenderSettings,
+    /// The files besides the scene description the scene was built from
+    pub source_files: Vec<PathBuf>,
+}
+
+fn to_vec3<T>(a: [f64; 3]) -> Vec3<T> where T: FloatType<T> {
+    Vec3::<T>::new(a[0], a[1], a[2])
+}
+
+fn length(a: [f64; 3]) -> f64 {
+    (a[0] * a[0] + a[1] * a[1] + a[2] * a[2]).sqrt()
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn invalid(message: String) -> SceneError {
+    SceneError::Invalid(message)
+}
+
+impl CameraDescription {
+    pub fn validate(&self) -> Result<(), SceneError> {
+        let view = [
+            self.look_from[0] - self.look_at[0],
+            self.look_from[1] - self.look_at[1],
+            self.look_from[2] - self.look_at[2],
+        ];
+        if length(view) == 0.0 {
+            return Err(invalid("camera look_from and look_at are the same point".to_string()));
+        }
+        if length(self.up) == 0.0 {
+            return Err(invalid("camera up vector has zero length".to_string()));
+        }
+        if length(cross(self.up, view)) == 0.0 {
+            return Err(invalid("camera up vector is parallel to the view direction".to_string()));
+        }
+        if self.vertical_fov <= 0.0 || self.vertical_fov >= 180.0 {
+            return Err(invalid(format!("camera vertical_fov must be between 0 and 180, got {}", self.vertical_fov)));
+        }
+        if self.aperture < 0.0 {
+            return Err(invalid(format!("camera aperture can't be negative, got {}", self.aperture)));
+        }
+        if let Some(d) = self.focus_distance {
+            if d <= 0.0 {
+                return Err(invalid(format!("camera focus_distance must be positive, got {}", d)));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_camera<T>(&self, aspect_ratio: T) -> Camera<T> where T: FloatType<T> {
+        let look_from = to_vec3::<T>(self.look_from);
+        let look_at = to_vec3::<T>(self.look_at);
+        let focus_distance = match self.focus_distance {
+            Some(d) => T::from(d).unwrap(),
+            None => Vec3::distance(look_from, look_at),
+        };
+        Camera::<T>::new(
+            look_from,
+            look_at,
+            to_vec3(self.up),
+            T::from(self.vertical_fov).unwrap(),
+            aspect_ratio,
+            T::from(self.aperture).unwrap(),
+            focus_distance,
+            T::from(self.time0).unwrap(),
+            T::from(self.time1).unwrap(),
+        )
+    }
+}
+
+impl RenderSettings {
+    /// Hash of the scene's files and of the settings that change what a sample looks like, so
+    /// checkpoints aren't resumed with a different scene or settings. `scene_source` holds the
+    /// contents of the scene description and every file it references. The settings are hashed
+    /// on their own because command line options override the file. The sample count is left
+    /// out, it may be raised to keep refining, except for the stratified sampler whose strata
+    /// depend on it.
+    pub fn fingerprint(&self, scene_source: &[u8]) -> u64 {
+        let strata = match self.sampler {
+            SamplerKind::Stratified => format!(" for {} samples", self.samples),
+            _ => String::new(),
+        };
+        let description = format!(
+            "{}x{} crop {:?} depth {} roulette {} epsilon {} light sampling {} sampler {}{}",
+            self.width,
+            self.height,
+            self.crop,
+            self.max_depth,
+            self.roulette_depth,
+            self.ray_epsilon,
+            self.light_sampling,
+            self.sampler,
+            strata
+        );
+        //64-bit FNV-1a, stable across builds unlike the standard library's hasher
+        scene_source
+            .iter()
+            .chain(description.as_bytes())
+            .fold(0xcbf2_9ce4_8422_2325u64, |hash, b| (hash ^ *b as u64).wrapping_mul(0x0100_0000_01b3))
+    }
+
+    /// The pixels to render, the crop window or else the whole image
+    pub fn film_area(&self) -> Rect {
+        match self.crop {
+            Some([x, y, width, height]) => Rect { x, y, width, height },
+            None => Rect {
+                x: 0,
+                y: 0,
+                width: self.width,
+                height: self.height,
+            },
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), SceneError> {
+        if self.width == 0 || self.height == 0 {
+            return Err(invalid(format!("resolution must be at least 1x1, got {}x{}", self.width, self.height)));
+        }
+        if self.tile_size == 0 {
+            return Err(invalid("tile_size must be at least 1".to_string()));
+        }
+        if let Some([x, y, width, height]) = self.crop {
+            let inside = matches!(x.checked_add(width), Some(right) if right <= self.width)
+                && matches!(y.checked_add(height), Some(bottom) if bottom <= self.height);
+            if width == 0 || height == 0 || !inside {
+                return Err(invalid(format!(
+                    "crop [{}, {}, {}, {}] must be a non-empty part of the {}x{} image",
+                    x, y, width, height, self.width, self.height
+                )));
+            }
+        }
+        if self.samples == 0 {
+            return Err(invalid("samples must be at least 1".to_string()));
+        }
+        if self.max_depth == 0 {
+            return Err(invalid("max_depth must be at least 1".to_string()));
+        }
+        if !(self.ray_epsilon >= 0.0 && self.ray_epsilon.is_finite()) {
+            return Err(invalid(format!("ray_epsilon must be a non-negative number, got {}", self.ray_epsilon)));
+        }
+        if let Some(adaptive) = &self.adaptive {
+            if adaptive.min_samples < 2 || adaptive.min_samples > self.samples {
+                return Err(invalid(format!(
+                    "adaptive min_samples must be between 2 and samples ({}), got {}",
+                    self.samples, adaptive.min_samples
+                )));
+            }
+            if !(adaptive.threshold > 0.0 && adaptive.threshold.is_finite()) {
+                return Err(invalid(format!("adaptive threshold must be a positive number, got {}", adaptive.threshold)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TextureDescription {
+    pub fn validate(&self, context: &str, textures: &HashMap<String, TextureDescription>) -> Result<(), SceneError> {
+        let finite = |what: &str, values: &[f64]| {
+            if values.iter().all(|v| v.is_finite()) {
+                Ok(())
+            } else {
+                Err(invalid(format!("{} needs a finite {}", context, what)))
+            }
+        };
+        let (scale, octaves) = match self {
+            TextureDescription::Constant { .. } => return Ok(()),
+            TextureDescription::Image { file, .. } => {
+                if file.is_empty() {
+                    return Err(invalid(format!("{} needs an image file", context)));
+                }
+                return Ok(());
+            }
+            TextureDescription::Checker { even, odd, scale, .. } => {
+                even.validate(context, textures)?;
+                odd.validate(context, textures)?;
+                (*scale, 1)
+            }
+            TextureDescription::Noise { scale, octaves, .. }
+            | TextureDescription::Marble { scale, octaves, .. }
+            | TextureDescription::Wood { scale, octaves, .. } => (*scale, *octaves),
+            TextureDescription::Scale { texture, factor } => {
+                finite("factor", &[*factor])?;
+                return texture.validate(context, textures);
+            }
+            TextureDescription::Add { textures: inputs } | TextureDescription::Multiply { textures: inputs } => {
+                if inputs.is_empty() {
+                    return Err(invalid(format!("{} needs at least one texture", context)));
+                }
+                for input in inputs {
+                    input.validate(context, textures)?;
+                }
+                return Ok(());
+            }
+            TextureDescription::Mix { base, layer, mask } => {
+                base.validate(context, textures)?;
+                layer.validate(context, textures)?;
+                return mask.validate(context, textures);
+            }
+            TextureDescription::Ramp { input, stops } => {
+                if stops.is_empty() {
+                    return Err(invalid(format!("{} needs at least one stop", context)));
+                }
+                let positions: Vec<f64> = stops.iter().map(|s| s.position).collect();
+                finite("position for every stop", &positions)?;
+                return input.validate(context, textures);
+            }
+            TextureDescription::UvTransform { texture, offset, scale, rotation } => {
+                finite("offset, scale and rotation", &[offset[0], offset[1], scale[0], scale[1], *rotation])?;
+                return texture.validate(context, textures);
+            }
+            TextureDescription::Triplanar { texture, scale, sharpness } => {
+                if !(*sharpness >= 0.0 && sharpness.is_finite()) {
+                    return Err(invalid(format!("{} needs a non-negative sharpness, got {}", context, sharpness)));
+                }
+                texture.validate(context, textures)?;
+                (*scale, 1)
+            }
+        };
+        if !(scale > 0.0 && scale.is_finite()) {
+            return Err(invalid(format!("{} needs a positive scale, got {}", context, scale)));
+        }
+        if octaves == 0 || octaves > 16 {
+            return Err(invalid(format!("{} needs between 1 and 16 octaves, got {}", context, octaves)));
+        }
+        Ok(())
+    }
+
+    /// Adds the names of the textures from the `textures` table this one is built from
+    fn references<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            TextureDescription::Checker { even, odd, .. } => {
+                even.references(names);
+                odd.references(names);
+            }
+            TextureDescription::Add { textures } | TextureDescription::Multiply { textures } => {
+                for texture in textures {
+                    texture.references(names);
+                }
+            }
+            TextureDescription::Mix { base, layer, mask } => {
+                base.references(names);
+                layer.references(names);
+                mask.references(names);
+            }
+            TextureDescription::Scale { texture, .. }
+            | TextureDescription::UvTransform { texture, .. }
+            | TextureDescription::Triplanar { texture, .. }
+            | TextureDescription::Ramp { input: texture, .. } => texture.references(names),
+            TextureDescription::Constant { .. }
+            | TextureDescription::Image { .. }
+            | TextureDescription::Noise { .. }
+            | TextureDescription::Marble { .. }
+            | TextureDescription::Wood { .. } => {}
+        }
+    }
+
+    /// `context` holds at least the textures this one references, by name
+    pub fn to_texture<T>(&self, context: &mut TextureContext<T>) -> Result<Arc<dyn Texture<T>>, SceneError>
+        where
+            T: FloatType<T> + Send + Sync + 'static,
+    {
+        let colors = |c: &[[f64; 3]; 2]| [to_vec3(c[0]), to_vec3(c[1])];
+        let texture: Arc<dyn Texture<T>> = match self {
+            TextureDescription::Constant { color } => Arc::new(ConstantTexture::new(&to_vec3(*color))),
+            TextureDescription::Checker { even, odd, scale, mapping } => Arc::new(CheckerTexture::new(
+                even.to_texture(context)?,
+                odd.to_texture(context)?,
+                T::from(*scale).unwrap(),
+                *mapping,
+            )),
+            TextureDescription::Image { file, wrap, filter, srgb } => {
+                let mip_map = context.cache.load(&context.base_directory.join(file), *srgb)?;
+                Arc::new(ImageTexture::new(mip_map, *wrap, *filter))
+            }
+            TextureDescription::Noise { scale, octaves, turbulence, colors: c, seed } => Arc::new(NoiseTexture::new(
+                *seed,
+                T::from(*scale).unwrap(),
+                *octaves,
+                *turbulence,
+                colors(c),
+            )),
+            TextureDescription::Marble { scale, octaves, distortion, colors: c, seed } => Arc::new(MarbleTexture::new(
+                *seed,
+                T::from(*scale).unwrap(),
+                *octaves,
+                T::from(*distortion).unwrap(),
+                colors(c),
+            )),
+            TextureDescription::Wood { scale, octaves, distortion, colors: c, seed } => Arc::new(WoodTexture::new(
+                *seed,
+                T::from(*scale).unwrap(),
+                *octaves,
+                T::from(*distortion).unwrap(),
+                colors(c),
+            )),
+            TextureDescription::Scale { texture, factor } => {
+                Arc::new(ScaleTexture::new(texture.to_texture(context)?, T::from(*factor).unwrap()))
+            }
+            TextureDescription::Add { textures } => Arc::new(AddTexture::new(
+                textures.iter().map(|t| t.to_texture(context)).collect::<Result<_, _>>()?,
+            )),
+            TextureDescription::Multiply { textures } => Arc::new(MultiplyTexture::new(
+                textures.iter().map(|t| t.to_texture(context)).collect::<Result<_, _>>()?,
+            )),
+            TextureDescription::Mix { base, layer, mask } => Arc::new(MixTexture::new(
+                base.to_texture(context)?,
+                layer.to_texture(context)?,
+                mask.to_texture(context)?,
+            )),
+            TextureDescription::Ramp { input, stops } => Arc::new(RampTexture::new(
+                input.to_texture(context)?,
+                stops.iter().map(|s| (T::from(s.position).unwrap(), to_vec3(s.color))).collect(),
+            )),
+            TextureDescription::UvTransform { texture, offset, scale, rotation } => {
+                let pair = |a: &[f64; 2]| (T::from(a[0]).unwrap(), T::from(a[1]).unwrap());
+                Arc::new(UvTransformTexture::new(
+                    texture.to_texture(context)?,
+                    pair(offset),
+                    pair(scale),
+                    T::from(*rotation).unwrap(),
+                ))
+            }
+            TextureDescription::Triplanar { texture, scale, sharpness } => Arc::new(TriplanarTexture::new(
+                texture.to_texture(context)?,
+                T::from(*scale).unwrap(),
+                T::from(*sharpness).unwrap(),
+            )),
+        };
+        Ok(texture)
+    }
+}
+
+impl TextureReference {
+    pub fn validate(&self, context: &str, textures: &HashMap<String, TextureDescription>) -> Result<(), SceneError> {
+        match self {
+            TextureReference::Value(_) | TextureReference::Color(_) => Ok(()),
+            TextureReference::Named(name) => {
+                if textures.contains_key(name) {
+                    Ok(())
+                } else {
+                    Err(SceneError::UnknownTexture {
+                        context: context.to_string(),
+                        texture: name.clone(),
+                    })
+                }
+            }
+            TextureReference::Inline(t) => t.validate(&format!("inline texture of the {}", context), textures),
+        }
+    }
+
+    fn references<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            TextureReference::Value(_) | TextureReference::Color(_) => {}
+            TextureReference::Named(name) => names.push(name),
+            TextureReference::Inline(t) => t.references(names),
+        }
+    }
+
+    /// Whether a constant has a negative channel, other textures can only be checked per sample
+    fn is_negative(&self) -> bool {
+        match self {
+            TextureReference::Value(v) => *v < 0.0,
+            TextureReference::Color(c) => c.iter().any(|x| *x < 0.0),
+            TextureReference::Inline(t) => {
+                matches!(t.as_ref(), TextureDescription::Constant { color } if color.iter().any(|x| *x < 0.0))
+            }
+            TextureReference::Named(_) => false,
+        }
+    }
+
+    pub fn to_texture<T>(&self, context: &mut TextureContext<T>) -> Result<Arc<dyn Texture<T>>, SceneError>
+        where
+            T: FloatType<T> + Send + Sync + 'static,
+    {
+        match self {
+            TextureReference::Value(v) => Ok(Arc::new(ConstantTexture::value(T::from(*v).unwrap()))),
+            TextureReference::Color(c) => Ok(Arc::new(ConstantTexture::new(&to_vec3(*c)))),
+            TextureReference::Named(name) => Ok(context.named[name.as_str()].clone()),
+            TextureReference::Inline(t) => t.to_texture(context),
+        }
+    }
+}
+
+impl SurfaceDetailDescription {
+    pub fn validate(&self, context: &str, textures: &HashMap<String, TextureDescription>) -> Result<(), SceneError> {
+        let (texture, strength) = match self {
+            SurfaceDetailDescription::NormalMap { normals, strength } => (normals, strength),
+            SurfaceDetailDescription::BumpMap { height, strength } => (height, strength),
+        };
+        texture.validate(context, textures)?;
+        if !strength.is_finite() {
+            return Err(invalid(format!("{} needs a finite detail strength", context)));
+        }
+        Ok(())
+    }
+
+    pub fn to_detail<T>(&self, context: &mut TextureContext<T>) -> Result<SurfaceDetail<T>, SceneError>
+        where
+            T: FloatType<T> + Send + Sync + 'static,
+    {
+        let detail = match self {
+            SurfaceDetailDescription::NormalMap { normals, strength } => SurfaceDetail::NormalMap {
+                normals: normals.to_texture(context)?,
+                strength: T::from(*strength).unwrap(),
+            },
+            SurfaceDetailDescription::BumpMap { height, strength } => SurfaceDetail::BumpMap {
+                height: height.to_texture(context)?,
+                strength: T::from(*strength).unwrap(),
+            },
+        };
+        Ok(detail)
+    }
+}
+
+impl MaterialDescription {
+    fn detail(&self) -> Option<&SurfaceDetailDescription> {
+        match self {
+            MaterialDescription::Lambertian { detail, .. }
+            | MaterialDescription::Metal { detail, .. }
+            | MaterialDescription::Dielectric { detail, .. } => detail.as_ref(),
+            MaterialDescription::DiffuseLight { .. } => None,
+        }
+    }
+
+    pub fn validate(&self, context: &str, textures: &HashMap<String, TextureDescription>) -> Result<(), SceneError> {
+        if let Some(detail) = self.detail() {
+            detail.validate(context, textures)?;
+        }
+        match self {
+            MaterialDescription::Lambertian { albedo, .. } => albedo.validate(context, textures),
+            MaterialDescription::Metal { albedo, roughness, .. } => {
+                albedo.validate(context, textures)?;
+                roughness.validate(context, textures)?;
+                if roughness.is_negative() {
+                    Err(invalid(format!("{} has a negative roughness", context)))
+                } else {
+                    Ok(())
+                }
+            }
+            MaterialDescription::Dielectric { refractive_index, .. } => {
+                if *refractive_index <= 0.0 {
+                    Err(invalid(format!("{} needs a positive refractive_index", context)))
+                } else {
+                    Ok(())
+                }
+            }
+            MaterialDescription::DiffuseLight { emit } => {
+                emit.validate(context, textures)?;
+                if emit.is_negative() {
+                    Err(invalid(format!("{} can't emit negative light", context)))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// `context` holds the textures of the `textures` table, by name
+    pub fn to_material<T>(&self, context: &mut TextureContext<T>) -> Result<Arc<dyn Material<T>>, SceneError>
+        where
+            T: FloatType<T> + Send + Sync + 'static,
+    {
+        let material: Arc<dyn Material<T>> = match self {
+            MaterialDescription::Lambertian { albedo, .. } => {
+                Arc::new(LambertianMaterial::with_texture(albedo.to_texture(context)?))
+            }
+            MaterialDescription::Metal { albedo, roughness, .. } => Arc::new(MetalMaterial::with_textures(
+                albedo.to_texture(context)?,
+                roughness.to_texture(context)?,
+            )),
+            MaterialDescription::Dielectric { refractive_index, .. } => {
+                Arc::new(DielectricMaterial::create(T::from(*refractive_index).unwrap()))
+            }
+            MaterialDescription::DiffuseLight { emit } => {
+                Arc::new(DiffuseLight::with_texture(emit.to_texture(context)?))
+            }
+        };
+        match self.detail() {
+            Some(detail) => Ok(Arc::new(DetailedMaterial::new(material, detail.to_detail(context)?))),
+            None => Ok(material),
+        }
+    }
+}
+
+impl SceneDescription {
+    pub fn parse(contents: &str, path: &Path) -> Result<SceneDescription, SceneError> {
+        toml::from_str(contents).map_err(|error| SceneError::Parse {
+            path: path.to_path_buf(),
+            error,
+        })
+    }
+
+    pub fn validate(&self) -> Result<(), SceneError> {
+        self.camera.validate()?;
+        self.render.validate()?;
+        if let BackgroundDescription::Named(name) = &self.background {
+            if name != "sky" {
+                return Err(invalid(format!("unknown background '{}', expected \"sky\" or a colour", name)));
+            }
+        }
+        for (name, texture) in &self.textures {
+            texture.validate(&format!("texture '{}'", name), &self.textures)?;
+        }
+        self.texture_order()?;
+        for (name, material) in &self.materials {
+            material.validate(&format!("material '{}'", name), &self.textures)?;
+        }
+        for (i, shape) in self.shapes.iter().enumerate() {
+            let material = match shape {
+                ShapeDescription::Sphere { radius, material, .. } => {
+                    if *radius <= 0.0 {
+                        return Err(invalid(format!("shape {} has a non-positive radius {}", i, radius)));
+                    }
+                    material
+                }
+                ShapeDescription::MovingSphere { radius, time0, time1, material, .. } => {
+                    if *radius <= 0.0 {
+                        return Err(invalid(format!("shape {} has a non-positive radius {}", i, radius)));
+                    }
+                    if time1 <= time0 {
+                        return Err(invalid(format!("shape {} needs time1 to be after time0", i)));
+                    }
+                    material
+                }
+                ShapeDescription::Triangle { material, .. } => material,
+                ShapeDescription::Quad { edge_u, edge_v, material, .. } => {
+                    if length(cross(*edge_u, *edge_v)) == 0.0 {
+                        return Err(invalid(format!("shape {} has parallel or zero length edges", i)));
+                    }
+                    material
+                }
+                ShapeDescription::Mesh { material, .. } => material,
+            };
+            match material {
+                MaterialReference::Named(name) => {
+                    if !self.materials.contains_key(name) {
+                        return Err(SceneError::UnknownMaterial {
+                            shape: i,
+                            material: name.clone(),
+                        });
+                    }
+                }
+                MaterialReference::Inline(m) => m.validate(&format!("inline material of shape {}", i), &self.textures)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Names from the `textures` table in an order where every texture comes after the ones it
+    /// references, so they can be built one by one
+    fn texture_order(&self) -> Result<Vec<&str>, SceneError> {
+        let mut order: Vec<&str> = Vec::with_capacity(self.textures.len());
+        let mut remaining: Vec<(&str, Vec<&str>)> = self
+            .textures
+            .iter()
+            .map(|(name, texture)| {
+                let mut references = Vec::new();
+                texture.references(&mut references);
+                (name.as_str(), references)
+            })
+            .collect();
+        while !remaining.is_empty() {
+            let count = remaining.len();
+            remaining.retain(|(name, references)| {
+                let ready = references.iter().all(|r| order.contains(r));
+                if ready {
+                    order.push(*name);
+                }
+                !ready
+            });
+            if remaining.len() == count {
+                let mut names: Vec<&str> = remaining.iter().map(|(name, _)| *name).collect();
+                names.sort_unstable();
+                return Err(invalid(format!("textures {} reference each other in a cycle", names.join(", "))));
+            }
+        }
+        Ok(order)
+    }
+
+    /// Builds the scene, camera and settings. `base_directory` is used to resolve mesh and image
+    /// files.
+    pub fn build<T>(&self, base_directory: &Path) -> Result<LoadedScene<T>, SceneError>
+        where
+            T: FloatType<T> + Debug + Send + Sync + 'static,
+    {
+        self.validate()?;
+
+        //Textures, images and materials are shared between everything that references them
+        let mut context = TextureContext::new(base_directory);
+        for name in self.texture_order()? {
+            let texture = self.textures[name].to_texture(&mut context)?;
+            context.named.insert(name, texture);
+        }
+        let mut materials: HashMap<&str, Arc<dyn Material<T>>> = HashMap::new();
+        for (name, material) in &self.materials {
+            materials.insert(name.as_str(), material.to_material(&mut context)?);
+        }
+        let resolve = |reference: &MaterialReference, context: &mut TextureContext<T>| {
+            match reference {
+                MaterialReference::Named(name) => Ok(materials[name.as_str()].clone()),
+                MaterialReference::Inline(m) => m.to_material(context),
+            }
+        };
+
+        let mut scene = HittableScene::<T>::new();
+        let mut source_files = Vec::new();
+        scene.background = match &self.background {
+            BackgroundDescription::Named(_) => Background::Sky,
+            BackgroundDescription::Color(color) => Background::Color(to_vec3(*color)),
+        };
+        for shape in &self.shapes {
+            match shape {
+                ShapeDescription::Sphere { center, radius, material } => {
+                    let material = resolve(material, &mut context)?;
+                    let emissive = material.is_emissive();
+                    let sphere = Arc::new(Sphere {
+                        center: to_vec3(*center),
+                        radius: T::from(*radius).unwrap(),
+                        material,
+                    });
+                    if emissive {
+                        scene.add_light(sphere);
+                    } else {
+                        scene.add_hittable(sphere);
+                    }
+                }
+                ShapeDescription::MovingSphere { center0, center1, time0, time1, radius, material } => {
+                    scene.add_hittable(Arc::new(MovableSphere {
+                        center0: to_vec3(*center0),
+                        center1: to_vec3(*center1),
+                        time0: T::from(*time0).unwrap(),
+                        time1: T::from(*time1).unwrap(),
+                        radius: T::from(*radius).unwrap(),
+                        material: resolve(material, &mut context)?,
+                    }));
+                }
+                ShapeDescription::Triangle { vertices, material } => {
+                    let mesh = MeshData::new(
+                        vertices.iter().map(|v| to_vec3(*v)).collect(),
+                        Vec::new(),
+                        Vec::new(),
+                        vec![[0, 1, 2]],
+                        resolve(material, &mut context)?,
+                    );
+                    scene.add_mesh(&Arc::new(mesh));
+                }
+                ShapeDescription::Quad { corner, edge_u, edge_v, material } => {
+                    let corner = to_vec3::<T>(*corner);
+                    let edge_u = to_vec3::<T>(*edge_u);
+                    let edge_v = to_vec3::<T>(*edge_v);
+                    let mesh = MeshData::new(
+                        vec![corner, corner + edge_u, corner + edge_u + edge_v, corner + edge_v],
+                        Vec::new(),
+                        vec![(T::zero(), T::zero()), (T::one(), T::zero()), (T::one(), T::one()), (T::zero(), T::one())],
+                        vec![[0, 1, 2], [0, 2, 3]],
+                        resolve(material, &mut context)?,
+                    );
+                    scene.add_mesh(&Arc::new(mesh));
+                }
+                ShapeDescription::Mesh { file, material } => {
+                    let obj_path = base_directory.join(file);
+                    let material = resolve(material, &mut context)?;
+                    let model = load_obj(&obj_path, material, &mut context.cache)?;
+                    for (_, mesh) in &model.meshes {
+                        scene.add_mesh(mesh);
+                    }
+                    source_files.push(obj_path);
+                    source_files.extend(model.mtl_files);
+                }
+            }
+        }
+        source_files.extend(context.cache.paths());
+
+        let aspect_ratio = T::from(self.render.width).unwrap() / T::from(self.render.height).unwrap();
+        Ok(LoadedScene {
+            scene,
+            camera: self.camera.to_camera(aspect_ratio),
+            settings: self.render.clone(),
+            source_files,
+        })
+    }
+}
+
+/// Reads and parses a scene description file, without validating or building it yet
+pub fn load_scene_description(path: &Path) -> Result<SceneDescription, SceneError> {
+    let contents = fs::read_to_string(path).map_err(|error| SceneError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    SceneDescription::parse(&contents, path)
+}
+
+/// Reads, validates and builds a scene description file
+pub fn load_scene<T>(path: &Path) -> Result<LoadedScene<T>, SceneError>
+    where
+        T: FloatType<T> + Debug + Send + Sync + 'static,
+{
+    let description = load_scene_description(path)?;
+    description.build(path.parent().unwrap_or_else(|| Path::new("")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAMERA: &str = "[camera]\nlook_from = [0, 0, 5]\nlook_at = [0, 0, 0]\nvertical_fov = 40\n";
+
+    fn parse(source: &str) -> Result<SceneDescription, SceneError> {
+        SceneDescription::parse(&format!("{}{}", CAMERA, source), Path::new("test.toml"))
+    }
+
+    fn invalid_message(source: &str) -> String {
+        match parse(source).and_then(|description| description.validate()) {
+            Err(SceneError::Invalid(message)) => message,
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(()) => panic!("validation should have failed"),
+        }
+    }
+
+    #[test]
+    fn material_references_are_names_or_inline_tables() {
+        let source = "[materials.red]\ntype = \"lambertian\"\nalbedo = [1, 0, 0]\n\n\
+            [[shapes]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"red\"\n\n\
+            [[shapes]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\n\
+            material = { type = \"metal\", albedo = [1, 1, 1], roughness = 0.5 }\n";
+        let description = parse(source).unwrap();
+        description.validate().unwrap();
+        let material = |i: usize| match &description.shapes[i] {
+            ShapeDescription::Sphere { material, .. } => material.clone(),
+            _ => panic!("shape {} should be a sphere", i),
+        };
+        assert!(matches!(material(0), MaterialReference::Named(ref name) if name == "red"));
+        assert!(matches!(material(1), MaterialReference::Inline(MaterialDescription::Metal { .. })));
+    }
+
+    #[test]
+    fn material_inputs_are_values_colors_or_textures() {
+        let source = "[textures.grey]\ntype = \"constant\"\ncolor = [0.5, 0.5, 0.5]\n\n\
+            [materials.brushed]\ntype = \"metal\"\nalbedo = \"grey\"\nroughness = 0.2\n\n\
+            [materials.lamp]\ntype = \"diffuse_light\"\nemit = { type = \"constant\", color = [4, 4, 4] }\n";
+        let description = parse(source).unwrap();
+        description.validate().unwrap();
+        match &description.materials["brushed"] {
+            MaterialDescription::Metal { albedo, roughness, .. } => {
+                assert!(matches!(albedo, TextureReference::Named(ref name) if name == "grey"));
+                assert!(matches!(roughness, TextureReference::Value(v) if *v == 0.2));
+            }
+            _ => panic!("brushed should be a metal"),
+        }
+        match &description.materials["lamp"] {
+            MaterialDescription::DiffuseLight { emit: TextureReference::Inline(texture) } => {
+                assert!(matches!(**texture, TextureDescription::Constant { .. }));
+            }
+            _ => panic!("lamp should be a light with an inline texture"),
+        }
+    }
+
+    #[test]
+    fn bad_texture_inputs_are_rejected() {
+        let source = "[materials.red]\ntype = \"lambertian\"\nalbedo = \"missing\"\n";
+        match parse(source).unwrap().validate() {
+            Err(SceneError::UnknownTexture { context, texture }) => {
+                assert_eq!(context, "material 'red'");
+                assert_eq!(texture, "missing");
+            }
+            _ => panic!("the texture name should have been rejected"),
+        }
+        let metal = "[materials.m]\ntype = \"metal\"\nalbedo = 1\nroughness = ";
+        assert!(invalid_message(&format!("{}-0.5\n", metal)).contains("negative roughness"));
+        assert!(invalid_message(&format!("{}[0.1, -0.1, 0.1]\n", metal)).contains("negative roughness"));
+        let light = "[materials.l]\ntype = \"diffuse_light\"\nemit = ";
+        let negative = format!("{}{{ type = \"constant\", color = [1, -1, 1] }}\n", light);
+        assert!(invalid_message(&negative).contains("negative light"));
+        let misspelled = "[textures.t]\ntype = \"constant\"\ncolour = [1, 1, 1]\n";
+        assert!(matches!(parse(misspelled), Err(SceneError::Parse { .. })));
+    }
+
+    #[test]
+    fn procedural_textures_are_validated() {
+        let textures = "[textures.white]\ntype = \"constant\"\ncolor = [1, 1, 1]\n\n\
+            [textures.board]\ntype = \"checker\"\neven = \"white\"\nodd = [0, 0, 0]\nmapping = \"uv\"\n\n\
+            [textures.stone]\ntype = \"marble\"\nseed = 7\n\n\
+            [materials.floor]\ntype = \"lambertian\"\nalbedo = \"board\"\n";
+        let description = parse(textures).unwrap();
+        description.validate().unwrap();
+        assert!(description.build::<f64>(Path::new("")).is_ok());
+
+        let texture = |fields: &str| invalid_message(&format!("[textures.t]\n{}\n", fields));
+        assert!(texture("type = \"noise\"\nscale = 0").contains("texture 't' needs a positive scale"));
+        assert!(texture("type = \"wood\"\nscale = -1").contains("positive scale"));
+        assert!(texture("type = \"marble\"\noctaves = 0").contains("between 1 and 16 octaves"));
+        assert!(texture("type = \"noise\"\noctaves = 17").contains("between 1 and 16 octaves"));
+        assert!(texture("type = \"checker\"\neven = 1\nodd = 0\nscale = inf").contains("positive scale"));
+
+        let cycle = "[textures.a]\ntype = \"checker\"\neven = \"b\"\nodd = 0\n\n\
+            [textures.b]\ntype = \"checker\"\neven = 1\nodd = \"a\"\n";
+        assert!(invalid_message(cycle).contains("textures a, b reference each other in a cycle"));
+        let missing = "[textures.a]\ntype = \"checker\"\neven = \"b\"\nodd = 0\n";
+        assert!(matches!(parse(missing).unwrap().validate(), Err(SceneError::UnknownTexture { .. })));
+    }
+
+    #[test]
+    fn texture_graphs_are_validated() {
+        let graph = "[textures.noise]\ntype = \"noise\"\n\n\
+            [textures.veins]\ntype = \"ramp\"\ninput = \"noise\"\n\
+            stops = [{ position = 0, color = [0, 0, 0] }, { position = 1, color = [1, 1, 1] }]\n\n\
+            [textures.tiled]\ntype = \"uv_transform\"\ntexture = \"veins\"\nscale = [4, 4]\nrotation = 30\n\n\
+            [textures.sum]\ntype = \"add\"\ntextures = [\"tiled\", [0.1, 0, 0]]\n\n\
+            [textures.mixed]\ntype = \"mix\"\nbase = \"sum\"\nmask = \"veins\"\n\
+            layer = { type = \"scale\", texture = \"noise\", factor = 2 }\n\n\
+            [textures.projected]\ntype = \"triplanar\"\n\
+            texture = { type = \"multiply\", textures = [\"mixed\", 0.5] }\n\n\
+            [materials.stone]\ntype = \"lambertian\"\nalbedo = \"projected\"\n";
+        let description = parse(graph).unwrap();
+        description.validate().unwrap();
+        assert!(description.build::<f64>(Path::new("")).is_ok());
+
+        let texture = |fields: &str| invalid_message(&format!("[textures.t]\n{}\n", fields));
+        assert!(texture("type = \"add\"\ntextures = []").contains("texture 't' needs at least one texture"));
+        assert!(texture("type = \"multiply\"\ntextures = []").contains("needs at least one texture"));
+        assert!(texture("type = \"ramp\"\ninput = 1\nstops = []").contains("needs at least one stop"));
+        let stop = "type = \"ramp\"\ninput = 1\nstops = [{ position = nan, color = [1, 1, 1] }]";
+        assert!(texture(stop).contains("needs a finite position for every stop"));
+        assert!(texture("type = \"scale\"\ntexture = 1\nfactor = inf").contains("needs a finite factor"));
+        let transform = "type = \"uv_transform\"\ntexture = 1\nrotation = nan";
+        assert!(texture(transform).contains("needs a finite offset, scale and rotation"));
+        let triplanar = "type = \"triplanar\"\ntexture = 1\nsharpness = -1";
+        assert!(texture(triplanar).contains("needs a non-negative sharpness"));
+        assert!(texture("type = \"triplanar\"\ntexture = 1\nscale = 0").contains("needs a positive scale"));
+
+        //Inputs nested in the graph are checked too, including against cycles
+        let nested = "[textures.t]\ntype = \"mix\"\nbase = 0\nlayer = 1\nmask = { type = \"noise\", octaves = 0 }\n";
+        assert!(invalid_message(nested).contains("between 1 and 16 octaves"));
+        let cycle = "[textures.a]\ntype = \"scale\"\ntexture = \"b\"\nfactor = 2\n\n\
+            [textures.b]\ntype = \"add\"\ntextures = [1, \"a\"]\n";
+        assert!(invalid_message(cycle).contains("reference each other in a cycle"));
+        let missing = "[textures.t]\ntype = \"ramp\"\ninput = \"missing\"\n\
+            stops = [{ position = 0, color = [1, 1, 1] }]\n";
+        assert!(matches!(parse(missing).unwrap().validate(), Err(SceneError::UnknownTexture { .. })));
+    }
+
+    #[test]
+    fn image_textures_need_a_readable_file() {
+        let image = |fields: &str| format!("[textures.t]\ntype = \"image\"\n{}\n", fields);
+        assert!(invalid_message(&image("file = \"\"")).contains("texture 't' needs an image file"));
+        assert!(matches!(parse(&image("file = \"a.png\"\nwrap = \"tile\"")), Err(SceneError::Parse { .. })));
+        assert!(matches!(parse(&image("file = \"a.png\"\nfilter = \"cubic\"")), Err(SceneError::Parse { .. })));
+
+        let source = image("file = \"missing.png\"\nwrap = \"mirror\"\nfilter = \"nearest\"\nsrgb = false");
+        let description = parse(&source).unwrap();
+        description.validate().unwrap();
+        assert!(matches!(description.build::<f64>(Path::new("")), Err(SceneError::Image(ImageError::Io { .. }))));
+    }
+
+    #[test]
+    fn unknown_material_names_are_rejected() {
+        let source = "[[shapes]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"missing\"\n";
+        match parse(source).unwrap().validate() {
+            Err(SceneError::UnknownMaterial { shape, material }) => {
+                assert_eq!(shape, 0);
+                assert_eq!(material, "missing");
+            }
+            _ => panic!("the material name should have been rejected"),
+        }
+    }
+
+    #[test]
+    fn unknown_fields_and_types_are_parse_errors() {
+        assert!(matches!(parse("[render]\nsamplez = 10\n"), Err(SceneError::Parse { .. })));
+        let source = "[[shapes]]\ntype = \"cube\"\ncenter = [0, 0, 0]\nmaterial = \"red\"\n";
+        assert!(matches!(parse(source), Err(SceneError::Parse { .. })));
+    }
+
+    #[test]
+    fn bad_settings_are_rejected() {
+        assert!(invalid_message("[render]\nwidth = 0\n").contains("resolution"));
+        assert!(invalid_message("[render]\nsamples = 0\n").contains("samples"));
+        assert!(invalid_message("[render]\nmax_depth = 0\n").contains("max_depth"));
+        let sphere = "[[shapes]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\n";
+        let radius = format!("{}radius = 0\nmaterial = {{ type = \"dielectric\", refractive_index = 1.5 }}\n", sphere);
+        assert!(invalid_message(&radius).contains("radius"));
+        let index = format!("{}radius = 1\nmaterial = {{ type = \"dielectric\", refractive_index = 0 }}\n", sphere);
+        assert!(invalid_message(&index).contains("refractive_index"));
+    }
+
+    #[test]
+    fn adaptive_settings() {
+        let description = parse("[render]\nsamples = 64\n\n[render.adaptive]\nthreshold = 0.05\n").unwrap();
+        description.validate().unwrap();
+        let adaptive = description.render.adaptive.unwrap();
+        assert_eq!((adaptive.min_samples, adaptive.threshold), (16, 0.05));
+        assert!(parse("").unwrap().render.adaptive.is_none());
+
+        assert!(invalid_message("[render]\nsamples = 8\n\n[render.adaptive]\n").contains("min_samples"));
+        assert!(invalid_message("[render.adaptive]\nmin_samples = 1\n").contains("min_samples"));
+        assert!(invalid_message("[render.adaptive]\nthreshold = 0\n").contains("threshold"));
+        assert!(invalid_message("[render.adaptive]\nthreshold = inf\n").contains("threshold"));
+    }
+
+    #[test]
+    fn crop_windows_lie_within_the_image() {
+        let description = parse("[render]\nwidth = 8\nheight = 6\ncrop = [2, 1, 6, 5]\n").unwrap();
+        description.validate().unwrap();
+        let area = description.render.film_area();
+        assert_eq!((area.x, area.y, area.width, area.height), (2, 1, 6, 5));
+        let full = parse("[render]\nwidth = 8\nheight = 6\n").unwrap().render.film_area();
+        assert_eq!((full.x, full.y, full.width, full.height), (0, 0, 8, 6));
+
+        let far = "[9223372036854775807, 0, 1, 1]";
+        for crop in &["[2, 1, 7, 5]", "[2, 1, 6, 6]", "[0, 0, 0, 6]", "[0, 0, 8, 0]", far] {
+            let message = invalid_message(&format!("[render]\nwidth = 8\nheight = 6\ncrop = {}\n", crop));
+            assert!(message.contains("must be a non-empty part of the 8x6 image"), "{}: {}", crop, message);
+        }
+        assert!(invalid_message("[render]\ntile_size = 0\n").contains("tile_size"));
+    }
+
+    #[test]
+    fn bad_cameras_are_rejected() {
+        let camera = |extra: &str| {
+            let source = format!("[camera]\nlook_from = [0, 0, 5]\n{}", extra);
+            let description: SceneDescription = toml::from_str(&source).unwrap();
+            description.camera.validate().is_err()
+        };
+        assert!(!camera("look_at = [0, 0, 0]\nvertical_fov = 40\n"));
+        assert!(camera("look_at = [0, 0, 5]\nvertical_fov = 40\n"));
+        assert!(camera("look_at = [0, 0, 0]\nvertical_fov = 180\n"));
+        assert!(camera("look_at = [0, 0, 0]\nvertical_fov = 40\nup = [0, 0, 1]\n"));
+        assert!(camera("look_at = [0, 0, 0]\nvertical_fov = 40\naperture = -1\n"));
+        assert!(camera("look_at = [0, 0, 0]\nvertical_fov = 40\nfocus_distance = 0\n"));
+    }
+
+    #[test]
+    fn fingerprint_covers_the_scene_but_not_the_sample_count() {
+        let settings = RenderSettings::default();
+        let fingerprint = settings.fingerprint(b"[camera]");
+        assert_ne!(fingerprint, settings.fingerprint(b"[camera] "));
+        let more_samples = RenderSettings {
+            samples: settings.samples * 2,
+            ..RenderSettings::default()
+        };
+        assert_eq!(fingerprint, more_samples.fingerprint(b"[camera]"));
+        let deeper = RenderSettings {
+            max_depth: settings.max_depth + 1,
+            ..RenderSettings::default()
+        };
+        assert_ne!(fingerprint, deeper.fingerprint(b"[camera]"));
+        let cropped = RenderSettings {
+            crop: Some([0, 0, 10, 10]),
+            ..RenderSettings::default()
+        };
+        assert_ne!(fingerprint, cropped.fingerprint(b"[camera]"));
+
+        //The strata of the stratified sampler are laid out for the total sample count
+        let stratified = |samples: u32| RenderSettings {
+            samples,
+            sampler: SamplerKind::Stratified,
+            ..RenderSettings::default()
+        };
+        assert_ne!(stratified(16).fingerprint(b"[camera]"), stratified(32).fingerprint(b"[camera]"));
+    }
+}
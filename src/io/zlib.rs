@@ -0,0 +1,589 @@
+//! Just enough of zlib (RFC 1950) and deflate (RFC 1951) to read and write PNG files.
+//! Data is compressed with LZ77 and the fixed Huffman codes, and falls back to stored blocks
+//! when that doesn't make it any smaller. Any valid stream can be decompressed.
+
+/// Base match length for the length symbols 257 to 285
+pub const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+pub const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// Base distance for the distance symbols 0 to 29
+pub const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097,
+    6145, 8193, 12289, 16385, 24577,
+];
+pub const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_SIZE: usize = 1 << 15;
+/// Number of earlier positions with the same hash that are tried before settling on a match
+const MAX_CHAIN: usize = 64;
+const MAX_STORED_BLOCK: usize = 65535;
+/// Order in which the code lengths of the code length alphabet are stored in dynamic blocks
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+const MAX_CODE_LENGTH: usize = 15;
+
+pub fn adler32(data: &[u8]) -> u32 {
+    const MODULO: u32 = 65521;
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+    //Sums of 5552 bytes can't overflow before the modulo is taken
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= MODULO;
+        b %= MODULO;
+    }
+    (b << 16) | a
+}
+
+/// Wraps `data` in a zlib stream
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    let mut output = vec![0x78, 0x9c];
+    output.extend_from_slice(&deflate(data));
+    output.extend_from_slice(&adler32(data).to_be_bytes());
+    output
+}
+
+/// Raw deflate stream of `data`
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let compressed = deflate_fixed(data);
+    //Noisy data doesn't compress, and the 9 bit literals would make it grow
+    let stored_size = data.len() + 5 * (data.len() / MAX_STORED_BLOCK + 1);
+    if compressed.len() < stored_size {
+        compressed
+    } else {
+        deflate_stored(data)
+    }
+}
+
+/// Writes bits least significant first, as deflate expects
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            bytes: Vec::new(),
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn write_bits(&mut self, value: u32, count: u32) {
+        self.buffer |= value << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Huffman codes are stored starting at their most significant bit
+    fn write_code(&mut self, code: u32, length: u32) {
+        let reversed = code.reverse_bits() >> (32 - length);
+        self.write_bits(reversed, length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+fn write_fixed_literal(writer: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => writer.write_code(0x30 + symbol, 8),
+        144..=255 => writer.write_code(0x190 + symbol - 144, 9),
+        256..=279 => writer.write_code(symbol - 256, 7),
+        _ => writer.write_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_fixed_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let length_index = (0..LENGTH_BASE.len()).rev().find(|i| LENGTH_BASE[*i] as usize <= length).unwrap();
+    write_fixed_literal(writer, 257 + length_index as u32);
+    writer.write_bits(
+        (length - LENGTH_BASE[length_index] as usize) as u32,
+        LENGTH_EXTRA_BITS[length_index] as u32,
+    );
+
+    let distance_index = (0..DISTANCE_BASE.len()).rev().find(|i| DISTANCE_BASE[*i] as usize <= distance).unwrap();
+    writer.write_code(distance_index as u32, 5);
+    writer.write_bits(
+        (distance - DISTANCE_BASE[distance_index] as usize) as u32,
+        DISTANCE_EXTRA_BITS[distance_index] as u32,
+    );
+}
+
+/// Remembers where every 3 byte sequence occurred, chained by hash, to find earlier matches
+struct MatchFinder {
+    head: Vec<usize>,
+    previous: Vec<usize>,
+}
+
+impl MatchFinder {
+    fn new() -> MatchFinder {
+        MatchFinder {
+            head: vec![usize::MAX; HASH_SIZE],
+            previous: vec![usize::MAX; WINDOW_SIZE],
+        }
+    }
+
+    fn hash(data: &[u8], i: usize) -> usize {
+        ((data[i] as usize) << 10 ^ (data[i + 1] as usize) << 5 ^ data[i + 2] as usize) & (HASH_SIZE - 1)
+    }
+
+    fn insert(&mut self, data: &[u8], i: usize) {
+        if i + MIN_MATCH <= data.len() {
+            let h = MatchFinder::hash(data, i);
+            self.previous[i % WINDOW_SIZE] = self.head[h];
+            self.head[h] = i;
+        }
+    }
+
+    /// Length and distance of the longest earlier match for the data at `i`
+    fn longest_match(&self, data: &[u8], i: usize) -> (usize, usize) {
+        let max_length = MAX_MATCH.min(data.len() - i);
+        if max_length < MIN_MATCH {
+            return (0, 0);
+        }
+        let mut best = (0, 0);
+        let mut candidate = self.head[MatchFinder::hash(data, i)];
+        let mut chain = 0;
+        while candidate != usize::MAX && i - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+            let length = (0..max_length).take_while(|k| data[candidate + *k] == data[i + *k]).count();
+            if length > best.0 {
+                best = (length, i - candidate);
+                if length == max_length {
+                    break;
+                }
+            }
+            candidate = self.previous[candidate % WINDOW_SIZE];
+            chain += 1;
+        }
+        best
+    }
+}
+
+/// A single block with the fixed Huffman codes, matches are chosen greedily
+fn deflate_fixed(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    //Final block, fixed Huffman codes
+    writer.write_bits(1, 1);
+    writer.write_bits(1, 2);
+
+    let mut matches = MatchFinder::new();
+    let mut i = 0;
+    while i < data.len() {
+        let (length, distance) = matches.longest_match(data, i);
+        if length >= MIN_MATCH {
+            write_fixed_match(&mut writer, length, distance);
+            for k in i..i + length {
+                matches.insert(data, k);
+            }
+            i += length;
+        } else {
+            write_fixed_literal(&mut writer, data[i] as u32);
+            matches.insert(data, i);
+            i += 1;
+        }
+    }
+
+    //End of block
+    write_fixed_literal(&mut writer, 256);
+    writer.finish()
+}
+
+fn deflate_stored(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len() + 5 * (data.len() / MAX_STORED_BLOCK + 1));
+    let mut chunks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if chunks.peek().is_none() {
+        //An empty stream still needs a final block
+        output.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let is_final = chunks.peek().is_none();
+        //The three header bits are padded to a full byte
+        output.push(is_final as u8);
+        let length = chunk.len() as u16;
+        output.extend_from_slice(&length.to_le_bytes());
+        output.extend_from_slice(&(!length).to_le_bytes());
+        output.extend_from_slice(chunk);
+    }
+    output
+}
+
+/// Unwraps a zlib stream and checks its checksum
+pub fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 6 {
+        return Err("zlib stream is too short".to_string());
+    }
+    let (cmf, flags) = (data[0], data[1]);
+    if cmf & 0x0f != 8 || (cmf as u16 * 256 + flags as u16) % 31 != 0 {
+        return Err("not a deflate compressed zlib stream".to_string());
+    }
+    if flags & 0x20 != 0 {
+        return Err("zlib streams with a preset dictionary are not supported".to_string());
+    }
+    let (output, consumed) = inflate_with_length(&data[2..])?;
+    match data.get(2 + consumed..2 + consumed + 4) {
+        Some(checksum) if checksum == adler32(&output).to_be_bytes() => Ok(output),
+        Some(_) => Err("zlib checksum mismatch, the data is corrupt".to_string()),
+        None => Err("zlib stream ends before its checksum".to_string()),
+    }
+}
+
+/// Decompresses a raw deflate stream
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    inflate_with_length(data).map(|(output, _)| output)
+}
+
+/// The decompressed data and the number of bytes of `data` the stream took up
+fn inflate_with_length(data: &[u8]) -> Result<(Vec<u8>, usize), String> {
+    let mut reader = BitReader::new(data);
+    let mut output = Vec::with_capacity(data.len() * 4);
+    loop {
+        let is_final = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => inflate_stored(&mut reader, &mut output)?,
+            1 => {
+                let (literals, distances) = fixed_codes();
+                inflate_block(&mut reader, &mut output, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut output, &literals, &distances)?;
+            }
+            _ => return Err("invalid deflate block type".to_string()),
+        }
+        if is_final {
+            break;
+        }
+    }
+    reader.align_to_byte();
+    Ok((output, reader.byte_position()))
+}
+
+/// Reads bits least significant first, the counterpart of `BitWriter`
+struct BitReader<'a> {
+    data: &'a [u8],
+    /// Next byte of `data` to move into the buffer, may run past the end, which reads zeros
+    position: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data,
+            position: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    /// Fills the buffer to at least 57 bits. Past the end of the data that takes zeros, which
+    /// are fine as long as they are never consumed.
+    fn refill(&mut self) -> Result<(), String> {
+        while self.count <= 56 {
+            let byte = self.data.get(self.position).copied().unwrap_or(0);
+            self.buffer |= (byte as u64) << self.count;
+            self.position += 1;
+            self.count += 8;
+        }
+        if self.byte_position() > self.data.len() {
+            return Err("unexpected end of compressed data".to_string());
+        }
+        Ok(())
+    }
+
+    fn consume(&mut self, count: u32) {
+        self.buffer >>= count;
+        self.count -= count;
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, String> {
+        if self.count < count {
+            self.refill()?;
+        }
+        let value = (self.buffer & ((1u64 << count) - 1)) as u32;
+        self.consume(count);
+        if self.byte_position() > self.data.len() {
+            return Err("unexpected end of compressed data".to_string());
+        }
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.consume(self.count % 8);
+    }
+
+    /// Index of the byte the next unread bit is in
+    fn byte_position(&self) -> usize {
+        self.position - (self.count as usize).div_ceil(8)
+    }
+}
+
+/// Canonical Huffman code, decoded one bit at a time by counting codes per length (as in
+/// zlib's puff)
+struct HuffmanCode {
+    counts: [u16; MAX_CODE_LENGTH + 1],
+    /// Symbols ordered by code
+    symbols: Vec<u16>,
+}
+
+impl HuffmanCode {
+    fn new(lengths: &[u8]) -> Result<HuffmanCode, String> {
+        let mut counts = [0u16; MAX_CODE_LENGTH + 1];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        //Codes that don't fit in their lengths can't be decoded, incomplete codes are allowed
+        let mut left: i32 = 1;
+        for count in &counts[1..] {
+            left = left * 2 - *count as i32;
+            if left < 0 {
+                return Err("invalid Huffman code lengths".to_string());
+            }
+        }
+
+        let mut offsets = [0u16; MAX_CODE_LENGTH + 2];
+        for length in 1..=MAX_CODE_LENGTH {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0u16; offsets[MAX_CODE_LENGTH + 1] as usize];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length > 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+        counts[0] = 0;
+        Ok(HuffmanCode { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, String> {
+        if reader.count < MAX_CODE_LENGTH as u32 {
+            reader.refill()?;
+        }
+        //`first` is the first code of the current length, `index` the index of its symbol
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for length in 1..=MAX_CODE_LENGTH {
+            code |= ((reader.buffer >> (length - 1)) & 1) as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                reader.consume(length as u32);
+                if reader.byte_position() > reader.data.len() {
+                    return Err("unexpected end of compressed data".to_string());
+                }
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code in compressed data".to_string())
+    }
+}
+
+fn fixed_codes() -> (HuffmanCode, HuffmanCode) {
+    let mut lengths = [0u8; 288];
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    //These tables are valid, building them can't fail
+    (HuffmanCode::new(&lengths).unwrap(), HuffmanCode::new(&[5; 30]).unwrap())
+}
+
+fn read_dynamic_codes(reader: &mut BitReader) -> Result<(HuffmanCode, HuffmanCode), String> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err("too many codes in dynamic Huffman block".to_string());
+    }
+
+    let mut code_length_lengths = [0u8; 19];
+    for i in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_length_lengths[*i] = reader.bits(3)? as u8;
+    }
+    let code_lengths = HuffmanCode::new(&code_length_lengths)?;
+
+    //Literal and distance code lengths form one sequence, repeats may cross from one to the other
+    let total = literal_count + distance_count;
+    let mut lengths = Vec::with_capacity(total);
+    while lengths.len() < total {
+        let symbol = code_lengths.decode(reader)?;
+        let (length, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => match lengths.last() {
+                Some(previous) => (*previous, 3 + reader.bits(2)? as usize),
+                None => return Err("code length repeat without a previous length".to_string()),
+            },
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if lengths.len() + repeat > total {
+            return Err("code length repeats run past the number of codes".to_string());
+        }
+        lengths.extend(std::iter::repeat(length).take(repeat));
+    }
+    if lengths[256] == 0 {
+        return Err("dynamic Huffman block has no end of block code".to_string());
+    }
+    Ok((HuffmanCode::new(&lengths[..literal_count])?, HuffmanCode::new(&lengths[literal_count..])?))
+}
+
+fn inflate_stored(reader: &mut BitReader, output: &mut Vec<u8>) -> Result<(), String> {
+    reader.align_to_byte();
+    let length = reader.bits(16)? as usize;
+    let complement = reader.bits(16)? as usize;
+    if length != !complement & 0xffff {
+        return Err("stored block length doesn't match its complement".to_string());
+    }
+    //Copy straight from the data rather than through the bit buffer
+    let start = reader.byte_position();
+    let bytes = reader
+        .data
+        .get(start..start + length)
+        .ok_or_else(|| "unexpected end of compressed data".to_string())?;
+    output.extend_from_slice(bytes);
+    reader.position = start + length;
+    reader.buffer = 0;
+    reader.count = 0;
+    Ok(())
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    literals: &HuffmanCode,
+    distances: &HuffmanCode,
+) -> Result<(), String> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        if symbol < 256 {
+            output.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+        let length_index = symbol - 257;
+        if length_index >= LENGTH_BASE.len() {
+            return Err(format!("invalid length symbol {}", symbol));
+        }
+        let length = LENGTH_BASE[length_index] as usize + reader.bits(LENGTH_EXTRA_BITS[length_index] as u32)? as usize;
+        let distance_index = distances.decode(reader)? as usize;
+        if distance_index >= DISTANCE_BASE.len() {
+            return Err(format!("invalid distance symbol {}", distance_index));
+        }
+        let distance =
+            DISTANCE_BASE[distance_index] as usize + reader.bits(DISTANCE_EXTRA_BITS[distance_index] as u32)? as usize;
+        if distance > output.len() {
+            return Err("match refers to data before the start of the stream".to_string());
+        }
+        //Matches may overlap the bytes they produce, so they are copied a byte at a time
+        let start = output.len() - distance;
+        for i in start..start + length {
+            let byte = output[i];
+            output.push(byte);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adler32_matches_known_values() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn adler32_takes_the_modulo_on_long_input() {
+        //Long enough to overflow the sums if the modulo were only taken at the end
+        let data = vec![0xff; 100_000];
+        let (mut a, mut b) = (1u64, 0u64);
+        for byte in &data {
+            a = (a + *byte as u64) % 65521;
+            b = (b + a) % 65521;
+        }
+        assert_eq!(adler32(&data), ((b << 16) | a) as u32);
+    }
+
+    #[test]
+    fn zlib_streams_carry_the_header_and_checksum() {
+        let data = b"abcabcabcabcabcabcabc";
+        let compressed = zlib_compress(data);
+        //Deflate with a 32K window and no dictionary, the check bits make the header a multiple of 31
+        assert_eq!(compressed[0], 0x78);
+        assert_eq!(((compressed[0] as u16) << 8 | compressed[1] as u16) % 31, 0);
+        assert_eq!(compressed[compressed.len() - 4..], adler32(data).to_be_bytes());
+    }
+
+    #[test]
+    fn compressed_data_round_trips() {
+        //A simple generator, so some of the data doesn't compress and ends up in stored blocks
+        let mut state = 1u32;
+        let noise: Vec<u8> = (0..70_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 24) as u8
+            })
+            .collect();
+        let repetitive: Vec<u8> = b"straaljager ".iter().cycle().take(100_000).cloned().collect();
+        let inputs: [&[u8]; 5] = [b"", b"a", b"hello hello hello hello", &repetitive, &noise];
+        for data in inputs.iter() {
+            let compressed = zlib_compress(data);
+            assert_eq!(zlib_decompress(&compressed).unwrap(), *data);
+        }
+        assert!(zlib_compress(&repetitive).len() < repetitive.len() / 10);
+    }
+
+    #[test]
+    fn decompresses_a_dynamic_huffman_block() {
+        //zlib's output for this input with the Huffman only strategy, a single dynamic block
+        let compressed = [
+            0x78, 0x01, 0x05, 0xc1, 0x01, 0x01, 0x00, 0x30, 0x0c, 0xc3, 0x20, 0xad, 0xa4, 0xbb, 0x7f, 0x0b, 0x07,
+            0x00, 0xaa, 0xda, 0x76, 0xf7, 0x3e, 0x57, 0xfa, 0x08, 0x0b,
+        ];
+        assert_eq!(compressed[2] >> 1 & 3, 2);
+        assert_eq!(zlib_decompress(&compressed).unwrap(), b"aaaaaaaaaabbbbbcccdde");
+    }
+
+    #[test]
+    fn rejects_corrupt_streams() {
+        let mut compressed = zlib_compress(b"hello hello hello hello");
+        assert!(zlib_decompress(&compressed[..compressed.len() - 1]).is_err());
+        let last = compressed.len() - 1;
+        compressed[last] ^= 1;
+        assert!(zlib_decompress(&compressed).is_err());
+        assert!(zlib_decompress(&[0x78, 0x9d]).is_err());
+    }
+}
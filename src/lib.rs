@@ -0,0 +1,14 @@
+pub mod error;
+pub mod film;
+pub mod geometry;
+pub mod integrator;
+pub mod io;
+pub mod material;
+pub mod math;
+pub mod renderer;
+pub mod sampler;
+pub mod textures;
+pub mod tiles;
+
+pub use error::{Error, Result};
+pub use renderer::*;
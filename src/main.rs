@@ -1,197 +1,245 @@
-use std::sync::Arc;
-use std::time::Instant;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant};
 
-use rand::Rng;
-use rayon::prelude::*;
-use straal::*;
+use straaljager::film::Film;
+use straaljager::geometry::*;
+use straaljager::io::*;
+use straaljager::math::*;
+use straaljager::{Error, Renderer, Result};
 
-use crate::geometry::*;
-use crate::geometry::Hittable;
-use crate::io::*;
-use crate::material::*;
-use crate::math::*;
+use crate::cli::{Command, Options, OutputFormat};
 
-pub mod geometry;
-pub mod io;
-pub mod material;
-pub mod math;
-pub mod textures;
+mod cli;
 
 type Precision = f32;
 
 fn main() {
+    let options = match cli::parse_args(env::args().skip(1)) {
+        Ok(Command::Render(options)) => *options,
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+
+    if let Some(threads) = options.threads {
+        if let Err(e) = rayon::ThreadPoolBuilder::new().num_threads(threads).build_global() {
+            eprintln!("Could not set up the render threads: {}", e);
+            process::exit(1);
+        }
+    }
+
+    if let Err(e) = render(&options) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn render(options: &Options) -> Result<()> {
     //Timer
     let start_time = Instant::now();
 
-    let mut scene = set_up_scene();
-    let bvh = BvhNode::<Precision>::new(&mut scene.hittable_list[..], 0.0, 1.0);
-
-    //Setting up the output image settings
-    let samples = 50;
-    let image_width = 600;
-    let image_height = 480;
-
-    let camera_pos = Vec3::new(8, 2, 3);
-    let camera_target = Vec3::new(0.0, 0.0, 0.0);
-    let focus_distance = Vec3::distance(camera_pos, camera_target);
-    let aperture = 0.2;
-
-    let camera = Camera::<Precision>::new(
-        camera_pos,
-        camera_target,
-        Vec3::up(),
-        40.0,
-        image_width as Precision / image_height as Precision,
-        aperture,
-        focus_distance,
-        0.0,
-        1.0,
-    );
-    let row_coords: Vec<usize> = (0..image_height).rev().collect();
-
-    let mut rows: Vec<Vec<Vec3<Precision>>> = Vec::with_capacity(image_height);
-
-    row_coords
-        .par_iter()
-        .map(|j| {
-            let mut rng = rand::thread_rng();
-            let row: Vec<Vec3<Precision>> = (0..image_width)
-                .map(|i| {
-                    let average: Vec3<Precision> = (0..samples)
-                        .map(|_s| {
-                            let u = (i as Precision + rng.gen_range(-0.5, 0.5))
-                                / image_width as Precision;
-                            let v = (*j as Precision + rng.gen_range(-0.5, 0.5))
-                                / image_height as Precision;
-                            get_ray_color(&camera.get_ray(u, v), &bvh, 0)
-                        })
-                        .sum();
-                    let res = average / samples as Precision;
-                    gamma_color(&res)
-                })
-                .collect();
-            println!("Row {} done", j);
-            row
-        })
-        .collect_into_vec(&mut rows);
-
-    //Frame buffer
-    let mut pixels = Vec::with_capacity(image_width * image_height);
-
-    for mut row in rows {
-        pixels.append(&mut row);
-    }
-
-    println!("{}", duration_to_string(&start_time.elapsed()));
-
-    write_ppm_file(&pixels, image_width, image_height, None);
+    let mut description = load_scene_description(&options.scene_path)?;
+    if let Some((width, height)) = options.resolution {
+        description.render.width = width;
+        description.render.height = height;
+    }
+    if let Some(samples) = options.samples {
+        description.render.samples = samples;
+    }
+    if let Some(max_depth) = options.max_depth {
+        description.render.max_depth = max_depth;
+    }
+    if options.no_light_sampling {
+        description.render.light_sampling = false;
+    }
+    if let Some(crop) = options.crop {
+        description.render.crop = Some(crop);
+    }
+    if let Some(tile_size) = options.tile_size {
+        description.render.tile_size = tile_size;
+    }
+    if let Some(tile_order) = options.tile_order {
+        description.render.tile_order = tile_order;
+    }
+    if let Some(sampler) = options.sampler {
+        description.render.sampler = sampler;
+    }
+    if let Some(threshold) = options.adaptive_threshold {
+        let adaptive = description.render.adaptive.get_or_insert_with(AdaptiveSettings::default);
+        adaptive.threshold = threshold;
+        adaptive.min_samples = adaptive.min_samples.min(description.render.samples).max(2);
+    }
+
+    let base_directory = options.scene_path.parent().unwrap_or_else(|| Path::new(""));
+    let LoadedScene { scene, camera, settings, source_files } = description.build::<Precision>(base_directory)?;
+    if !options.quiet {
+        println!("Scene set up.");
+    }
+
+    if options.benchmark_bvh {
+        return benchmark_bvh(&scene, &camera, &settings);
+    }
+
+    let mut renderer = Renderer::new(scene, camera, settings)?;
+    if let Some(seed) = options.seed {
+        renderer = renderer.with_seed(seed);
+    }
+    if !options.quiet {
+        println!("{}", renderer.bvh_stats());
+    }
+
+    let path = match &options.output {
+        None => unique_output_path(options.format.extension())?,
+        Some(path) if path.extension().is_none() => path.with_extension(options.format.extension()),
+        Some(path) => path.clone(),
+    };
+
+    let film = match &options.checkpoint {
+        Some(checkpoint_path) => render_progressive(renderer, options, &source_files, checkpoint_path, &path)?,
+        None => {
+            let mut film = renderer.new_film();
+            renderer.render_pass(&mut film, renderer.settings().samples, |tile, done, total| {
+                if !options.quiet {
+                    println!("Tile {} done ({}/{})", tile, done, total);
+                }
+            })?;
+            write_image(&film.image(), options.format, options, &path)?;
+            film
+        }
+    };
+
+    if !options.quiet {
+        println!("{}", duration_to_string(&start_time.elapsed()));
+        println!("{:.1} samples per pixel on average", film.average_samples());
+    }
+    if let Some(heatmap_path) = &options.heatmap {
+        let format = OutputFormat::from_extension(heatmap_path).unwrap_or(OutputFormat::Png);
+        write_image(&film.output().sample_heatmap(), format, options, heatmap_path)?;
+    }
+    Ok(())
 }
 
-fn set_up_scene() -> HittableScene<Precision> {
-    let mut scene = HittableScene::<Precision>::new();
-
-    scene.add_hittable(Arc::new(Sphere {
-        center: Vec3::<Precision>::new(0.0, -1000.0, -0.0),
-        radius: 1000.0,
-        material: Arc::new(LambertianMaterial {
-            albedo: Vec3::<Precision>::new(0.5, 0.5, 0.5),
-        }),
-    }));
-
-    scene.add_hittable(Arc::new(Sphere {
-        center: Vec3::<Precision>::new(-0.0, 1.0, 0.0),
-        radius: 1.0,
-        material: Arc::new(DielectricMaterial {
-            refractive_index: 1.5,
-        }),
-    }));
-    scene.add_hittable(Arc::new(Sphere {
-        center: Vec3::<Precision>::new(-4.0, 1.0, 0.0),
-        radius: 1.0,
-        material: Arc::new(LambertianMaterial {
-            albedo: Vec3::<Precision>::new(0.8, 0.3, 0.3),
-        }),
-    }));
-    scene.add_hittable(Arc::new(Sphere {
-        center: Vec3::<Precision>::new(4.0, 1.0, 0.0),
-        radius: 1.0,
-        material: Arc::new(MetalMaterial {
-            albedo: Vec3::<Precision>::new(0.8, 0.6, 0.2),
-            roughness: 0.8,
-        }),
-    }));
-
-    let mut rng = rand::thread_rng();
-    for a in -11..11 {
-        for b in -11..11 {
-            let r = 0.2;
-            let mat_choice = rng.gen_range(0.0, 1.0);
-            let c = Vec3::new(
-                a as Precision + rng.gen_range(0.0, 1.0 - r / 2.0),
-                r,
-                b as Precision + rng.gen_range(0.0, 1.0 - r / 2.0),
-            ) * Vec3::new(1.0, 1.0, 1.0);
-            if mat_choice < 0.8 {
-                scene.add_hittable(Arc::new(MovableSphere {
-                    center0: c,
-                    center1: c + Vec3::new(0.0, rng.gen_range(0.0, 0.5), 0.0),
-                    time0: 0.0,
-                    time1: 1.0,
-                    radius: r,
-                    material: Arc::new(LambertianMaterial {
-                        albedo: Vec3::<Precision>::new(
-                            rng.gen_range(0.0, 1.0) * rng.gen_range(0.0, 1.0),
-                            rng.gen_range(0.0, 1.0) * rng.gen_range(0.0, 1.0),
-                            rng.gen_range(0.0, 1.0) * rng.gen_range(0.0, 1.0),
-                        ),
-                    }),
-                }));
-            } else if mat_choice < 0.95 {
-                scene.add_hittable(Arc::new(Sphere {
-                    center: c,
-                    radius: r,
-                    material: Arc::new(MetalMaterial {
-                        albedo: Vec3::<Precision>::new(
-                            rng.gen_range(0.5, 1.0),
-                            rng.gen_range(0.5, 1.0),
-                            rng.gen_range(0.5, 1.0),
-                        ),
-                        roughness: (rng.gen_range(0.0, 0.5)),
-                    }),
-                }));
-            } else {
-                scene.add_hittable(Arc::new(Sphere {
-                    center: c,
-                    radius: r,
-                    material: Arc::new(DielectricMaterial {
-                        refractive_index: 1.5,
-                    }),
-                }));
-            }
+/// Renders in passes of `options.pass_samples`, resuming from the checkpoint when it exists and
+/// saving the image and the checkpoint whenever `options.checkpoint_interval` has passed
+fn render_progressive(
+    mut renderer: Renderer<Precision>,
+    options: &Options,
+    source_files: &[PathBuf],
+    checkpoint_path: &Path,
+    image_path: &Path,
+) -> Result<Film<Precision>> {
+    let settings_hash = renderer.settings().fingerprint(&read_scene_source(&options.scene_path, source_files)?);
+    let mut film = renderer.new_film();
+    if checkpoint_path.exists() {
+        let checkpoint = read_checkpoint::<Precision>(checkpoint_path)?;
+        let mismatch = |message: &str| Error::Checkpoint {
+            path: checkpoint_path.to_path_buf(),
+            message: message.to_string(),
+        };
+        if checkpoint.settings_hash != settings_hash || checkpoint.film.area != film.area {
+            return Err(mismatch("it was made from a different scene or with different render settings"));
+        }
+        if options.seed.is_some() && options.seed != Some(checkpoint.seed) {
+            return Err(mismatch("it was made with a different seed"));
+        }
+        renderer = renderer.with_seed(checkpoint.seed);
+        film = checkpoint.film;
+        if !options.quiet {
+            println!("Resuming at {:.1} samples per pixel", film.average_samples());
+        }
+    }
+
+    let interval = Duration::from_secs(options.checkpoint_interval);
+    let mut last_save = Instant::now();
+    let mut pass = 0;
+    loop {
+        let finished = renderer.render_pass(&mut film, options.pass_samples, |_tile, _done, _total| {})?;
+        pass += 1;
+        if !options.quiet {
+            println!("Pass {} done, {:.1} samples per pixel on average", pass, film.average_samples());
+        }
+        if finished || last_save.elapsed() >= interval {
+            write_image(&film.image(), options.format, options, image_path)?;
+            let checkpoint = Checkpoint {
+                settings_hash,
+                seed: renderer.seed(),
+                film,
+            };
+            write_checkpoint(&checkpoint, checkpoint_path)?;
+            film = checkpoint.film;
+            last_save = Instant::now();
+        }
+        if finished {
+            return Ok(film);
         }
     }
+}
 
-    println!("Scene set up.");
-    scene
+/// The contents of the scene description followed by those of the files it references, each
+/// prefixed by its length
+fn read_scene_source(scene_path: &Path, source_files: &[PathBuf]) -> Result<Vec<u8>> {
+    let mut source = Vec::new();
+    for path in std::iter::once(scene_path).chain(source_files.iter().map(PathBuf::as_path)) {
+        let contents = fs::read(path).map_err(|e| Error::io(path, e))?;
+        source.extend_from_slice(&(contents.len() as u64).to_le_bytes());
+        source.extend_from_slice(&contents);
+    }
+    Ok(source)
 }
 
-pub fn get_ray_color(
-    r: &Ray<Precision>,
-    scene: &BvhNode<Precision>,
-    depth: u32,
-) -> Vec3<Precision> {
-    let mut rec = HitRecord::<Precision>::default();
-    if scene.hit(r, 0.01, 10000000.0, &mut rec) {
-        let mut scattered = Ray::<Precision>::default();
-        let mut attenuation = Vec3::<Precision>::zero();
-        if depth < 50 && rec.material.upgrade().expect("Could not get RC to material from weak ptr").scatter(r, &mut rec, &mut attenuation, &mut scattered) {
-            attenuation * get_ray_color(&scattered, &scene, depth + 1)
-        } else {
-            Vec3::<Precision>::zero()
+fn write_image(image: &ImageBuffer<Precision>, format: OutputFormat, options: &Options, path: &Path) -> Result<()> {
+    if !options.quiet {
+        println!("Writing pixels to: {}", path.display());
+    }
+    let (pixels, width, height) = (&image.pixels, image.width, image.height);
+    match format {
+        OutputFormat::Ppm => write_ppm_binary_to_path(pixels, width, height, path),
+        OutputFormat::Png => write_png_to_path(pixels, width, height, path),
+        OutputFormat::Exr => {
+            let pixel_type = if options.half_float { ExrPixelType::Half } else { ExrPixelType::Float };
+            write_exr_to_path(pixels, width, height, path, pixel_type)
         }
-    } else {
-        let unit_direction = r.get_direction().normalized();
-        let t = 0.5 * (unit_direction.y + 1.0);
-        Vec3::<Precision>::one() * (1.0 - t) + Vec3::<Precision>::new(0.5, 0.7, 1.0) * t
+        OutputFormat::Hdr => write_radiance_hdr_to_path(pixels, width, height, path),
+        OutputFormat::Pfm => write_pfm_to_path(pixels, width, height, path),
     }
 }
+
+/// Traces the same rays through the original median split BVH, the SAH built pointer tree and the
+/// flattened BVH, and reports their speed relative to the median split one
+fn benchmark_bvh(scene: &HittableScene<Precision>, camera: &Camera<Precision>, settings: &RenderSettings) -> Result<()> {
+    let list = &scene.hittable_list[..];
+    let rays = generate_primary_rays(camera, settings.width, settings.height);
+
+    let baseline = match MedianSplitBvh::<Precision>::new(list, camera.time0, camera.time1) {
+        Some(median) => {
+            let result = benchmark_traversal("Median split BvhNode (baseline)", &median, &rays);
+            println!("{}", result);
+            Some(result)
+        }
+        None => {
+            println!("The scene has primitives without a bounding box, skipping the median split baseline");
+            None
+        }
+    };
+    let report = |result: TraversalBenchmark| match &baseline {
+        Some(baseline) => println!("{}, {:.2}x the baseline", result, result.speedup_over(baseline)),
+        None => println!("{}", result),
+    };
+
+    let (tree, tree_stats) = BvhNode::<Precision>::with_stats(list, camera.time0, camera.time1)?;
+    println!("{}", tree_stats);
+    report(benchmark_traversal("SAH BvhNode", &tree, &rays));
+
+    let (linear, linear_stats) = LinearBvh::<Precision>::with_stats(list, camera.time0, camera.time1)?;
+    println!("{}", linear_stats);
+    report(benchmark_traversal("LinearBvh", &linear, &rays));
+    Ok(())
+}
@@ -1,9 +1,9 @@
-use rand::{thread_rng, Rng};
 use straal::{FloatType, Vec3};
 
 use crate::geometry::HitRecord;
-use crate::material::Material;
+use crate::material::{Material, ScatterRecord};
 use crate::math::{schlick, Ray};
+use crate::sampler::Sampler;
 
 pub struct DielectricMaterial<T> {
     pub refractive_index: T,
@@ -22,58 +22,52 @@ impl<T> Material<T> for DielectricMaterial<T>
 where
     T: FloatType<T> + Send + Sync,
 {
-    fn scatter(
-        &self,
-        r: &Ray<T>,
-        record: &mut HitRecord<T>,
-        attenuation: &mut Vec3<T>,
-        scattered: &mut Ray<T>,
-    ) -> bool {
-        let reflected = Vec3::<T>::reflect(r.direction, record.normal);
-
-        attenuation.x = T::one();
-        attenuation.y = T::one();
-        attenuation.z = T::one();
+    fn scatter(&self, r: &Ray<T>, record: &HitRecord<T>, sampler: &mut dyn Sampler) -> Option<ScatterRecord<T>> {
+        //Whether the ray enters or leaves goes by the surface itself, the bending by the shading normal
+        let normal = record.shading_normal;
+        let reflected = Vec3::<T>::reflect(r.direction, normal);
+        let incoming = Vec3::<T>::dot(r.direction, record.normal);
 
         let outward_normal;
         let ni_over_nt;
         let cosine;
-        if Vec3::<T>::dot(r.direction, record.normal) > T::zero() {
-            outward_normal = -record.normal;
+        if incoming > T::zero() {
+            outward_normal = -normal;
             ni_over_nt = self.refractive_index;
-            let tmp_cos = Vec3::<T>::dot(r.direction, record.normal) / r.direction.length();
+            let tmp_cos = Vec3::<T>::dot(r.direction, normal) / r.direction.length();
             cosine = (T::one()
                 - self.refractive_index * self.refractive_index * (T::one() - tmp_cos * tmp_cos))
                 .sqrt();
         } else {
-            outward_normal = record.normal;
+            outward_normal = normal;
             ni_over_nt = T::one() / self.refractive_index;
-            cosine = -Vec3::<T>::dot(r.direction, record.normal) / r.direction.length();
+            cosine = -Vec3::<T>::dot(r.direction, normal) / r.direction.length();
         }
 
-        let refracted;
-        let reflect_prob;
-        match refract(r.direction, outward_normal, ni_over_nt) {
-            Some(r) => {
-                refracted = r;
-                reflect_prob = schlick(cosine, self.refractive_index);
-            }
-            None => {
-                scattered.origin = record.position;
-                scattered.direction = reflected;
-                refracted = Vec3::<T>::zero();
-                reflect_prob = T::one();
-            }
-        }
+        let (refracted, reflect_prob) = match refract(r.direction, outward_normal, ni_over_nt) {
+            Some(refracted) => (refracted, schlick(cosine, self.refractive_index)),
+            None => (Vec3::<T>::zero(), T::one()),
+        };
 
-        if T::from(thread_rng().gen_range(0.0, 1.0)).unwrap() < reflect_prob {
-            scattered.origin = record.position;
-            scattered.direction = reflected;
-        } else {
-            scattered.origin = record.position;
-            scattered.direction = refracted;
+        let is_reflection = T::from(sampler.get_1d()).unwrap() < reflect_prob;
+        let direction = if is_reflection { reflected } else { refracted };
+        //Reflections have to stay on the side the ray came from and refractions have to cross the
+        //surface, a tilted shading normal can break either and those paths are absorbed
+        let outgoing = Vec3::<T>::dot(direction, record.normal);
+        let crosses = (incoming < T::zero()) == (outgoing < T::zero());
+        if outgoing == T::zero() || crosses == is_reflection {
+            return None;
         }
-        true
+        Some(ScatterRecord {
+            scattered: Ray {
+                origin: record.position,
+                direction,
+                time: r.time,
+            },
+            attenuation: Vec3::<T>::one(),
+            pdf: T::zero(),
+            is_specular: true,
+        })
     }
 }
 
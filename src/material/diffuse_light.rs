@@ -0,0 +1,43 @@
+use std::sync::Arc;
+
+use straal::{FloatType, Vec3};
+
+use crate::geometry::HitRecord;
+use crate::material::{Material, ScatterRecord};
+use crate::math::Ray;
+use crate::sampler::Sampler;
+use crate::textures::{ConstantTexture, Texture, TextureLookup};
+
+pub struct DiffuseLight<T> {
+    pub emit: Arc<dyn Texture<T>>,
+}
+
+impl<T> DiffuseLight<T>
+where
+    T: FloatType<T> + Send + Sync + 'static,
+{
+    pub fn create(emit: &Vec3<T>) -> DiffuseLight<T> {
+        DiffuseLight::with_texture(Arc::new(ConstantTexture::new(emit)))
+    }
+
+    pub fn with_texture(emit: Arc<dyn Texture<T>>) -> DiffuseLight<T> {
+        DiffuseLight { emit }
+    }
+}
+
+impl<T> Material<T> for DiffuseLight<T>
+where
+    T: FloatType<T> + Send + Sync,
+{
+    fn scatter(&self, _r: &Ray<T>, _record: &HitRecord<T>, _sampler: &mut dyn Sampler) -> Option<ScatterRecord<T>> {
+        None
+    }
+
+    fn emitted(&self, _r: &Ray<T>, record: &HitRecord<T>) -> Vec3<T> {
+        self.emit.lookup(&TextureLookup::at(record))
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
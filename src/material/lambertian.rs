@@ -1,18 +1,35 @@
+use std::sync::Arc;
+
 use crate::geometry::HitRecord;
-use crate::material::Material;
-use crate::math::{random_in_unit_sphere, Ray};
+use crate::material::{Material, ScatterRecord};
+use crate::math::{random_unit_vector, Ray};
+use crate::sampler::Sampler;
+use crate::textures::{ConstantTexture, Texture, TextureLookup};
 use straal::{FloatType, Vec3};
 
 pub struct LambertianMaterial<T> {
-    pub albedo: Vec3<T>,
+    pub albedo: Arc<dyn Texture<T>>,
 }
 
 impl<T> LambertianMaterial<T>
 where
-    T: FloatType<T>,
+    T: FloatType<T> + Send + Sync + 'static,
 {
     pub fn create(albedo: &Vec3<T>) -> LambertianMaterial<T> {
-        LambertianMaterial { albedo: *albedo }
+        LambertianMaterial::with_texture(Arc::new(ConstantTexture::new(albedo)))
+    }
+
+    pub fn with_texture(albedo: Arc<dyn Texture<T>>) -> LambertianMaterial<T> {
+        LambertianMaterial { albedo }
+    }
+}
+
+impl<T> LambertianMaterial<T>
+where
+    T: FloatType<T>,
+{
+    fn albedo_at(&self, record: &HitRecord<T>) -> Vec3<T> {
+        self.albedo.lookup(&TextureLookup::at(record))
     }
 }
 
@@ -20,19 +37,44 @@ impl<T> Material<T> for LambertianMaterial<T>
 where
     T: FloatType<T> + Send + Sync,
 {
-    fn scatter(
-        &self,
-        _r: &Ray<T>,
-        record: &mut HitRecord<T>,
-        attenuation: &mut Vec3<T>,
-        scattered: &mut Ray<T>,
-    ) -> bool {
-        let target = record.position + record.normal + random_in_unit_sphere();
-        scattered.origin = record.position;
-        scattered.direction = target - record.position;
-        attenuation.x = self.albedo.x;
-        attenuation.y = self.albedo.y;
-        attenuation.z = self.albedo.z;
-        true
+    fn scatter(&self, r: &Ray<T>, record: &HitRecord<T>, sampler: &mut dyn Sampler) -> Option<ScatterRecord<T>> {
+        //A point on the unit sphere around the normal gives exactly the cosine distribution
+        //that makes the albedo the right weight. Both sides of a surface reflect the same way.
+        let normal = record.facing_shading_normal();
+        let mut direction = normal + random_unit_vector(sampler.get_2d());
+        if direction.length_squared() < T::from(1e-8).unwrap() {
+            direction = normal;
+        }
+        //A tilted shading normal can point the direction into the surface, which would let light
+        //through it
+        if Vec3::dot(direction, record.facing_normal()) <= T::zero() {
+            return None;
+        }
+        Some(ScatterRecord {
+            pdf: self.pdf(r, record, &direction),
+            scattered: Ray {
+                origin: record.position,
+                direction,
+                time: r.time,
+            },
+            attenuation: self.albedo_at(record),
+            is_specular: false,
+        })
+    }
+
+    fn eval(&self, r: &Ray<T>, record: &HitRecord<T>, direction: &Vec3<T>) -> Vec3<T> {
+        if Vec3::dot(*direction, record.facing_normal()) <= T::zero() {
+            return Vec3::<T>::zero();
+        }
+        self.albedo_at(record) * self.pdf(r, record, direction)
+    }
+
+    fn pdf(&self, _r: &Ray<T>, record: &HitRecord<T>, direction: &Vec3<T>) -> T {
+        let cosine = Vec3::dot(direction.normalized(), record.facing_shading_normal());
+        if cosine > T::zero() {
+            cosine / T::from(std::f64::consts::PI).unwrap()
+        } else {
+            T::zero()
+        }
     }
 }
@@ -1,27 +1,72 @@
+use std::sync::Arc;
+
 use straal::{FloatType, Vec3};
 
 use crate::geometry::HitRecord;
-use crate::material::Material;
-use crate::math::{random_in_unit_sphere, Ray};
+use crate::material::{Material, ScatterRecord};
+use crate::math::{Onb, Ray};
+use crate::sampler::Sampler;
+use crate::textures::{ConstantTexture, Texture, TextureLookup};
 
+/// Glossy reflector. The roughness spreads reflections over a Phong lobe around the mirror
+/// direction with exponent `5 / roughness^2 - 2`, a roughness of zero is a perfect mirror.
+/// Roughness keeps its old meaning of the radius of the sphere the mirror direction used to be
+/// fuzzed by: that lobe has the same average spread, so existing scenes look the same.
+/// Roughness is read from the average of the texture channels and clamped to [0, 1].
 pub struct MetalMaterial<T> {
-    pub albedo: Vec3<T>,
-    pub roughness: T,
+    pub albedo: Arc<dyn Texture<T>>,
+    pub roughness: Arc<dyn Texture<T>>,
 }
 
 impl<T> MetalMaterial<T>
     where
-        T: FloatType<T>,
+        T: FloatType<T> + Send + Sync + 'static,
 {
     pub fn create(albedo: &Vec3<T>, roughness: T) -> MetalMaterial<T> {
-        MetalMaterial {
-            albedo: *albedo,
-            roughness: if roughness > T::one() {
-                T::one()
-            } else {
-                roughness
-            },
+        MetalMaterial::with_textures(
+            Arc::new(ConstantTexture::new(albedo)),
+            Arc::new(ConstantTexture::value(roughness)),
+        )
+    }
+
+    pub fn with_textures(albedo: Arc<dyn Texture<T>>, roughness: Arc<dyn Texture<T>>) -> MetalMaterial<T> {
+        MetalMaterial { albedo, roughness }
+    }
+}
+
+impl<T> MetalMaterial<T>
+    where
+        T: FloatType<T>,
+{
+    fn albedo_at(&self, record: &HitRecord<T>) -> Vec3<T> {
+        self.albedo.lookup(&TextureLookup::at(record))
+    }
+
+    fn roughness_at(&self, record: &HitRecord<T>) -> T {
+        let roughness = self.roughness.lookup_value(&TextureLookup::at(record));
+        T::max(T::zero(), T::min(roughness, T::one()))
+    }
+
+    fn is_mirror(roughness: T) -> bool {
+        roughness < T::from(0.001).unwrap()
+    }
+
+    fn exponent(roughness: T) -> T {
+        T::from(5).unwrap() / (roughness * roughness) - T::from(2).unwrap()
+    }
+
+    fn reflected(r: &Ray<T>, record: &HitRecord<T>) -> Vec3<T> {
+        Vec3::<T>::reflect(r.direction.normalized(), record.facing_shading_normal())
+    }
+
+    /// Density of the lobe around the mirror direction, which is never a mirror itself
+    fn lobe_pdf(roughness: T, r: &Ray<T>, record: &HitRecord<T>, direction: &Vec3<T>) -> T {
+        let cos_alpha = Vec3::dot(MetalMaterial::reflected(r, record), direction.normalized());
+        if cos_alpha <= T::zero() {
+            return T::zero();
         }
+        let n = MetalMaterial::<T>::exponent(roughness);
+        (n + T::one()) / T::from(2.0 * std::f64::consts::PI).unwrap() * cos_alpha.powf(n)
     }
 }
 
@@ -29,19 +74,58 @@ impl<T> Material<T> for MetalMaterial<T>
     where
         T: FloatType<T> + Send + Sync,
 {
-    fn scatter(
-        &self,
-        r: &Ray<T>,
-        record: &mut HitRecord<T>,
-        attenuation: &mut Vec3<T>,
-        scattered: &mut Ray<T>,
-    ) -> bool {
-        let reflected = Vec3::<T>::reflect(r.direction.normalized(), record.normal);
-        scattered.origin = record.position;
-        scattered.direction = reflected + random_in_unit_sphere() * self.roughness;
-        attenuation.x = self.albedo.x;
-        attenuation.y = self.albedo.y;
-        attenuation.z = self.albedo.z;
-        scattered.direction.dot(record.normal) > T::zero()
-    }
-}
\ No newline at end of file
+    fn scatter(&self, r: &Ray<T>, record: &HitRecord<T>, sampler: &mut dyn Sampler) -> Option<ScatterRecord<T>> {
+        let reflected = MetalMaterial::reflected(r, record);
+        let roughness = self.roughness_at(record);
+        let is_mirror = MetalMaterial::<T>::is_mirror(roughness);
+        let (direction, pdf) = if is_mirror {
+            (reflected, T::zero())
+        } else {
+            let (u1, u2) = sampler.get_2d();
+            let r1 = T::from(u1).unwrap();
+            let r2 = T::from(u2).unwrap();
+            let cos_alpha = r1.powf(T::one() / (MetalMaterial::<T>::exponent(roughness) + T::one()));
+            let sin_alpha = (T::one() - cos_alpha * cos_alpha).sqrt();
+            let phi = T::from(2.0 * std::f64::consts::PI).unwrap() * r2;
+            let local = Vec3::<T> {
+                x: phi.cos() * sin_alpha,
+                y: phi.sin() * sin_alpha,
+                z: cos_alpha,
+            };
+            let direction = Onb::from_w(&reflected).local(&local);
+            (direction, MetalMaterial::lobe_pdf(roughness, r, record, &direction))
+        };
+
+        //Reflections that end up below the surface are absorbed, also when a tilted shading normal
+        //sends the mirror direction there
+        if direction.dot(record.facing_normal()) <= T::zero() {
+            return None;
+        }
+        Some(ScatterRecord {
+            scattered: Ray {
+                origin: record.position,
+                direction,
+                time: r.time,
+            },
+            attenuation: self.albedo_at(record),
+            pdf,
+            is_specular: is_mirror,
+        })
+    }
+
+    /// The lobe is normalised so the weight of a sampled direction is exactly the albedo
+    fn eval(&self, r: &Ray<T>, record: &HitRecord<T>, direction: &Vec3<T>) -> Vec3<T> {
+        if direction.dot(record.facing_normal()) <= T::zero() {
+            return Vec3::<T>::zero();
+        }
+        self.albedo_at(record) * self.pdf(r, record, direction)
+    }
+
+    fn pdf(&self, r: &Ray<T>, record: &HitRecord<T>, direction: &Vec3<T>) -> T {
+        let roughness = self.roughness_at(record);
+        if MetalMaterial::<T>::is_mirror(roughness) {
+            return T::zero();
+        }
+        MetalMaterial::lobe_pdf(roughness, r, record, direction)
+    }
+}
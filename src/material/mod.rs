@@ -1,26 +1,62 @@
 use straal::{FloatType, Vec3};
 
 pub mod dielectric;
+pub mod diffuse_light;
 pub mod lambertian;
 pub mod metal;
+pub mod surface_detail;
 
 use crate::geometry::HitRecord;
 use crate::math::Ray;
+use crate::sampler::Sampler;
 pub use dielectric::*;
+pub use diffuse_light::*;
 pub use lambertian::*;
 pub use metal::*;
+pub use surface_detail::*;
+
+/// Outcome of sampling a material for an outgoing direction
+pub struct ScatterRecord<T> {
+    pub scattered: Ray<T>,
+    /// BSDF times cosine divided by the pdf, the weight of the light arriving along `scattered`
+    pub attenuation: Vec3<T>,
+    /// Solid angle density of the sampled direction, meaningless for specular scattering
+    pub pdf: T,
+    /// Delta distributions (mirrors, glass) can't be evaluated for other directions, so they
+    /// are never combined with light sampling
+    pub is_specular: bool,
+}
 
 pub trait Material<T>: Send + Sync
 where
     T: FloatType<T> + Send + Sync,
 {
-    fn scatter(
-        &self,
-        r: &Ray<T>,
-        record: &mut HitRecord<T>,
-        attenuation: &mut Vec3<T>,
-        scattered: &mut Ray<T>,
-    ) -> bool;
+    /// Tilts the shading normal of `record` for surface detail like normal maps. Called once per
+    /// hit, before any of the other methods and after the pixel footprint is set.
+    fn perturb_normal(&self, _record: &mut HitRecord<T>) {}
+
+    /// Samples an outgoing direction using the values from `sampler`, `None` when the ray is absorbed
+    fn scatter(&self, r: &Ray<T>, record: &HitRecord<T>, sampler: &mut dyn Sampler) -> Option<ScatterRecord<T>>;
+
+    /// BSDF times the cosine with the normal, for light arriving from `direction` and leaving
+    /// along `-r`. Zero for specular materials.
+    fn eval(&self, _r: &Ray<T>, _record: &HitRecord<T>, _direction: &Vec3<T>) -> Vec3<T> {
+        Vec3::<T>::zero()
+    }
+
+    /// Density with which `scatter` picks `direction`. Zero for specular materials.
+    fn pdf(&self, _r: &Ray<T>, _record: &HitRecord<T>, _direction: &Vec3<T>) -> T {
+        T::zero()
+    }
+
+    /// Light emitted by the surface at the hit point, black for everything but light sources
+    fn emitted(&self, _r: &Ray<T>, _record: &HitRecord<T>) -> Vec3<T> {
+        Vec3::<T>::zero()
+    }
+
+    fn is_emissive(&self) -> bool {
+        false
+    }
 }
 
 pub struct DummyMaterial;
@@ -29,14 +65,8 @@ impl<T> Material<T> for DummyMaterial
 where
     T: FloatType<T> + Send + Sync,
 {
-    fn scatter(
-        &self,
-        _r: &Ray<T>,
-        _record: &mut HitRecord<T>,
-        _attenuation: &mut Vec3<T>,
-        _scattered: &mut Ray<T>,
-    ) -> bool {
+    fn scatter(&self, _r: &Ray<T>, _record: &HitRecord<T>, _sampler: &mut dyn Sampler) -> Option<ScatterRecord<T>> {
         println!("Drawing using dummy material now, this should not happen!");
-        return false;
+        None
     }
 }
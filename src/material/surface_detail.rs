@@ -0,0 +1,211 @@
+use std::sync::Arc;
+
+use straal::{FloatType, Vec3};
+
+use crate::geometry::HitRecord;
+use crate::material::{Material, ScatterRecord};
+use crate::math::Ray;
+use crate::sampler::Sampler;
+use crate::textures::{Texture, TextureLookup};
+
+/// Small scale relief that tilts the shading normal without changing the geometry
+pub enum SurfaceDetail<T> {
+    /// Tangent space normals stored as colours, mapped from [0, 1] to [-1, 1] with x along the
+    /// tangent, y along the bitangent and z along the normal. The strength scales the tilt, zero
+    /// flattens the map. Image textures holding normals should be read with `srgb` off.
+    NormalMap {
+        normals: Arc<dyn Texture<T>>,
+        strength: T,
+    },
+    /// Heights along the normal in world units, the average of the texture channels times `strength`
+    BumpMap {
+        height: Arc<dyn Texture<T>>,
+        strength: T,
+    },
+}
+
+/// Wraps any material to shade it with the normals from a normal or bump map. The geometric
+/// normal is left alone, so materials can still tell what is in front of the surface.
+pub struct DetailedMaterial<T> {
+    pub material: Arc<dyn Material<T>>,
+    pub detail: SurfaceDetail<T>,
+}
+
+impl<T> DetailedMaterial<T>
+where
+    T: FloatType<T> + Send + Sync,
+{
+    pub fn new(material: Arc<dyn Material<T>>, detail: SurfaceDetail<T>) -> DetailedMaterial<T> {
+        DetailedMaterial { material, detail }
+    }
+
+    fn detail_normal(&self, record: &HitRecord<T>) -> Vec3<T> {
+        let frame = record.tangent_frame();
+        let at = TextureLookup::at(record);
+        match &self.detail {
+            SurfaceDetail::NormalMap { normals, strength } => {
+                let c = normals.lookup(&at);
+                let two = T::from(2).unwrap();
+                frame.local(&Vec3::<T> {
+                    x: (two * c.x - T::one()) * *strength,
+                    y: (two * c.y - T::one()) * *strength,
+                    z: two * c.z - T::one(),
+                })
+            }
+            SurfaceDetail::BumpMap { height, strength } => {
+                //Finite differences over half the pixel footprint, stepping the position as well as
+                //the texture coordinates so solid textures make bumps too
+                let step = T::max(at.width * T::from(0.5).unwrap(), T::from(1e-4).unwrap());
+                let uv_step = step * record.uv_density;
+                let height_at = |lookup: &TextureLookup<T>| height.lookup_value(lookup) * *strength;
+                let base = height_at(&at);
+                let along_u = height_at(&TextureLookup {
+                    u: at.u + uv_step,
+                    position: at.position + frame.u * step,
+                    ..at
+                });
+                let along_v = height_at(&TextureLookup {
+                    v: at.v + uv_step,
+                    position: at.position + frame.v * step,
+                    ..at
+                });
+                frame.w - (frame.u * (along_u - base) + frame.v * (along_v - base)) / step
+            }
+        }
+    }
+
+}
+
+impl<T> Material<T> for DetailedMaterial<T>
+where
+    T: FloatType<T> + Send + Sync,
+{
+    /// Replaces the shading normal with the one from the detail, kept in front of the surface
+    fn perturb_normal(&self, record: &mut HitRecord<T>) {
+        self.material.perturb_normal(record);
+        let normal = self.detail_normal(record);
+        if normal.length_squared() < T::from(1e-12).unwrap() {
+            return;
+        }
+        let normal = normal.normalized();
+        let min_facing = T::from(0.01).unwrap();
+        let facing = Vec3::dot(normal, record.normal);
+        record.shading_normal = if facing < min_facing {
+            (normal + record.normal * (min_facing - facing)).normalized()
+        } else {
+            normal
+        };
+    }
+
+    fn scatter(&self, r: &Ray<T>, record: &HitRecord<T>, sampler: &mut dyn Sampler) -> Option<ScatterRecord<T>> {
+        self.material.scatter(r, record, sampler)
+    }
+
+    fn eval(&self, r: &Ray<T>, record: &HitRecord<T>, direction: &Vec3<T>) -> Vec3<T> {
+        self.material.eval(r, record, direction)
+    }
+
+    fn pdf(&self, r: &Ray<T>, record: &HitRecord<T>, direction: &Vec3<T>) -> T {
+        self.material.pdf(r, record, direction)
+    }
+
+    fn emitted(&self, r: &Ray<T>, record: &HitRecord<T>) -> Vec3<T> {
+        self.material.emitted(r, record)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::LambertianMaterial;
+    use crate::textures::ConstantTexture;
+
+    /// A hit on the xy plane from above, with `u` along x and `v` along y
+    fn record() -> HitRecord<f64> {
+        let mut record = HitRecord::default();
+        record.normal = Vec3::new(0.0, 0.0, 1.0);
+        record.shading_normal = record.normal;
+        record.tangent = Vec3::new(1.0, 0.0, 0.0);
+        record.bitangent = Vec3::new(0.0, 1.0, 0.0);
+        record.uv_density = 1.0;
+        record
+    }
+
+    fn perturbed(detail: SurfaceDetail<f64>, mut record: HitRecord<f64>) -> HitRecord<f64> {
+        let material = DetailedMaterial::new(Arc::new(LambertianMaterial::create(&Vec3::all(0.5))), detail);
+        material.perturb_normal(&mut record);
+        record
+    }
+
+    fn normal_map(color: Vec3<f64>, strength: f64) -> SurfaceDetail<f64> {
+        SurfaceDetail::NormalMap {
+            normals: Arc::new(ConstantTexture::new(&color)),
+            strength,
+        }
+    }
+
+    #[test]
+    fn a_flat_normal_map_keeps_the_normal() {
+        let record = perturbed(normal_map(Vec3::new(0.5, 0.5, 1.0), 1.0), record());
+        assert_eq!(record.shading_normal, record.normal);
+    }
+
+    #[test]
+    fn normal_maps_tilt_along_the_tangent_frame() {
+        let tilted = perturbed(normal_map(Vec3::new(1.0, 0.5, 1.0), 1.0), record()).shading_normal;
+        assert!((tilted - Vec3::new(1.0, 0.0, 1.0).normalized()).length() < 1e-12);
+        let tilted = perturbed(normal_map(Vec3::new(0.5, 0.0, 1.0), 1.0), record()).shading_normal;
+        assert!((tilted - Vec3::new(0.0, -1.0, 1.0).normalized()).length() < 1e-12);
+        //Zero strength flattens the map
+        let flat = perturbed(normal_map(Vec3::new(1.0, 0.0, 1.0), 0.0), record()).shading_normal;
+        assert!((flat - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+    }
+
+    #[test]
+    fn shading_normals_stay_in_front_of_the_surface() {
+        //A map pointing below the surface is pulled back just above it
+        let record = perturbed(normal_map(Vec3::new(1.0, 0.5, 0.0), 1.0), record());
+        let facing = Vec3::dot(record.shading_normal, record.normal);
+        assert!(facing > 0.0 && facing < 0.02, "{}", facing);
+        assert!((record.shading_normal.length() - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn back_faces_see_the_same_relief() {
+        let mut back = record();
+        back.front_face = false;
+        let front = perturbed(normal_map(Vec3::new(1.0, 0.5, 1.0), 1.0), record());
+        let back = perturbed(normal_map(Vec3::new(1.0, 0.5, 1.0), 1.0), back);
+        assert_eq!(back.shading_normal, front.shading_normal);
+        assert_eq!(back.facing_shading_normal(), -front.shading_normal);
+    }
+
+    #[test]
+    fn bump_maps_tilt_away_from_rising_heights() {
+        let flat = SurfaceDetail::BumpMap {
+            height: Arc::new(ConstantTexture::value(0.7)),
+            strength: 2.0,
+        };
+        assert_eq!(perturbed(flat, record()).shading_normal, Vec3::new(0.0, 0.0, 1.0));
+        //Heights rising along x, one unit per unit of distance
+        let ramp = SurfaceDetail::BumpMap {
+            height: Arc::new(PositionX),
+            strength: 1.0,
+        };
+        let tilted = perturbed(ramp, record()).shading_normal;
+        assert!((tilted - Vec3::new(-1.0, 0.0, 1.0).normalized()).length() < 1e-9, "{:?}", tilted);
+    }
+
+    /// The x coordinate of the lookup position as a height
+    struct PositionX;
+
+    impl Texture<f64> for PositionX {
+        fn sample_color(&self, _u: f64, _v: f64, p: &Vec3<f64>) -> Vec3<f64> {
+            Vec3::all(p.x)
+        }
+    }
+}
@@ -1,7 +1,7 @@
-use rand::Rng;
 use straal::{FloatType, Vec3};
 
 use crate::math::{random_in_unit_disk, Ray};
+use crate::sampler::Sampler;
 
 pub struct Camera<T> {
     pub origin: Vec3<T>,
@@ -44,14 +44,24 @@ impl<T> Camera<T>
         }
     }
 
-    pub fn get_ray(&self, s: T, t: T) -> Ray<T>
+    /// Angle between the rays through vertically neighbouring pixels of an image `image_height`
+    /// pixels high
+    pub fn pixel_spread(&self, image_height: usize) -> T {
+        let half = T::from(0.5).unwrap();
+        let center = self.lower_left_corner + self.horizontal * half + self.vertical * half;
+        let focus_distance = (center - self.origin).length();
+        self.vertical.length() / (focus_distance * T::from(image_height.max(1)).unwrap())
+    }
+
+    /// Ray through (`s`, `t`) on the image plane, the lens position and time are taken from
+    /// `sampler`, in that order
+    pub fn get_ray(&self, s: T, t: T, sampler: &mut dyn Sampler) -> Ray<T>
         where
             T: FloatType<T>,
     {
-        let mut rng = rand::thread_rng();
-        let random_dist = random_in_unit_disk() * self.lens_radius.clone();
+        let random_dist = random_in_unit_disk(sampler.get_2d()) * self.lens_radius.clone();
         let offset = self.u * random_dist.x + self.v * random_dist.y;
-        let time = self.time0 + T::from(rng.gen_range(0.0, 1.0)).unwrap() * (self.time1 - self.time0);
+        let time = self.time0 + T::from(sampler.get_1d()).unwrap() * (self.time1 - self.time0);
         Ray {
             origin: self.origin + offset,
             direction: self.lower_left_corner + self.horizontal * s + self.vertical * t - self.origin - offset,
@@ -1,11 +1,15 @@
 pub use camera::*;
 pub use light::*;
+pub use onb::*;
+pub use random::*;
 pub use ray::*;
 pub use time_utils::*;
 pub use vector_utils::*;
 
 pub mod camera;
 pub mod light;
+pub mod onb;
+pub mod random;
 pub mod ray;
 pub mod time_utils;
 pub mod vector_utils;
\ No newline at end of file
@@ -0,0 +1,29 @@
+use straal::{FloatType, Vec3};
+
+/// Orthonormal basis around `w`, used to turn directions sampled around the z axis into world space
+pub struct Onb<T> {
+    pub u: Vec3<T>,
+    pub v: Vec3<T>,
+    pub w: Vec3<T>,
+}
+
+impl<T> Onb<T>
+    where
+        T: FloatType<T>,
+{
+    pub fn from_w(n: &Vec3<T>) -> Onb<T> {
+        let w = n.normalized();
+        let a = if w.x.abs() > T::from(0.9).unwrap() {
+            Vec3::<T>::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::<T>::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(a).normalized();
+        let u = w.cross(v);
+        Onb { u, v, w }
+    }
+
+    pub fn local(&self, a: &Vec3<T>) -> Vec3<T> {
+        self.u * a.x + self.v * a.y + self.w * a.z
+    }
+}
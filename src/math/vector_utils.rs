@@ -1,4 +1,4 @@
-use rand::Rng;
+use rand::{Rng, RngCore};
 use straal::{FloatType, Vec3};
 
 pub fn gamma_color<T>(v: &Vec3<T>) -> Vec3<T>
@@ -12,8 +12,45 @@ pub fn gamma_color<T>(v: &Vec3<T>) -> Vec3<T>
     }
 }
 
-pub fn random_in_unit_sphere<T>() -> Vec3<T> where T: FloatType<T> {
-    let mut rng = rand::thread_rng();
+/// sRGB transfer curve for a single linear channel in [0, 1]
+pub fn linear_to_srgb<T>(c: T) -> T where T: FloatType<T> {
+    if c <= T::from(0.0031308).unwrap() {
+        c * T::from(12.92).unwrap()
+    } else {
+        T::from(1.055).unwrap() * c.powf(T::from(1.0 / 2.4).unwrap()) - T::from(0.055).unwrap()
+    }
+}
+
+/// Inverse of `linear_to_srgb`
+pub fn srgb_to_linear<T>(c: T) -> T where T: FloatType<T> {
+    if c <= T::from(0.04045).unwrap() {
+        c / T::from(12.92).unwrap()
+    } else {
+        ((c + T::from(0.055).unwrap()) / T::from(1.055).unwrap()).powf(T::from(2.4).unwrap())
+    }
+}
+
+/// Perceived brightness of a linear Rec. 709 color
+pub fn luminance<T>(v: &Vec3<T>) -> T where T: FloatType<T> {
+    v.x * T::from(0.2126).unwrap() + v.y * T::from(0.7152).unwrap() + v.z * T::from(0.0722).unwrap()
+}
+
+/// Linear interpolation from `a` at `t` = 0 to `b` at `t` = 1
+pub fn lerp<T>(a: &Vec3<T>, b: &Vec3<T>, t: T) -> Vec3<T> where T: FloatType<T> {
+    *a * (T::one() - t) + *b * t
+}
+
+/// Clamps a linear color to [0, 1] and encodes it for display
+pub fn srgb_color<T>(v: &Vec3<T>) -> Vec3<T> where T: FloatType<T> {
+    let encode = |c: T| linear_to_srgb(T::max(T::zero(), T::min(c, T::one())));
+    Vec3::<T> {
+        x: encode(v.x),
+        y: encode(v.y),
+        z: encode(v.z),
+    }
+}
+
+pub fn random_in_unit_sphere<T>(rng: &mut dyn RngCore) -> Vec3<T> where T: FloatType<T> {
     let mut p = Vec3::<T>::new(
         rng.gen_range(-1.0, 1.0),
         rng.gen_range(-1.0, 1.0),
@@ -26,18 +63,46 @@ pub fn random_in_unit_sphere<T>() -> Vec3<T> where T: FloatType<T> {
     p
 }
 
-pub fn random_in_unit_disk<T>() -> Vec3<T> where T: FloatType<T> {
-    let mut rng = rand::thread_rng();
-    let mut p = Vec3::<T>::new(
-        rng.gen_range(-1.0, 1.0),
-        rng.gen_range(-1.0, 1.0),
-        0.0);
-    while p.length_squared() >= T::one() {
-        p.x = T::from(rng.gen_range(-1.0, 1.0)).unwrap();
-        p.y = T::from(rng.gen_range(-1.0, 1.0)).unwrap();
-        p.z = T::zero();
+/// Uniformly distributed direction for the sample `u` in [0, 1)², added to a normal this gives a
+/// cosine weighted direction
+pub fn random_unit_vector<T>(u: (f64, f64)) -> Vec3<T> where T: FloatType<T> {
+    let z = 1.0 - 2.0 * u.0;
+    let phi = 2.0 * std::f64::consts::PI * u.1;
+    let r = (1.0 - z * z).sqrt();
+    Vec3::<T>::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Random direction around the z axis within the cone subtended by a sphere of `radius` at
+/// `distance_squared`, uniformly distributed over that solid angle for the sample `u` in [0, 1)²
+pub fn random_to_sphere<T>(radius: T, distance_squared: T, u: (f64, f64)) -> Vec3<T> where T: FloatType<T> {
+    let r1 = T::from(u.0).unwrap();
+    let r2 = T::from(u.1).unwrap();
+    let cos_theta_max = (T::one() - radius * radius / distance_squared).sqrt();
+    let z = T::one() + r2 * (cos_theta_max - T::one());
+    let phi = T::from(2.0 * std::f64::consts::PI).unwrap() * r1;
+    let sin_theta = (T::one() - z * z).sqrt();
+    Vec3::<T> {
+        x: phi.cos() * sin_theta,
+        y: phi.sin() * sin_theta,
+        z,
     }
-    p
+}
+
+/// Point in the unit disk for the sample `u` in [0, 1)², using the concentric mapping (Shirley and
+/// Chiu 1997), which keeps samples that are well spread over the square well spread over the disk
+pub fn random_in_unit_disk<T>(u: (f64, f64)) -> Vec3<T> where T: FloatType<T> {
+    let a = 2.0 * u.0 - 1.0;
+    let b = 2.0 * u.1 - 1.0;
+    if a == 0.0 && b == 0.0 {
+        return Vec3::<T>::zero();
+    }
+    let quarter_pi = std::f64::consts::FRAC_PI_4;
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, quarter_pi * (b / a))
+    } else {
+        (b, 2.0 * quarter_pi - quarter_pi * (a / b))
+    };
+    Vec3::<T>::new(r * theta.cos(), r * theta.sin(), 0.0)
 }
 
 //pub fn refract<T>(v: Vec3<T>, n: Vec3<T>, ni_over_nt: T) -> Option<Vec3<T>> where T: FloatType<T> {
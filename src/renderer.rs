@@ -0,0 +1,295 @@
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rayon::prelude::*;
+use straal::FloatType;
+
+use crate::error::Result;
+use crate::film::{sample_heatmap, Film, FilmTile, PixelStats};
+use crate::geometry::{BvhStats, HittableScene, LinearBvh};
+use crate::integrator::{Integrator, PathTracer};
+use crate::io::{ImageBuffer, LoadedScene, RenderSettings};
+use crate::math::Camera;
+use crate::sampler::Sampler;
+use crate::tiles::generate_tiles;
+
+/// Seed of renderers that aren't given one
+pub const DEFAULT_SEED: u64 = 0;
+
+/// A rendered image and the number of samples taken for each of its pixels
+pub struct RenderOutput<T> {
+    pub image: ImageBuffer<T>,
+    /// Top row first, like the pixels
+    pub sample_counts: Vec<u32>,
+}
+
+impl<T> RenderOutput<T>
+    where
+        T: FloatType<T>,
+{
+    /// Shows where the samples went, see `sample_heatmap`
+    pub fn sample_heatmap(&self) -> ImageBuffer<T> {
+        sample_heatmap(&self.sample_counts, self.image.width, self.image.height)
+    }
+}
+
+/// Renders a scene as seen through a camera into a linear framebuffer.
+///
+/// The acceleration structure is built once when the renderer is created, after which
+/// `render` can be called as often as needed.
+pub struct Renderer<T>
+    where
+        T: FloatType<T> + Send + Sync,
+{
+    scene: HittableScene<T>,
+    camera: Camera<T>,
+    settings: RenderSettings,
+    bvh: LinearBvh<T>,
+    bvh_stats: BvhStats,
+    integrator: Box<dyn Integrator<T>>,
+    seed: u64,
+}
+
+impl<T> Renderer<T>
+    where
+        T: FloatType<T> + Send + Sync + Debug + 'static,
+{
+    /// Uses a path tracer configured by `settings` and `DEFAULT_SEED`, so rendering the same scene
+    /// twice gives the same image unless `with_seed` picks another one
+    pub fn new(scene: HittableScene<T>, camera: Camera<T>, settings: RenderSettings) -> Result<Renderer<T>> {
+        let (bvh, bvh_stats) = LinearBvh::with_stats(&scene.hittable_list[..], camera.time0, camera.time1)?;
+        let integrator =
+            Box::new(PathTracer::from_settings(&settings).with_pixel_spread(camera.pixel_spread(settings.height)));
+        Ok(Renderer {
+            scene,
+            camera,
+            settings,
+            bvh,
+            bvh_stats,
+            integrator,
+            seed: DEFAULT_SEED,
+        })
+    }
+
+    pub fn from_loaded(loaded: LoadedScene<T>) -> Result<Renderer<T>> {
+        Renderer::new(loaded.scene, loaded.camera, loaded.settings)
+    }
+
+    pub fn with_integrator(mut self, integrator: Box<dyn Integrator<T>>) -> Renderer<T> {
+        self.integrator = integrator;
+        self
+    }
+
+    /// Renders with the same seed and settings give the same image
+    pub fn with_seed(mut self, seed: u64) -> Renderer<T> {
+        self.seed = seed;
+        self
+    }
+
+    pub fn scene(&self) -> &HittableScene<T> {
+        &self.scene
+    }
+
+    pub fn camera(&self) -> &Camera<T> {
+        &self.camera
+    }
+
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    pub fn bvh_stats(&self) -> &BvhStats {
+        &self.bvh_stats
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Empty film covering the pixels to render, the crop window when there is one
+    pub fn new_film(&self) -> Film<T> {
+        Film::new(self.settings.film_area())
+    }
+
+    pub fn render(&self) -> Result<RenderOutput<T>> {
+        self.render_with_progress(|_tile, _done, _total| {})
+    }
+
+    /// Renders the image in one go, calling `progress` like `render_pass` does
+    pub fn render_with_progress<F>(&self, progress: F) -> Result<RenderOutput<T>>
+        where
+            F: Fn(usize, usize, usize) + Sync,
+    {
+        let mut film = self.new_film();
+        self.render_pass(&mut film, self.settings.samples, progress)?;
+        Ok(film.output())
+    }
+
+    /// Whether a pixel has the configured number of samples or, with adaptive sampling, converged
+    fn is_pixel_done(&self, stats: &PixelStats<T>) -> bool {
+        let samples = self.settings.samples;
+        match &self.settings.adaptive {
+            Some(adaptive) => {
+                stats.count >= samples
+                    || (stats.count >= adaptive.min_samples && stats.relative_error() <= adaptive.threshold)
+            }
+            None => stats.count >= samples,
+        }
+    }
+
+    /// Adds up to `pass_samples` samples to every pixel of the tile, continuing after the
+    /// samples it already holds
+    fn render_tile(&self, tile: &mut FilmTile<T>, pass_samples: u32, sampler: &mut dyn Sampler) -> Result<()> {
+        let width = self.settings.width;
+        let height = self.settings.height;
+        let area = tile.area;
+        for (row, pixels) in tile.rows.iter_mut().enumerate() {
+            //The film starts at the top row, the camera at the bottom
+            let j = height - 1 - (area.y + row);
+            for (column, stats) in pixels.iter_mut().enumerate() {
+                let i = area.x + column;
+                let end = stats.count.saturating_add(pass_samples);
+                while stats.count < end && !self.is_pixel_done(stats) {
+                    sampler.start_pixel_sample(i, j, stats.count);
+                    let (du, dv) = sampler.get_2d();
+                    let u = T::from(i as f64 + du - 0.5).unwrap() / T::from(width).unwrap();
+                    let v = T::from(j as f64 + dv - 0.5).unwrap() / T::from(height).unwrap();
+                    let ray = self.camera.get_ray(u, v, sampler);
+                    stats.add(&self.integrator.radiance(&ray, &self.scene, &self.bvh, sampler)?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds up to `pass_samples` samples to every pixel of `film`, continuing after the samples
+    /// it already holds, and returns whether the image is finished: every pixel has the
+    /// configured number of samples or, with adaptive sampling, converged. Splitting a render
+    /// into passes gives the same image as rendering it at once.
+    ///
+    /// The film is split into tiles in the configured order that rayon spreads over its threads,
+    /// threads that got cheap tiles steal more of them. `progress` is called with the index of
+    /// the tile, the number of finished tiles and the total number of tiles every time a tile is
+    /// done, from multiple threads.
+    pub fn render_pass<F>(&self, film: &mut Film<T>, pass_samples: u32, progress: F) -> Result<bool>
+        where
+            F: Fn(usize, usize, usize) + Sync,
+    {
+        let tiles = generate_tiles(&film.area, self.settings.tile_size, self.settings.tile_order);
+        let tiles_done = AtomicUsize::new(0);
+        film.split_tiles(&tiles).into_par_iter().enumerate().try_for_each_init(
+            //Sample values only depend on the pixel and sample index, not on the thread
+            || self.settings.sampler.create(self.settings.samples, self.seed),
+            |sampler, (index, mut tile)| -> Result<()> {
+                self.render_tile(&mut tile, pass_samples, sampler.as_mut())?;
+                let done = tiles_done.fetch_add(1, Ordering::Relaxed) + 1;
+                progress(index, done, tiles.len());
+                Ok(())
+            },
+        )?;
+
+        Ok(film.pixels.iter().all(|stats| self.is_pixel_done(stats)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use rayon::ThreadPoolBuilder;
+    use straal::Vec3;
+
+    use super::*;
+    use crate::io::SceneDescription;
+
+    const SCENE: &str = r#"
+[camera]
+look_from = [3, 2, 4]
+look_at = [0, 0.5, 0]
+vertical_fov = 40
+aperture = 0.1
+
+[render]
+width = 12
+height = 8
+samples = 4
+max_depth = 8
+
+[materials.ground]
+type = "lambertian"
+albedo = [0.5, 0.5, 0.5]
+
+[materials.glass]
+type = "dielectric"
+refractive_index = 1.5
+
+[materials.gold]
+type = "metal"
+albedo = [0.8, 0.6, 0.2]
+roughness = 0.3
+
+[materials.light]
+type = "diffuse_light"
+emit = [4, 4, 4]
+
+[[shapes]]
+type = "sphere"
+center = [0, -100, 0]
+radius = 100
+material = "ground"
+
+[[shapes]]
+type = "sphere"
+center = [0, 0.5, 0]
+radius = 0.5
+material = "glass"
+
+[[shapes]]
+type = "sphere"
+center = [1.2, 0.5, 0]
+radius = 0.5
+material = "gold"
+
+[[shapes]]
+type = "sphere"
+center = [0, 3, 1]
+radius = 0.5
+material = "light"
+"#;
+
+    /// Renders `SCENE` with `settings` added to its render table
+    fn render_on_threads(threads: usize, seed: u64, settings: &str) -> Vec<Vec3<f64>> {
+        let source = SCENE.replace("max_depth = 8\n", &format!("max_depth = 8\n{}", settings));
+        let loaded = SceneDescription::parse(&source, Path::new("test.toml"))
+            .unwrap()
+            .build::<f64>(Path::new(""))
+            .unwrap();
+        let renderer = Renderer::from_loaded(loaded).unwrap().with_seed(seed);
+        let pool = ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        pool.install(|| renderer.render()).unwrap().image.pixels
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_pixels_on_any_number_of_threads() {
+        let single = render_on_threads(1, 7, "");
+        let multi = render_on_threads(4, 7, "");
+        assert_eq!(bits(&single), bits(&multi));
+        assert_ne!(bits(&single), bits(&render_on_threads(4, 8, "")));
+    }
+
+    fn bits(pixels: &[Vec3<f64>]) -> Vec<[u64; 3]> {
+        pixels.iter().map(|p| [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]).collect()
+    }
+
+    #[test]
+    fn tiles_and_crops_dont_change_the_pixels() {
+        let full = bits(&render_on_threads(4, 7, ""));
+        for order in &["scanline", "spiral", "hilbert"] {
+            let tiled = render_on_threads(4, 7, &format!("tile_size = 5\ntile_order = \"{}\"\n", order));
+            assert_eq!(bits(&tiled), full, "{}", order);
+        }
+        let cropped = bits(&render_on_threads(3, 7, "tile_size = 3\ncrop = [2, 1, 7, 5]\n"));
+        let window: Vec<[u64; 3]> = (1..6).flat_map(|y| full[y * 12 + 2..y * 12 + 9].iter().cloned()).collect();
+        assert_eq!(cropped, window);
+    }
+}
@@ -0,0 +1,79 @@
+use crate::math::mix_bits;
+use crate::sampler::{hash_to_unit, permutation_element, pixel_seed, sample_hash, Sampler, ONE_MINUS_EPSILON};
+
+/// Bases of the Halton sequence, one prime per dimension
+pub const PRIMES: [u64; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107,
+    109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193, 197, 199, 211, 223, 227, 229,
+    233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307, 311,
+];
+
+/// Mirrors the digits of `index` in `base` around the radix point, with Owen scrambling: every
+/// digit is permuted, with a permutation that depends on the digits before it. Continues past
+/// the last digit of `index` until the remaining digits no longer change the result.
+pub fn scrambled_radical_inverse(base: u64, mut index: u64, seed: u64) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut value = 0.0;
+    let mut digit_weight = inverse_base;
+    let mut prefix_hash = seed;
+    while 1.0 - (base - 1) as f64 * digit_weight < 1.0 {
+        let next = index / base;
+        let digit = permutation_element((index - next * base) as u32, base as u32, prefix_hash as u32);
+        value += digit as f64 * digit_weight;
+        prefix_hash = mix_bits(prefix_hash ^ (digit as u64 + 1));
+        digit_weight *= inverse_base;
+        index = next;
+    }
+    value.min(ONE_MINUS_EPSILON)
+}
+
+/// The Halton sequence over the samples of a pixel, Owen scrambled per pixel and dimension so
+/// neighbouring pixels don't share their error and the higher dimensions don't line up.
+/// Dimensions past the table of primes get independent random values, the large bases
+/// wouldn't distribute a few samples any better.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel_seed: u64,
+    index: u32,
+    dimension: u32,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> HaltonSampler {
+        HaltonSampler {
+            seed,
+            pixel_seed: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.pixel_seed = pixel_seed(self.seed, x, y);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        match PRIMES.get(dimension as usize) {
+            Some(base) => {
+                let seed = sample_hash(self.pixel_seed, u32::MAX, dimension);
+                scrambled_radical_inverse(*base, self.index as u64, seed)
+            }
+            None => hash_to_unit(sample_hash(self.pixel_seed, self.index, dimension)),
+        }
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let u = self.get_1d();
+        (u, self.get_1d())
+    }
+}
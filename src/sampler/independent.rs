@@ -0,0 +1,43 @@
+use crate::sampler::{hash_to_unit, pixel_seed, sample_hash, Sampler};
+
+/// Uniform random values without any stratification, the baseline to compare against
+pub struct IndependentSampler {
+    seed: u64,
+    pixel_seed: u64,
+    index: u32,
+    dimension: u32,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> IndependentSampler {
+        IndependentSampler {
+            seed,
+            pixel_seed: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.pixel_seed = pixel_seed(self.seed, x, y);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let value = hash_to_unit(sample_hash(self.pixel_seed, self.index, self.dimension));
+        self.dimension += 1;
+        value
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let u = self.get_1d();
+        (u, self.get_1d())
+    }
+}
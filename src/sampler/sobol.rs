@@ -0,0 +1,143 @@
+use crate::math::mix_bits;
+use crate::sampler::{pixel_seed, Sampler, ONE_MINUS_EPSILON};
+
+/// Direction numbers of the second Sobol dimension, the first is the van der Corput sequence
+fn sobol_directions() -> [u32; 32] {
+    let mut directions = [0u32; 32];
+    let mut direction = 1 << 31;
+    for d in directions.iter_mut() {
+        *d = direction;
+        direction ^= direction >> 1;
+    }
+    directions
+}
+
+fn sobol_2d(index: u32, directions: &[u32; 32]) -> (u32, u32) {
+    let mut y = 0;
+    for (bit, direction) in directions.iter().enumerate() {
+        if index >> bit & 1 != 0 {
+            y ^= direction;
+        }
+    }
+    (index.reverse_bits(), y)
+}
+
+/// Hash based permutation where every bit only depends on the bits below it (Laine-Karras)
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+/// Owen scrambling: random permutations of every subinterval, which keeps the stratification of
+/// the points intact (Burley 2020)
+pub fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+fn to_unit(x: u32) -> f64 {
+    (x as f64 * (1.0 / 4_294_967_296.0)).min(ONE_MINUS_EPSILON)
+}
+
+/// Owen-scrambled Sobol points, padded: every 1D or 2D request uses the first two Sobol
+/// dimensions, with the sample order shuffled and the points scrambled differently for every
+/// request so the dimensions don't correlate. Works best with a power of two samples per pixel.
+pub struct SobolSampler {
+    seed: u64,
+    pixel_seed: u64,
+    index: u32,
+    dimension: u32,
+    directions: [u32; 32],
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> SobolSampler {
+        SobolSampler {
+            seed,
+            pixel_seed: 0,
+            index: 0,
+            dimension: 0,
+            directions: sobol_directions(),
+        }
+    }
+
+    fn point(&self) -> (u32, u32) {
+        let hash = mix_bits(self.pixel_seed ^ mix_bits(self.dimension as u64));
+        let scramble = mix_bits(hash);
+        let index = nested_uniform_scramble(self.index, hash as u32);
+        let (x, y) = sobol_2d(index, &self.directions);
+        (
+            nested_uniform_scramble(x, (hash >> 32) as u32),
+            nested_uniform_scramble(y, scramble as u32),
+        )
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.pixel_seed = pixel_seed(self.seed, x, y);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (x, _) = self.point();
+        self.dimension += 1;
+        to_unit(x)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (x, y) = self.point();
+        self.dimension += 2;
+        (to_unit(x), to_unit(y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn power_of_two_point_sets_are_nets() {
+        //Every elementary interval of area 1 / 2^m, 2^i by 2^(m - i) cells, holds exactly one of
+        //the 2^m points of a pixel, in every dimension pair
+        for m in 0..=6 {
+            let samples = 1u32 << m;
+            let mut sampler = SobolSampler::new(9);
+            for dimension in &[0, 2, 7] {
+                let points: Vec<(f64, f64)> = (0..samples)
+                    .map(|index| {
+                        sampler.start_pixel_sample(6, 1, index);
+                        sampler.set_dimension(*dimension);
+                        sampler.get_2d()
+                    })
+                    .collect();
+                for i in 0..=m {
+                    let (columns, rows) = (1u32 << i, 1u32 << (m - i));
+                    let mut counts = vec![0; samples as usize];
+                    for (u, v) in &points {
+                        counts[(*v * rows as f64) as usize * columns as usize + (*u * columns as f64) as usize] += 1;
+                    }
+                    assert!(counts.iter().all(|c| *c == 1), "m = {}, {}x{}: {:?}", m, columns, rows, counts);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scrambling_is_a_permutation_of_every_prefix() {
+        //The values starting with the same k bits stay together, so strata are kept intact
+        for seed in &[0, 1, 0xdead_beef] {
+            let mut seen: Vec<u32> = (0..256u32).map(|x| nested_uniform_scramble(x << 24, *seed) >> 24).collect();
+            seen.sort_unstable();
+            assert_eq!(seen, (0..256).collect::<Vec<u32>>());
+        }
+    }
+}
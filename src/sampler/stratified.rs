@@ -0,0 +1,150 @@
+use crate::math::mix_bits;
+use crate::sampler::{hash_to_unit, pixel_seed, sample_hash, Sampler, ONE_MINUS_EPSILON};
+
+/// Pseudo-random permutation of [0, length) picked by `seed`, evaluated for a single element
+/// without storing the permutation (Kensler 2013)
+pub fn permutation_element(mut i: u32, length: u32, seed: u32) -> u32 {
+    let mut w = length - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    //Permutes [0, w], retrying until the result lands inside [0, length)
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < length {
+            break;
+        }
+    }
+    ((i as u64 + seed as u64) % length as u64) as u32
+}
+
+/// Jittered stratification: every dimension is divided into one stratum per sample, 2D requests
+/// into a grid, and the samples of a pixel each get their own stratum, in a different random
+/// order for every dimension so the dimensions don't correlate.
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    seed: u64,
+    pixel_seed: u64,
+    index: u32,
+    dimension: u32,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> StratifiedSampler {
+        StratifiedSampler {
+            samples_per_pixel: samples_per_pixel.max(1),
+            seed,
+            pixel_seed: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// Stratum of the current sample among `strata`. Samples beyond the first `strata` start
+    /// over with a new permutation.
+    fn stratum(&self, strata: u32, hash: u64) -> u32 {
+        let round = self.index / strata;
+        let seed = mix_bits(hash ^ round as u64) as u32;
+        permutation_element(self.index % strata, strata, seed)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.pixel_seed = pixel_seed(self.seed, x, y);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        //The permutation must not depend on the sample index, the jitter should
+        let permutation_hash = sample_hash(self.pixel_seed, u32::MAX, self.dimension);
+        let jitter = hash_to_unit(sample_hash(self.pixel_seed, self.index, self.dimension));
+        let stratum = self.stratum(self.samples_per_pixel, permutation_hash);
+        self.dimension += 1;
+        ((stratum as f64 + jitter) / self.samples_per_pixel as f64).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        //A grid with at least one cell per sample, as square as possible
+        let columns = ((self.samples_per_pixel as f64).sqrt() as u32).max(1);
+        let rows = self.samples_per_pixel.div_ceil(columns);
+
+        let permutation_hash = sample_hash(self.pixel_seed, u32::MAX, self.dimension);
+        let jitter_x = hash_to_unit(sample_hash(self.pixel_seed, self.index, self.dimension));
+        let jitter_y = hash_to_unit(sample_hash(self.pixel_seed, self.index, self.dimension + 1));
+        let stratum = self.stratum(columns * rows, permutation_hash);
+        self.dimension += 2;
+        (
+            (((stratum % columns) as f64 + jitter_x) / columns as f64).min(ONE_MINUS_EPSILON),
+            (((stratum / columns) as f64 + jitter_y) / rows as f64).min(ONE_MINUS_EPSILON),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Strata hit by the samples of a pixel, for a 1D and a 2D request
+    fn strata(samples: u32, x: usize) -> (Vec<u32>, Vec<u32>) {
+        let columns = (samples as f64).sqrt() as u32;
+        let rows = samples.div_ceil(columns);
+        let mut sampler = StratifiedSampler::new(samples, 5);
+        let mut counts_1d = vec![0; samples as usize];
+        let mut counts_2d = vec![0; (columns * rows) as usize];
+        for index in 0..samples {
+            sampler.start_pixel_sample(x, 3, index);
+            counts_1d[(sampler.get_1d() * samples as f64) as usize] += 1;
+            let (u, v) = sampler.get_2d();
+            counts_2d[(v * rows as f64) as usize * columns as usize + (u * columns as f64) as usize] += 1;
+        }
+        (counts_1d, counts_2d)
+    }
+
+    #[test]
+    fn one_sample_per_stratum() {
+        for x in 0..8 {
+            let (counts_1d, counts_2d) = strata(16, x);
+            assert!(counts_1d.iter().all(|c| *c == 1), "{:?}", counts_1d);
+            assert!(counts_2d.iter().all(|c| *c == 1), "{:?}", counts_2d);
+
+            //Without a square grid some cells stay empty, but none gets two samples
+            let (counts_1d, counts_2d) = strata(7, x);
+            assert!(counts_1d.iter().all(|c| *c == 1), "{:?}", counts_1d);
+            assert!(counts_2d.iter().all(|c| *c <= 1), "{:?}", counts_2d);
+        }
+    }
+
+    #[test]
+    fn permutations_cover_every_element_once() {
+        for length in &[1, 2, 7, 16, 100] {
+            let mut seen: Vec<u32> = (0..*length).map(|i| permutation_element(i, *length, 0x1234_5678)).collect();
+            seen.sort_unstable();
+            assert_eq!(seen, (0..*length).collect::<Vec<u32>>());
+        }
+    }
+}
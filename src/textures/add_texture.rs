@@ -0,0 +1,32 @@
+use std::sync::Arc;
+
+use straal::{FloatType, Vec3};
+
+use crate::textures::{Texture, TextureLookup};
+
+/// The sum of any number of textures, for layering detail on top of a base
+pub struct AddTexture<T> {
+    pub textures: Vec<Arc<dyn Texture<T>>>,
+}
+
+impl<T> AddTexture<T>
+    where
+        T: FloatType<T>,
+{
+    pub fn new(textures: Vec<Arc<dyn Texture<T>>>) -> AddTexture<T> {
+        AddTexture { textures }
+    }
+}
+
+impl<T> Texture<T> for AddTexture<T>
+    where
+        T: FloatType<T> + Send + Sync,
+{
+    fn sample_color(&self, u: T, v: T, p: &Vec3<T>) -> Vec3<T> {
+        self.lookup(&TextureLookup::point(u, v, p))
+    }
+
+    fn lookup(&self, at: &TextureLookup<T>) -> Vec3<T> {
+        self.textures.iter().fold(Vec3::zero(), |sum, t| sum + t.lookup(at))
+    }
+}
@@ -0,0 +1,104 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+use straal::{FloatType, Vec3};
+
+use crate::textures::{Texture, TextureLookup};
+
+/// The coordinates a checker pattern is laid out in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckerMapping {
+    /// Cubes in world space, which never stretch but show the cut through them on curved surfaces
+    #[default]
+    Solid,
+    /// Squares in texture coordinates, which follow the surface parameterisation
+    Uv,
+}
+
+/// Alternates between two textures, `scale` cells per unit of distance or texture coordinate
+pub struct CheckerTexture<T> {
+    pub even: Arc<dyn Texture<T>>,
+    pub odd: Arc<dyn Texture<T>>,
+    pub scale: T,
+    pub mapping: CheckerMapping,
+}
+
+impl<T> CheckerTexture<T>
+    where
+        T: FloatType<T>,
+{
+    pub fn new(
+        even: Arc<dyn Texture<T>>,
+        odd: Arc<dyn Texture<T>>,
+        scale: T,
+        mapping: CheckerMapping,
+    ) -> CheckerTexture<T> {
+        CheckerTexture { even, odd, scale, mapping }
+    }
+
+    /// The texture of the cell that the lookup falls in
+    fn cell_texture(&self, u: T, v: T, p: &Vec3<T>) -> &dyn Texture<T> {
+        let cell = |x: T| -> i64 {
+            let x: f64 = num::cast((x * self.scale).floor()).unwrap_or(0.0);
+            x as i64
+        };
+        let parity = match self.mapping {
+            CheckerMapping::Solid => cell(p.x) + cell(p.y) + cell(p.z),
+            CheckerMapping::Uv => cell(u) + cell(v),
+        };
+        if parity & 1 == 0 {
+            self.even.as_ref()
+        } else {
+            self.odd.as_ref()
+        }
+    }
+}
+
+impl<T> Texture<T> for CheckerTexture<T>
+    where
+        T: FloatType<T> + Send + Sync,
+{
+    fn sample_color(&self, u: T, v: T, p: &Vec3<T>) -> Vec3<T> {
+        self.cell_texture(u, v, p).sample_color(u, v, p)
+    }
+
+    fn lookup(&self, at: &TextureLookup<T>) -> Vec3<T> {
+        self.cell_texture(at.u, at.v, &at.position).lookup(at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::textures::ConstantTexture;
+
+    fn checker(mapping: CheckerMapping) -> CheckerTexture<f64> {
+        CheckerTexture::new(
+            Arc::new(ConstantTexture::value(0.0)),
+            Arc::new(ConstantTexture::value(1.0)),
+            2.0,
+            mapping,
+        )
+    }
+
+    #[test]
+    fn cells_alternate_across_zero() {
+        let texture = checker(CheckerMapping::Solid);
+        let at = |x: f64, y: f64, z: f64| texture.sample_value(0.0, 0.0, &Vec3::new(x, y, z));
+        //Cells are half a unit wide, the ones either side of zero differ
+        let row: Vec<f64> = [-1.2, -0.7, -0.2, 0.2, 0.7, 1.2].iter().map(|x| at(*x, 0.1, 0.1)).collect();
+        assert_eq!(row, vec![1.0, 0.0, 1.0, 0.0, 1.0, 0.0]);
+        assert_eq!(at(-0.2, -0.2, 0.1), 0.0);
+        assert_eq!(at(-0.2, -0.2, -0.2), 1.0);
+    }
+
+    #[test]
+    fn uv_mapping_ignores_the_position() {
+        let texture = checker(CheckerMapping::Uv);
+        let at = |u: f64, v: f64, x: f64| texture.sample_value(u, v, &Vec3::new(x, 0.0, 0.0));
+        assert_eq!(at(0.2, 0.2, 0.0), at(0.2, 0.2, 0.7));
+        assert_eq!((at(0.2, 0.2, 0.0), at(0.7, 0.2, 0.0), at(0.7, 0.7, 0.0)), (0.0, 1.0, 0.0));
+        assert_eq!(at(-0.2, 0.2, 0.0), 1.0);
+    }
+}
@@ -18,10 +18,17 @@ impl<T> ConstantTexture<T> where T: FloatType<T> {
             color: c.clone()
         }
     }
+
+    /// The same value in every channel
+    pub fn value(v: T) -> ConstantTexture<T> {
+        ConstantTexture {
+            color: Vec3::<T>::all(v)
+        }
+    }
 }
 
 impl<T> Texture<T> for ConstantTexture<T> where T: FloatType<T> + Send + Sync {
-    fn sample_color(&self, u: T, v: T, p: &Vec3<T>) -> Vec3<T> {
+    fn sample_color(&self, _u: T, _v: T, _p: &Vec3<T>) -> Vec3<T> {
         self.color
     }
 }
\ No newline at end of file
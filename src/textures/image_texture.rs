@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+use straal::{FloatType, Vec3};
+
+use crate::textures::{MipMap, Texture, TextureFilter, TextureLookup, WrapMode};
+
+/// An image mapped onto texture coordinates, with (0, 0) at its bottom left corner
+pub struct ImageTexture<T> {
+    pub mip_map: Arc<MipMap<T>>,
+    pub wrap: WrapMode,
+    pub filter: TextureFilter,
+}
+
+impl<T> ImageTexture<T>
+    where
+        T: FloatType<T>,
+{
+    pub fn new(mip_map: Arc<MipMap<T>>, wrap: WrapMode, filter: TextureFilter) -> ImageTexture<T> {
+        ImageTexture { mip_map, wrap, filter }
+    }
+}
+
+impl<T> Texture<T> for ImageTexture<T>
+    where
+        T: FloatType<T> + Send + Sync,
+{
+    fn sample_color(&self, u: T, v: T, p: &Vec3<T>) -> Vec3<T> {
+        self.lookup(&TextureLookup::point(u, v, p))
+    }
+
+    fn lookup(&self, at: &TextureLookup<T>) -> Vec3<T> {
+        match self.filter {
+            TextureFilter::Nearest => self.mip_map.nearest(0, at.u, at.v, self.wrap),
+            TextureFilter::Bilinear => self.mip_map.bilinear(0, at.u, at.v, self.wrap),
+            TextureFilter::Trilinear => self.mip_map.trilinear(at.u, at.v, at.uv_width, self.wrap),
+        }
+    }
+}
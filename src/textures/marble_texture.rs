@@ -0,0 +1,44 @@
+use straal::{FloatType, Vec3};
+
+use crate::math::lerp;
+use crate::textures::{Perlin, Texture};
+
+/// Veins running across the z axis, bands of a sine wave whose phase is distorted by turbulence
+pub struct MarbleTexture<T> {
+    pub noise: Perlin,
+    /// Bands per 2π units of distance
+    pub scale: T,
+    pub octaves: u32,
+    /// How far the turbulence bends the bands, zero gives straight stripes
+    pub distortion: T,
+    /// The base colour and the colour of the veins
+    pub colors: [Vec3<T>; 2],
+}
+
+impl<T> MarbleTexture<T>
+    where
+        T: FloatType<T>,
+{
+    pub fn new(seed: u64, scale: T, octaves: u32, distortion: T, colors: [Vec3<T>; 2]) -> MarbleTexture<T> {
+        MarbleTexture {
+            noise: Perlin::new(seed),
+            scale,
+            octaves,
+            distortion,
+            colors,
+        }
+    }
+}
+
+impl<T> Texture<T> for MarbleTexture<T>
+    where
+        T: FloatType<T> + Send + Sync,
+{
+    fn sample_color(&self, _u: T, _v: T, p: &Vec3<T>) -> Vec3<T> {
+        let p = *p * self.scale;
+        let phase = p.z + self.distortion * self.noise.turbulence(&p, self.octaves);
+        //Thin veins where the wave is close to its peak
+        let wave = (T::one() + phase.sin()) / T::from(2).unwrap();
+        lerp(&self.colors[0], &self.colors[1], wave.powf(T::from(4).unwrap()))
+    }
+}
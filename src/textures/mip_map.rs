@@ -0,0 +1,241 @@
+use serde::Deserialize;
+use straal::{FloatType, Vec3};
+
+use crate::io::ImageBuffer;
+
+/// How texture coordinates outside [0, 1] are brought back onto the image
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WrapMode {
+    /// Tiles the image
+    #[default]
+    Repeat,
+    /// Stretches the edge texels
+    Clamp,
+    /// Tiles the image, flipping every other copy so the edges line up
+    Mirror,
+}
+
+impl WrapMode {
+    /// Index into a row or column of `size` texels for the texel at `i`
+    pub fn apply(self, i: i64, size: usize) -> usize {
+        let n = size as i64;
+        let wrapped = match self {
+            WrapMode::Repeat => i.rem_euclid(n),
+            WrapMode::Clamp => i.max(0).min(n - 1),
+            WrapMode::Mirror => {
+                let m = i.rem_euclid(2 * n);
+                if m < n {
+                    m
+                } else {
+                    2 * n - 1 - m
+                }
+            }
+        };
+        wrapped as usize
+    }
+}
+
+/// How image textures are reconstructed between and averaged over texels
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextureFilter {
+    /// The closest texel, blocky up close and aliased far away
+    Nearest,
+    /// Blends the four closest texels of the full resolution image, smooth up close but still
+    /// aliased when a pixel covers many texels
+    Bilinear,
+    /// Bilinear lookups in the two MIP levels whose texel size is closest to the footprint of a
+    /// pixel, blended, so distant textures are averaged instead of aliased
+    #[default]
+    Trilinear,
+}
+
+/// An image and successively halved copies of it down to a single texel, each texel of a level
+/// the average of the texels it covers in the level above
+pub struct MipMap<T> {
+    levels: Vec<ImageBuffer<T>>,
+}
+
+/// Averages `image` down to half its size, rounded down. Odd sizes let some destination texels
+/// cover three source texels instead of two.
+fn downsample<T>(image: &ImageBuffer<T>) -> ImageBuffer<T>
+    where
+        T: FloatType<T>,
+{
+    let width = (image.width / 2).max(1);
+    let height = (image.height / 2).max(1);
+    let span = |i: usize, size: usize, new_size: usize| {
+        (i * size / new_size, ((i + 1) * size).div_ceil(new_size))
+    };
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let (y0, y1) = span(y, image.height, height);
+        for x in 0..width {
+            let (x0, x1) = span(x, image.width, width);
+            let mut sum = Vec3::<T>::zero();
+            for row in image.pixels[y0 * image.width..y1 * image.width].chunks(image.width) {
+                for pixel in &row[x0..x1] {
+                    sum += *pixel;
+                }
+            }
+            pixels.push(sum / T::from((x1 - x0) * (y1 - y0)).unwrap());
+        }
+    }
+    ImageBuffer { width, height, pixels }
+}
+
+impl<T> MipMap<T>
+    where
+        T: FloatType<T>,
+{
+    pub fn new(image: ImageBuffer<T>) -> MipMap<T> {
+        let mut levels = vec![image];
+        loop {
+            let last = levels.last().unwrap();
+            if last.width == 1 && last.height == 1 {
+                break;
+            }
+            let next = downsample(last);
+            levels.push(next);
+        }
+        MipMap { levels }
+    }
+
+    pub fn width(&self) -> usize {
+        self.levels[0].width
+    }
+
+    pub fn height(&self) -> usize {
+        self.levels[0].height
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    fn texel(&self, level: usize, x: i64, y: i64, wrap: WrapMode) -> Vec3<T> {
+        let image = &self.levels[level];
+        image.pixels[wrap.apply(y, image.height) * image.width + wrap.apply(x, image.width)]
+    }
+
+    /// Continuous texel coordinates of (`u`, `v`) in `level`, `v` = 0 is the bottom row
+    fn texel_position(&self, level: usize, u: T, v: T) -> (f64, f64) {
+        let image = &self.levels[level];
+        let u: f64 = num::cast(u).unwrap_or(0.0);
+        let v: f64 = num::cast(v).unwrap_or(0.0);
+        (u * image.width as f64, (1.0 - v) * image.height as f64)
+    }
+
+    /// The texel of `level` that (`u`, `v`) falls in
+    pub fn nearest(&self, level: usize, u: T, v: T, wrap: WrapMode) -> Vec3<T> {
+        let (x, y) = self.texel_position(level, u, v);
+        self.texel(level, x.floor() as i64, y.floor() as i64, wrap)
+    }
+
+    /// Blend of the four texels of `level` whose centers surround (`u`, `v`)
+    pub fn bilinear(&self, level: usize, u: T, v: T, wrap: WrapMode) -> Vec3<T> {
+        let (x, y) = self.texel_position(level, u, v);
+        //Texel centers sit at half integer positions
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (T::from(x - x0).unwrap(), T::from(y - y0).unwrap());
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let row = |y: i64| self.texel(level, x0, y, wrap) * (T::one() - fx) + self.texel(level, x0 + 1, y, wrap) * fx;
+        row(y0) * (T::one() - fy) + row(y0 + 1) * fy
+    }
+
+    /// Average over a footprint `width` across in texture coordinates, interpolated between the
+    /// two levels whose texels are closest in size
+    pub fn trilinear(&self, u: T, v: T, width: T, wrap: WrapMode) -> Vec3<T> {
+        let width: f64 = num::cast(width).unwrap_or(0.0);
+        let texels = width * self.width().max(self.height()) as f64;
+        let last = self.levels.len() - 1;
+        if texels <= 1.0 {
+            return self.bilinear(0, u, v, wrap);
+        }
+        let level = texels.log2();
+        if level >= last as f64 {
+            return self.bilinear(last, u, v, wrap);
+        }
+        let lower = level.floor() as usize;
+        let t = T::from(level - lower as f64).unwrap();
+        self.bilinear(lower, u, v, wrap) * (T::one() - t) + self.bilinear(lower + 1, u, v, wrap) * t
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(width: usize, height: usize, values: &[f64]) -> ImageBuffer<f64> {
+        let pixels = values.iter().map(|v| Vec3::new(*v, *v, *v)).collect();
+        ImageBuffer { width, height, pixels }
+    }
+
+    #[test]
+    fn repeat_wraps_negative_indices() {
+        let wrapped: Vec<usize> = (-5..9).map(|i| WrapMode::Repeat.apply(i, 4)).collect();
+        assert_eq!(wrapped, vec![3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn clamp_stretches_the_edges() {
+        assert_eq!(WrapMode::Clamp.apply(-3, 4), 0);
+        assert_eq!(WrapMode::Clamp.apply(2, 4), 2);
+        assert_eq!(WrapMode::Clamp.apply(100, 4), 3);
+    }
+
+    #[test]
+    fn mirror_repeats_edge_texels() {
+        //Every copy starts with the texel the previous one ended with
+        let wrapped: Vec<usize> = (-5..13).map(|i| WrapMode::Mirror.apply(i, 4)).collect();
+        assert_eq!(wrapped, vec![3, 3, 2, 1, 0, 0, 1, 2, 3, 3, 2, 1, 0, 0, 1, 2, 3, 3]);
+        assert_eq!(WrapMode::Mirror.apply(7, 4), 0);
+        assert_eq!(WrapMode::Mirror.apply(-8, 4), 0);
+    }
+
+    #[test]
+    fn single_texel_images_always_give_that_texel() {
+        for mode in &[WrapMode::Repeat, WrapMode::Clamp, WrapMode::Mirror] {
+            for i in -3..3 {
+                assert_eq!(mode.apply(i, 1), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn levels_average_down_to_a_single_texel() {
+        let values: Vec<f64> = (0..15).map(f64::from).collect();
+        let mip_map = MipMap::new(gray(5, 3, &values));
+        assert_eq!(mip_map.level_count(), 3);
+        //Both texels of the 2x1 level cover the middle column of the odd width
+        assert_eq!(mip_map.nearest(1, 0.25, 0.5, WrapMode::Clamp).x, 6.0);
+        assert_eq!(mip_map.nearest(1, 0.75, 0.5, WrapMode::Clamp).x, 8.0);
+        assert_eq!(mip_map.nearest(2, 0.5, 0.5, WrapMode::Clamp).x, 7.0);
+    }
+
+    #[test]
+    fn bilinear_blends_between_texel_centers() {
+        let mip_map = MipMap::new(gray(2, 1, &[0.0, 1.0]));
+        assert_eq!(mip_map.bilinear(0, 0.25, 0.5, WrapMode::Clamp).x, 0.0);
+        assert_eq!(mip_map.bilinear(0, 0.5, 0.5, WrapMode::Clamp).x, 0.5);
+        assert_eq!(mip_map.bilinear(0, 0.75, 0.5, WrapMode::Clamp).x, 1.0);
+        //Past the last texel center repeat blends back towards the first texel
+        assert_eq!(mip_map.bilinear(0, 0.875, 0.5, WrapMode::Clamp).x, 1.0);
+        assert_eq!(mip_map.bilinear(0, 0.875, 0.5, WrapMode::Repeat).x, 0.75);
+    }
+
+    #[test]
+    fn trilinear_picks_the_levels_matching_the_footprint() {
+        //Levels of 4, 2 and 1 texels, with 0, 0.5 and 0.75 at the left edge
+        let mip_map = MipMap::new(gray(4, 1, &[0.0, 1.0, 1.0, 1.0]));
+        let at_width = |width: f64| mip_map.trilinear(0.125, 0.5, width, WrapMode::Clamp).x;
+        assert_eq!(at_width(0.0), 0.0);
+        assert_eq!(at_width(0.25), 0.0);
+        assert_eq!(at_width(0.5), 0.5);
+        assert!((at_width(0.5 * 2f64.sqrt()) - 0.625).abs() < 1e-9);
+        assert_eq!(at_width(1.0), 0.75);
+        assert_eq!(at_width(100.0), 0.75);
+    }
+}
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use straal::{FloatType, Vec3};
+
+use crate::textures::{Texture, TextureLookup};
+
+/// Blends `layer` over `base` where `mask` is one, per channel. The mask is clamped to [0, 1].
+pub struct MixTexture<T> {
+    pub base: Arc<dyn Texture<T>>,
+    pub layer: Arc<dyn Texture<T>>,
+    pub mask: Arc<dyn Texture<T>>,
+}
+
+impl<T> MixTexture<T>
+    where
+        T: FloatType<T>,
+{
+    pub fn new(base: Arc<dyn Texture<T>>, layer: Arc<dyn Texture<T>>, mask: Arc<dyn Texture<T>>) -> MixTexture<T> {
+        MixTexture { base, layer, mask }
+    }
+}
+
+impl<T> Texture<T> for MixTexture<T>
+    where
+        T: FloatType<T> + Send + Sync,
+{
+    fn sample_color(&self, u: T, v: T, p: &Vec3<T>) -> Vec3<T> {
+        self.lookup(&TextureLookup::point(u, v, p))
+    }
+
+    fn lookup(&self, at: &TextureLookup<T>) -> Vec3<T> {
+        let clamp = |x: T| T::max(T::zero(), T::min(x, T::one()));
+        let m = self.mask.lookup(at);
+        let m = Vec3::<T> {
+            x: clamp(m.x),
+            y: clamp(m.y),
+            z: clamp(m.z),
+        };
+        //Only sample the inputs that contribute, masks are often zero or one over large areas
+        let all = |c: T| m.x == c && m.y == c && m.z == c;
+        let base = if all(T::one()) { Vec3::zero() } else { self.base.lookup(at) };
+        let layer = if all(T::zero()) { Vec3::zero() } else { self.layer.lookup(at) };
+        base * (Vec3::one() - m) + layer * m
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::textures::ConstantTexture;
+
+    /// Fails the test when looked up
+    struct Unused;
+
+    impl Texture<f64> for Unused {
+        fn sample_color(&self, _u: f64, _v: f64, _p: &Vec3<f64>) -> Vec3<f64> {
+            panic!("a texture the mask hides was sampled")
+        }
+    }
+
+    fn mix(base: Arc<dyn Texture<f64>>, layer: Arc<dyn Texture<f64>>, mask: Vec3<f64>) -> Vec3<f64> {
+        MixTexture::new(base, layer, Arc::new(ConstantTexture::new(&mask))).sample_color(0.0, 0.0, &Vec3::zero())
+    }
+
+    #[test]
+    fn masks_of_zero_and_one_pick_a_single_input() {
+        let base = Arc::new(ConstantTexture::new(&Vec3::new(1.0, 2.0, 3.0)));
+        let layer = Arc::new(ConstantTexture::value(5.0));
+        assert_eq!(mix(base.clone(), Arc::new(Unused), Vec3::zero()), Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(mix(Arc::new(Unused), layer.clone(), Vec3::one()), Vec3::all(5.0));
+        //Values outside [0, 1] are clamped before the check
+        assert_eq!(mix(base, Arc::new(Unused), Vec3::all(-2.0)), Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(mix(Arc::new(Unused), layer, Vec3::all(3.0)), Vec3::all(5.0));
+    }
+
+    #[test]
+    fn the_mask_blends_per_channel() {
+        let base = Arc::new(ConstantTexture::value(0.0));
+        let layer = Arc::new(ConstantTexture::value(4.0));
+        assert_eq!(mix(base, layer, Vec3::new(0.0, 0.25, 1.0)), Vec3::new(0.0, 1.0, 4.0));
+    }
+}
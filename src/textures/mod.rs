@@ -1,9 +1,101 @@
 use straal::{FloatType, Vec3};
 
+use crate::geometry::HitRecord;
+
+pub use add_texture::*;
+pub use checker_texture::*;
 pub use constant_texture::*;
+pub use image_texture::*;
+pub use marble_texture::*;
+pub use mip_map::*;
+pub use mix_texture::*;
+pub use multiply_texture::*;
+pub use noise_texture::*;
+pub use perlin::*;
+pub use ramp_texture::*;
+pub use scale_texture::*;
+pub use texture_cache::*;
+pub use triplanar_texture::*;
+pub use uv_transform_texture::*;
+pub use wood_texture::*;
 
+pub mod add_texture;
+pub mod checker_texture;
 pub mod constant_texture;
+pub mod image_texture;
+pub mod marble_texture;
+pub mod mip_map;
+pub mod mix_texture;
+pub mod multiply_texture;
+pub mod noise_texture;
+pub mod perlin;
+pub mod ramp_texture;
+pub mod scale_texture;
+pub mod texture_cache;
+pub mod triplanar_texture;
+pub mod uv_transform_texture;
+pub mod wood_texture;
 
 pub trait Texture<T>: Send + Sync where T: FloatType<T> {
     fn sample_color(&self, u: T, v: T, p: &Vec3<T>) -> Vec3<T>;
+
+    /// Single channel lookup, for inputs like roughness. The average of the color channels.
+    fn sample_value(&self, u: T, v: T, p: &Vec3<T>) -> T {
+        let c = self.sample_color(u, v, p);
+        (c.x + c.y + c.z) / T::from(3).unwrap()
+    }
+
+    /// Lookup that knows the surface normal and the footprint of the pixel, so detail finer than
+    /// a pixel can be averaged instead of aliased. Textures that don't need either sample a point.
+    fn lookup(&self, at: &TextureLookup<T>) -> Vec3<T> {
+        self.sample_color(at.u, at.v, &at.position)
+    }
+
+    /// Single channel version of `lookup`
+    fn lookup_value(&self, at: &TextureLookup<T>) -> T {
+        let c = self.lookup(at);
+        (c.x + c.y + c.z) / T::from(3).unwrap()
+    }
+}
+
+/// Where a texture is looked up
+#[derive(Clone, Copy, Debug)]
+pub struct TextureLookup<T> {
+    pub u: T,
+    pub v: T,
+    pub position: Vec3<T>,
+    /// Geometric normal, zero when unknown
+    pub normal: Vec3<T>,
+    /// Width of the area around the point that one pixel covers
+    pub width: T,
+    /// The same width in texture coordinates
+    pub uv_width: T,
+}
+
+impl<T> TextureLookup<T>
+    where
+        T: FloatType<T> + Send + Sync,
+{
+    pub fn at(record: &HitRecord<T>) -> TextureLookup<T> {
+        TextureLookup {
+            u: record.u,
+            v: record.v,
+            position: record.position,
+            normal: record.normal,
+            width: record.footprint,
+            uv_width: record.uv_footprint(),
+        }
+    }
+
+    /// A single point without a normal, as sampled by `Texture::sample_color`
+    pub fn point(u: T, v: T, p: &Vec3<T>) -> TextureLookup<T> {
+        TextureLookup {
+            u,
+            v,
+            position: *p,
+            normal: Vec3::zero(),
+            width: T::zero(),
+            uv_width: T::zero(),
+        }
+    }
 }
\ No newline at end of file
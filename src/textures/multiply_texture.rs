@@ -0,0 +1,32 @@
+use std::sync::Arc;
+
+use straal::{FloatType, Vec3};
+
+use crate::textures::{Texture, TextureLookup};
+
+/// The product of any number of textures, per channel, for tinting or darkening one by another
+pub struct MultiplyTexture<T> {
+    pub textures: Vec<Arc<dyn Texture<T>>>,
+}
+
+impl<T> MultiplyTexture<T>
+    where
+        T: FloatType<T>,
+{
+    pub fn new(textures: Vec<Arc<dyn Texture<T>>>) -> MultiplyTexture<T> {
+        MultiplyTexture { textures }
+    }
+}
+
+impl<T> Texture<T> for MultiplyTexture<T>
+    where
+        T: FloatType<T> + Send + Sync,
+{
+    fn sample_color(&self, u: T, v: T, p: &Vec3<T>) -> Vec3<T> {
+        self.lookup(&TextureLookup::point(u, v, p))
+    }
+
+    fn lookup(&self, at: &TextureLookup<T>) -> Vec3<T> {
+        self.textures.iter().fold(Vec3::one(), |product, t| product * t.lookup(at))
+    }
+}
@@ -0,0 +1,45 @@
+use straal::{FloatType, Vec3};
+
+use crate::math::lerp;
+use crate::textures::{Perlin, Texture};
+
+/// Perlin noise blended between two colours. Plain noise is smooth and mapped from [-1, 1],
+/// turbulence creases where the noise crosses zero and is mapped from [0, 1].
+pub struct NoiseTexture<T> {
+    pub noise: Perlin,
+    /// Noise features per unit of distance
+    pub scale: T,
+    pub octaves: u32,
+    pub turbulence: bool,
+    pub colors: [Vec3<T>; 2],
+}
+
+impl<T> NoiseTexture<T>
+    where
+        T: FloatType<T>,
+{
+    pub fn new(seed: u64, scale: T, octaves: u32, turbulence: bool, colors: [Vec3<T>; 2]) -> NoiseTexture<T> {
+        NoiseTexture {
+            noise: Perlin::new(seed),
+            scale,
+            octaves,
+            turbulence,
+            colors,
+        }
+    }
+}
+
+impl<T> Texture<T> for NoiseTexture<T>
+    where
+        T: FloatType<T> + Send + Sync,
+{
+    fn sample_color(&self, _u: T, _v: T, p: &Vec3<T>) -> Vec3<T> {
+        let p = *p * self.scale;
+        let t = if self.turbulence {
+            self.noise.turbulence(&p, self.octaves)
+        } else {
+            (self.noise.fbm(&p, self.octaves) + T::one()) / T::from(2).unwrap()
+        };
+        lerp(&self.colors[0], &self.colors[1], T::max(T::zero(), T::min(t, T::one())))
+    }
+}
@@ -0,0 +1,177 @@
+use rand::RngCore;
+use straal::{FloatType, Vec3};
+
+use crate::math::Pcg32;
+
+/// Keeps the permutations independent of the other generators seeded with the same value
+const PERLIN_STREAM: u64 = 0x7065_726c_696e;
+
+/// Directions to the edge midpoints of a cube, the last four repeated so a hash picks one with
+/// a mask instead of a modulo
+const GRADIENTS: [[f64; 3]; 16] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [0.0, -1.0, 1.0],
+    [0.0, -1.0, -1.0],
+];
+
+/// Gradient noise (Perlin 2002). The lattice is shuffled with a seed, so the same seed always
+/// gives the same noise.
+#[derive(Clone, Debug)]
+pub struct Perlin {
+    /// A permutation of 0..256, stored twice so lookups of a hash plus a coordinate don't wrap
+    permutation: Vec<u8>,
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+fn gradient(hash: u8, x: f64, y: f64, z: f64) -> f64 {
+    let g = GRADIENTS[(hash & 15) as usize];
+    g[0] * x + g[1] * y + g[2] * z
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Perlin {
+        let mut rng = Pcg32::new(seed, PERLIN_STREAM);
+        let mut shuffled: Vec<u8> = (0..=255).collect();
+        for i in (1..shuffled.len()).rev() {
+            let j = rng.next_u32() as usize % (i + 1);
+            shuffled.swap(i, j);
+        }
+        let mut permutation = shuffled.clone();
+        permutation.extend_from_slice(&shuffled);
+        Perlin { permutation }
+    }
+
+    fn hash(&self, x: usize, y: usize, z: usize) -> u8 {
+        let p = &self.permutation;
+        p[p[p[x] as usize + y] as usize + z]
+    }
+
+    fn noise_f64(&self, x: f64, y: f64, z: f64) -> f64 {
+        let (xf, yf, zf) = (x.floor(), y.floor(), z.floor());
+        let (xi, yi, zi) = ((xf as i64 & 255) as usize, (yf as i64 & 255) as usize, (zf as i64 & 255) as usize);
+        let (x, y, z) = (x - xf, y - yf, z - zf);
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+
+        let corner = |dx: usize, dy: usize, dz: usize| {
+            let hash = self.hash(xi + dx, yi + dy, zi + dz);
+            gradient(hash, x - dx as f64, y - dy as f64, z - dz as f64)
+        };
+        lerp(
+            w,
+            lerp(v, lerp(u, corner(0, 0, 0), corner(1, 0, 0)), lerp(u, corner(0, 1, 0), corner(1, 1, 0))),
+            lerp(v, lerp(u, corner(0, 0, 1), corner(1, 0, 1)), lerp(u, corner(0, 1, 1), corner(1, 1, 1))),
+        )
+    }
+
+    /// Smooth noise in roughly [-1, 1], zero at every integer lattice point
+    pub fn noise<T>(&self, p: &Vec3<T>) -> T
+        where
+            T: FloatType<T>,
+    {
+        let f = |x: T| -> f64 { num::cast(x).unwrap_or(0.0) };
+        T::from(self.noise_f64(f(p.x), f(p.y), f(p.z))).unwrap()
+    }
+
+    /// Fractional Brownian motion: `octaves` layers of noise, each at twice the frequency and
+    /// half the amplitude of the previous one
+    pub fn fbm<T>(&self, p: &Vec3<T>, octaves: u32) -> T
+        where
+            T: FloatType<T>,
+    {
+        self.octaves(p, octaves, |n| n)
+    }
+
+    /// Like `fbm`, but sums the absolute value of every layer, which gives sharp creases where
+    /// the noise crosses zero. Always positive.
+    pub fn turbulence<T>(&self, p: &Vec3<T>, octaves: u32) -> T
+        where
+            T: FloatType<T>,
+    {
+        self.octaves(p, octaves, f64::abs)
+    }
+
+    fn octaves<T, F>(&self, p: &Vec3<T>, octaves: u32, layer: F) -> T
+        where
+            T: FloatType<T>,
+            F: Fn(f64) -> f64,
+    {
+        let f = |x: T| -> f64 { num::cast(x).unwrap_or(0.0) };
+        let (mut x, mut y, mut z) = (f(p.x), f(p.y), f(p.z));
+        let mut sum = 0.0;
+        let mut amplitude = 1.0;
+        for _ in 0..octaves {
+            sum += amplitude * layer(self.noise_f64(x, y, z));
+            amplitude *= 0.5;
+            x *= 2.0;
+            y *= 2.0;
+            z *= 2.0;
+        }
+        T::from(sum).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points() -> Vec<Vec3<f64>> {
+        (0..64)
+            .map(|i| {
+                let i = i as f64;
+                Vec3::new(i * 0.37 - 11.3, i * 1.21 + 0.5, 3.7 - i * 0.83)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_seed_decides_the_noise() {
+        let (a, b, c) = (Perlin::new(3), Perlin::new(3), Perlin::new(4));
+        let noise = |perlin: &Perlin| -> Vec<u64> { points().iter().map(|p| perlin.noise(p).to_bits()).collect() };
+        assert_eq!(noise(&a), noise(&b));
+        assert_ne!(noise(&a), noise(&c));
+        assert!(points().iter().all(|p| a.noise(p).abs() <= 1.0));
+    }
+
+    #[test]
+    fn noise_is_zero_at_lattice_points() {
+        let perlin = Perlin::new(9);
+        for x in -3..3 {
+            for y in -3..3 {
+                for z in &[-257, 0, 300] {
+                    let p = Vec3::<f64>::new(x as f64, y as f64, *z as f64);
+                    assert_eq!(perlin.noise(&p), 0.0, "{:?}", (x, y, z));
+                }
+            }
+        }
+        assert_ne!(perlin.noise(&Vec3::<f64>::new(0.5, 0.25, 0.75)), 0.0);
+    }
+
+    #[test]
+    fn turbulence_is_never_negative() {
+        let perlin = Perlin::new(1);
+        for p in points() {
+            assert!(perlin.turbulence(&p, 6) >= 0.0);
+            assert_eq!(perlin.fbm(&p, 1), perlin.noise(&p));
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::Arc;
+
+use straal::{FloatType, Vec3};
+
+use crate::math::lerp;
+use crate::textures::{Texture, TextureLookup};
+
+/// Remaps the value of another texture (the average of its channels) to a colour, interpolating
+/// linearly between the colours of the stops around it. Values beyond the first or last stop
+/// take its colour.
+pub struct RampTexture<T> {
+    pub input: Arc<dyn Texture<T>>,
+    /// Positions and colours, sorted by position
+    pub stops: Vec<(T, Vec3<T>)>,
+}
+
+impl<T> RampTexture<T>
+    where
+        T: FloatType<T>,
+{
+    /// `stops` has to have at least one entry
+    pub fn new(input: Arc<dyn Texture<T>>, mut stops: Vec<(T, Vec3<T>)>) -> RampTexture<T> {
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        RampTexture { input, stops }
+    }
+
+    pub fn remap(&self, value: T) -> Vec3<T> {
+        let next = self.stops.iter().position(|(position, _)| *position > value);
+        match next {
+            Some(0) => self.stops[0].1,
+            Some(i) => {
+                let (p0, c0) = self.stops[i - 1];
+                let (p1, c1) = self.stops[i];
+                lerp(&c0, &c1, (value - p0) / (p1 - p0))
+            }
+            None => self.stops[self.stops.len() - 1].1,
+        }
+    }
+}
+
+impl<T> Texture<T> for RampTexture<T>
+    where
+        T: FloatType<T> + Send + Sync,
+{
+    fn sample_color(&self, u: T, v: T, p: &Vec3<T>) -> Vec3<T> {
+        self.lookup(&TextureLookup::point(u, v, p))
+    }
+
+    fn lookup(&self, at: &TextureLookup<T>) -> Vec3<T> {
+        self.remap(self.input.lookup_value(at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::textures::ConstantTexture;
+
+    fn ramp(stops: Vec<(f64, Vec3<f64>)>) -> RampTexture<f64> {
+        RampTexture::new(Arc::new(ConstantTexture::value(0.0)), stops)
+    }
+
+    #[test]
+    fn values_between_stops_are_interpolated() {
+        //Stops are sorted, so the order they're given in doesn't matter
+        let texture = ramp(vec![(1.0, Vec3::all(4.0)), (0.0, Vec3::zero()), (0.5, Vec3::all(1.0))]);
+        assert_eq!(texture.remap(0.0), Vec3::zero());
+        assert_eq!(texture.remap(0.25), Vec3::all(0.5));
+        assert_eq!(texture.remap(0.5), Vec3::all(1.0));
+        assert_eq!(texture.remap(0.75), Vec3::all(2.5));
+    }
+
+    #[test]
+    fn values_past_the_ends_take_the_end_colours() {
+        let texture = ramp(vec![(0.2, Vec3::new(1.0, 0.0, 0.0)), (0.8, Vec3::new(0.0, 0.0, 1.0))]);
+        assert_eq!(texture.remap(-3.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(texture.remap(0.8), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(texture.remap(7.0), Vec3::new(0.0, 0.0, 1.0));
+        let single = ramp(vec![(0.5, Vec3::all(0.3))]);
+        assert_eq!(single.remap(0.0), Vec3::all(0.3));
+        assert_eq!(single.remap(1.0), Vec3::all(0.3));
+    }
+
+    #[test]
+    fn the_input_is_remapped() {
+        let input = Arc::new(ConstantTexture::new(&Vec3::new(0.0, 0.5, 1.0)));
+        let texture = RampTexture::new(input, vec![(0.0, Vec3::zero()), (1.0, Vec3::all(2.0))]);
+        assert_eq!(texture.sample_color(0.0, 0.0, &Vec3::zero()), Vec3::all(1.0));
+    }
+}
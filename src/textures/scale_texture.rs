@@ -0,0 +1,33 @@
+use std::sync::Arc;
+
+use straal::{FloatType, Vec3};
+
+use crate::textures::{Texture, TextureLookup};
+
+/// Another texture multiplied by a constant, to brighten or darken it
+pub struct ScaleTexture<T> {
+    pub texture: Arc<dyn Texture<T>>,
+    pub factor: T,
+}
+
+impl<T> ScaleTexture<T>
+    where
+        T: FloatType<T>,
+{
+    pub fn new(texture: Arc<dyn Texture<T>>, factor: T) -> ScaleTexture<T> {
+        ScaleTexture { texture, factor }
+    }
+}
+
+impl<T> Texture<T> for ScaleTexture<T>
+    where
+        T: FloatType<T> + Send + Sync,
+{
+    fn sample_color(&self, u: T, v: T, p: &Vec3<T>) -> Vec3<T> {
+        self.lookup(&TextureLookup::point(u, v, p))
+    }
+
+    fn lookup(&self, at: &TextureLookup<T>) -> Vec3<T> {
+        self.texture.lookup(at) * self.factor
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use straal::FloatType;
+
+use crate::io::{read_image_file, ImageError};
+use crate::textures::MipMap;
+
+/// Decoded images shared by every texture that uses them, so a file used by several materials
+/// is only read and filtered once. Color and data lookups of the same file are decoded
+/// separately, since only the first is converted from sRGB.
+pub struct TextureCache<T> {
+    images: HashMap<(PathBuf, bool), Arc<MipMap<T>>>,
+}
+
+impl<T> TextureCache<T>
+    where
+        T: FloatType<T>,
+{
+    pub fn new() -> TextureCache<T> {
+        TextureCache { images: HashMap::new() }
+    }
+
+    pub fn load(&mut self, path: &Path, srgb: bool) -> Result<Arc<MipMap<T>>, ImageError> {
+        let key = (path.to_path_buf(), srgb);
+        if let Some(mip_map) = self.images.get(&key) {
+            return Ok(mip_map.clone());
+        }
+        let mip_map = Arc::new(MipMap::new(read_image_file(path, srgb)?));
+        self.images.insert(key, mip_map.clone());
+        Ok(mip_map)
+    }
+
+    /// The files of the images loaded, sorted
+    pub fn paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self.images.keys().map(|(path, _)| path.clone()).collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    /// Number of distinct images loaded
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+}
+
+impl<T> Default for TextureCache<T>
+    where
+        T: FloatType<T>,
+{
+    fn default() -> Self {
+        TextureCache::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn images_are_shared_per_file_and_color_space() {
+        let path = std::env::temp_dir().join(format!("straaljager_texture_cache_{}.ppm", std::process::id()));
+        fs::write(&path, "P3\n1 1\n255\n128 128 128\n").unwrap();
+        let mut cache = TextureCache::<f64>::new();
+        let color = cache.load(&path, true).unwrap();
+        let data = cache.load(&path, false).unwrap();
+        let again = cache.load(&path, true).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(Arc::ptr_eq(&color, &again));
+        assert!(!Arc::ptr_eq(&color, &data));
+        assert!(color.nearest(0, 0.5, 0.5, Default::default()).x < data.nearest(0, 0.5, 0.5, Default::default()).x);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.paths(), vec![path]);
+    }
+
+    #[test]
+    fn failed_loads_are_not_cached() {
+        let mut cache = TextureCache::<f64>::new();
+        assert!(cache.load(Path::new("does/not/exist.png"), true).is_err());
+        assert!(cache.is_empty());
+        assert!(cache.paths().is_empty());
+    }
+}
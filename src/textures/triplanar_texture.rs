@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use straal::{FloatType, Vec3};
+
+use crate::textures::{Texture, TextureLookup};
+
+/// Projects another texture onto the surface along the x, y and z axes and blends the three by
+/// how much the surface faces each axis. Needs no texture coordinates, so it textures meshes
+/// without them, and doesn't stretch on spheres the way their coordinates do near the poles.
+pub struct TriplanarTexture<T> {
+    pub texture: Arc<dyn Texture<T>>,
+    /// Texture repeats per unit of distance
+    pub scale: T,
+    /// Higher values narrow the blend between projections, towards hard seams
+    pub sharpness: T,
+}
+
+impl<T> TriplanarTexture<T>
+    where
+        T: FloatType<T>,
+{
+    pub fn new(texture: Arc<dyn Texture<T>>, scale: T, sharpness: T) -> TriplanarTexture<T> {
+        TriplanarTexture { texture, scale, sharpness }
+    }
+
+    /// Blend weights of the x, y and z projections, equal when the normal is unknown
+    fn weights(&self, normal: &Vec3<T>) -> [T; 3] {
+        let w = [normal.x, normal.y, normal.z].map(|n| n.abs().powf(self.sharpness));
+        let sum = w[0] + w[1] + w[2];
+        if sum <= T::zero() {
+            let third = T::one() / T::from(3).unwrap();
+            return [third, third, third];
+        }
+        [w[0] / sum, w[1] / sum, w[2] / sum]
+    }
+}
+
+impl<T> Texture<T> for TriplanarTexture<T>
+    where
+        T: FloatType<T> + Send + Sync,
+{
+    fn sample_color(&self, u: T, v: T, p: &Vec3<T>) -> Vec3<T> {
+        self.lookup(&TextureLookup::point(u, v, p))
+    }
+
+    fn lookup(&self, at: &TextureLookup<T>) -> Vec3<T> {
+        let p = at.position * self.scale;
+        let projections = [(p.z, p.y), (p.x, p.z), (p.x, p.y)];
+        let mut color = Vec3::zero();
+        for (weight, (u, v)) in self.weights(&at.normal).iter().zip(projections.iter()) {
+            //Projections the surface barely faces aren't worth a lookup
+            if *weight < T::from(0.001).unwrap() {
+                continue;
+            }
+            let projected = TextureLookup {
+                u: *u,
+                v: *v,
+                uv_width: at.width * self.scale,
+                ..*at
+            };
+            color += self.texture.lookup(&projected) * *weight;
+        }
+        color
+    }
+}
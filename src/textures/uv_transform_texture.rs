@@ -0,0 +1,93 @@
+use std::sync::Arc;
+
+use straal::{FloatType, Vec3};
+
+use crate::textures::{Texture, TextureLookup};
+
+/// Looks another texture up at transformed texture coordinates: scaled, then rotated
+/// counterclockwise around the origin, then offset
+pub struct UvTransformTexture<T> {
+    pub texture: Arc<dyn Texture<T>>,
+    pub offset: (T, T),
+    pub scale: (T, T),
+    /// Counterclockwise, in degrees
+    pub rotation: T,
+}
+
+impl<T> UvTransformTexture<T>
+    where
+        T: FloatType<T>,
+{
+    pub fn new(texture: Arc<dyn Texture<T>>, offset: (T, T), scale: (T, T), rotation: T) -> UvTransformTexture<T> {
+        UvTransformTexture { texture, offset, scale, rotation }
+    }
+
+    pub fn transform(&self, u: T, v: T) -> (T, T) {
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (u, v) = (u * self.scale.0, v * self.scale.1);
+        (u * cos - v * sin + self.offset.0, u * sin + v * cos + self.offset.1)
+    }
+}
+
+impl<T> Texture<T> for UvTransformTexture<T>
+    where
+        T: FloatType<T> + Send + Sync,
+{
+    fn sample_color(&self, u: T, v: T, p: &Vec3<T>) -> Vec3<T> {
+        self.lookup(&TextureLookup::point(u, v, p))
+    }
+
+    fn lookup(&self, at: &TextureLookup<T>) -> Vec3<T> {
+        let (u, v) = self.transform(at.u, at.v);
+        //The footprint grows with the larger of the two scales, so filtering stays conservative
+        let stretch = T::max(self.scale.0.abs(), self.scale.1.abs());
+        self.texture.lookup(&TextureLookup {
+            u,
+            v,
+            uv_width: at.uv_width * stretch,
+            ..*at
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the coordinates and footprint it's looked up with
+    struct Coordinates;
+
+    impl Texture<f64> for Coordinates {
+        fn sample_color(&self, u: f64, v: f64, p: &Vec3<f64>) -> Vec3<f64> {
+            self.lookup(&TextureLookup::point(u, v, p))
+        }
+
+        fn lookup(&self, at: &TextureLookup<f64>) -> Vec3<f64> {
+            Vec3::new(at.u, at.v, at.uv_width)
+        }
+    }
+
+    fn transform(offset: (f64, f64), scale: (f64, f64), rotation: f64) -> UvTransformTexture<f64> {
+        UvTransformTexture::new(Arc::new(Coordinates), offset, scale, rotation)
+    }
+
+    fn assert_close(a: (f64, f64), b: (f64, f64)) {
+        assert!((a.0 - b.0).abs() < 1e-12 && (a.1 - b.1).abs() < 1e-12, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn scale_then_rotate_then_offset() {
+        assert_close(transform((0.0, 0.0), (1.0, 1.0), 0.0).transform(0.3, 0.7), (0.3, 0.7));
+        assert_close(transform((0.5, -1.0), (2.0, 3.0), 0.0).transform(0.5, 0.5), (1.5, 0.5));
+        assert_close(transform((0.0, 0.0), (1.0, 1.0), 90.0).transform(1.0, 0.0), (0.0, 1.0));
+        //Scaled to (2, 0), rotated a quarter turn to (0, 2), then offset
+        assert_close(transform((1.0, 1.0), (2.0, 5.0), 90.0).transform(1.0, 0.0), (1.0, 3.0));
+    }
+
+    #[test]
+    fn the_footprint_grows_with_the_larger_scale() {
+        let texture = transform((0.0, 0.0), (0.5, -4.0), 0.0);
+        let at = TextureLookup { uv_width: 0.25, ..TextureLookup::point(1.0, 1.0, &Vec3::zero()) };
+        assert_eq!(texture.lookup(&at), Vec3::new(0.5, -4.0, 1.0));
+    }
+}
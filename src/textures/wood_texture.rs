@@ -0,0 +1,45 @@
+use straal::{FloatType, Vec3};
+
+use crate::math::lerp;
+use crate::textures::{Perlin, Texture};
+
+/// Growth rings around the y axis, pushed in and out by noise so they aren't perfect circles
+pub struct WoodTexture<T> {
+    pub noise: Perlin,
+    /// Rings per unit of distance
+    pub scale: T,
+    pub octaves: u32,
+    /// How far the noise moves the rings, in rings
+    pub distortion: T,
+    /// The early wood and the darker late wood at the end of each ring
+    pub colors: [Vec3<T>; 2],
+}
+
+impl<T> WoodTexture<T>
+    where
+        T: FloatType<T>,
+{
+    pub fn new(seed: u64, scale: T, octaves: u32, distortion: T, colors: [Vec3<T>; 2]) -> WoodTexture<T> {
+        WoodTexture {
+            noise: Perlin::new(seed),
+            scale,
+            octaves,
+            distortion,
+            colors,
+        }
+    }
+}
+
+impl<T> Texture<T> for WoodTexture<T>
+    where
+        T: FloatType<T> + Send + Sync,
+{
+    fn sample_color(&self, _u: T, _v: T, p: &Vec3<T>) -> Vec3<T> {
+        let p = *p * self.scale;
+        let radius = (p.x * p.x + p.z * p.z).sqrt();
+        let ring = (radius + self.distortion * self.noise.fbm(&p, self.octaves)).fract();
+        //Negative distortion can pull the radius below zero, where fract is negative as well
+        let ring = if ring < T::zero() { ring + T::one() } else { ring };
+        lerp(&self.colors[0], &self.colors[1], ring * ring)
+    }
+}
@@ -0,0 +1,201 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+/// Rectangle of pixels, `x` and `y` of its top left pixel counted from the top left of the image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn area(&self) -> usize {
+        self.width * self.height
+    }
+}
+
+/// Order in which tiles are handed out to the render threads
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TileOrder {
+    /// Left to right, top to bottom
+    Scanline,
+    /// Outwards from the center, where the subject usually is
+    #[default]
+    Spiral,
+    /// Along a Hilbert curve, consecutive tiles stay close together which is kind to the caches
+    Hilbert,
+}
+
+impl TileOrder {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TileOrder::Scanline => "scanline",
+            TileOrder::Spiral => "spiral",
+            TileOrder::Hilbert => "hilbert",
+        }
+    }
+}
+
+impl fmt::Display for TileOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for TileOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "scanline" => Ok(TileOrder::Scanline),
+            "spiral" => Ok(TileOrder::Spiral),
+            "hilbert" => Ok(TileOrder::Hilbert),
+            _ => Err(format!("unknown tile order '{}', expected scanline, spiral or hilbert", s)),
+        }
+    }
+}
+
+/// Position of (`x`, `y`) along the Hilbert curve through an `n` by `n` grid, `n` a power of two
+fn hilbert_index(n: usize, mut x: usize, mut y: usize) -> usize {
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = (x & s) > 0;
+        let ry = (y & s) > 0;
+        d += s * s * ((3 * rx as usize) ^ ry as usize);
+        //Rotates the quadrant so the curve continues where the previous one ended
+        if !ry {
+            if rx {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+/// Grid positions of a `columns` by `rows` grid in a square spiral starting at the center
+fn spiral_order(columns: usize, rows: usize) -> Vec<(usize, usize)> {
+    let total = columns * rows;
+    let mut order = Vec::with_capacity(total);
+    let (mut x, mut y) = ((columns as isize - 1) / 2, (rows as isize - 1) / 2);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut direction = 0;
+    let mut run = 1;
+    while order.len() < total {
+        //Every run length is walked twice before it grows: right 1, down 1, left 2, up 2, ...
+        for _ in 0..2 {
+            let (dx, dy) = directions[direction];
+            for _ in 0..run {
+                if x >= 0 && y >= 0 && (x as usize) < columns && (y as usize) < rows {
+                    order.push((x as usize, y as usize));
+                }
+                x += dx;
+                y += dy;
+            }
+            direction = (direction + 1) % 4;
+        }
+        run += 1;
+    }
+    order
+}
+
+/// Splits `area` into tiles of at most `tile_size` pixels square, in the given order. Tiles at
+/// the right and bottom edge are smaller when the size doesn't divide the area.
+pub fn generate_tiles(area: &Rect, tile_size: usize, order: TileOrder) -> Vec<Rect> {
+    let columns = area.width.div_ceil(tile_size);
+    let rows = area.height.div_ceil(tile_size);
+    let positions = match order {
+        TileOrder::Scanline => (0..rows).flat_map(|y| (0..columns).map(move |x| (x, y))).collect(),
+        TileOrder::Spiral => spiral_order(columns, rows),
+        TileOrder::Hilbert => {
+            let n = columns.max(rows).next_power_of_two();
+            let mut positions: Vec<(usize, usize)> =
+                (0..rows).flat_map(|y| (0..columns).map(move |x| (x, y))).collect();
+            positions.sort_by_key(|(x, y)| hilbert_index(n, *x, *y));
+            positions
+        }
+    };
+    positions
+        .into_iter()
+        .map(|(column, row)| {
+            let x = column * tile_size;
+            let y = row * tile_size;
+            Rect {
+                x: area.x + x,
+                y: area.y + y,
+                width: tile_size.min(area.width - x),
+                height: tile_size.min(area.height - y),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_cover_every_pixel_once() {
+        let orders = [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert];
+        let areas = [(0, 0, 64, 64), (3, 5, 70, 33), (0, 0, 1, 1), (2, 0, 31, 100), (0, 0, 100, 7)];
+        for order in orders.iter() {
+            for (x, y, width, height) in areas.iter().cloned() {
+                for tile_size in &[1, 7, 16, 32, 128] {
+                    let area = Rect { x, y, width, height };
+                    let mut covered = vec![0; area.area()];
+                    for tile in generate_tiles(&area, *tile_size, *order) {
+                        assert!(tile.width >= 1 && tile.width <= *tile_size && tile.height <= *tile_size);
+                        for ty in tile.y..tile.y + tile.height {
+                            for tx in tile.x..tile.x + tile.width {
+                                covered[(ty - y) * width + tx - x] += 1;
+                            }
+                        }
+                    }
+                    assert!(covered.iter().all(|c| *c == 1), "{} {:?} size {}", order, area, tile_size);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn spiral_starts_in_the_center() {
+        let area = Rect {
+            x: 0,
+            y: 0,
+            width: 100,
+            height: 60,
+        };
+        let first = generate_tiles(&area, 20, TileOrder::Spiral)[0];
+        assert_eq!((first.x, first.y), (40, 20));
+        let scanline = generate_tiles(&area, 20, TileOrder::Scanline);
+        assert_eq!((scanline[1].x, scanline[1].y), (20, 0));
+    }
+
+    #[test]
+    fn hilbert_index_is_a_bijection() {
+        for n in &[1, 2, 4, 8, 32] {
+            let mut indices: Vec<usize> = (0..n * n).map(|i| hilbert_index(*n, i % n, i / n)).collect();
+            indices.sort_unstable();
+            assert!(indices.into_iter().eq(0..n * n), "n = {}", n);
+        }
+    }
+
+    #[test]
+    fn hilbert_neighbours_are_adjacent() {
+        let n = 16;
+        let mut positions: Vec<(usize, usize)> = (0..n * n).map(|i| (i % n, i / n)).collect();
+        positions.sort_by_key(|(x, y)| hilbert_index(n, *x, *y));
+        for pair in positions.windows(2) {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            assert_eq!(x0.max(x1) - x0.min(x1) + y0.max(y1) - y0.min(y1), 1, "{:?}", pair);
+        }
+    }
+}